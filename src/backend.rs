@@ -0,0 +1,84 @@
+use std::fmt::Display;
+
+use crate::{
+    chaum_pedersen::{ChaumPedersenParams, NamedGroup, ParamsError},
+    group::{Group, Ristretto255, P256},
+};
+
+static ENV_BACKEND: &str = "CP_BACKEND";
+
+/// Selects the group the Chaum-Pedersen protocol is run over. Client and server must use the same backend.
+/// Group elements are always exchanged in their byte encodings, so every backend fits the same gRPC messages.
+#[derive(Debug, Clone)]
+pub enum Backend {
+    /// The order-q subgroup of Z_p*, see [`crate::group::Group`] for ChaumPedersenParams
+    ModP(ChaumPedersenParams),
+    /// The Ristretto255 group, see [`crate::group::Ristretto255`]
+    Ristretto255,
+    /// The NIST P-256 curve, see [`crate::group::P256`]
+    P256,
+}
+
+impl From<ChaumPedersenParams> for Backend {
+    fn from(params: ChaumPedersenParams) -> Self {
+        Backend::ModP(params)
+    }
+}
+
+impl Display for Backend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Backend::ModP(params) => write!(f, "modp ({} bits)", params.p.bits()),
+            Backend::Ristretto255 => write!(f, "ristretto255"),
+            Backend::P256 => write!(f, "p256"),
+        }
+    }
+}
+
+impl Backend {
+    /// Selects the backend from CP_BACKEND : `modp` (the default) loads ChaumPedersenParams from the environment,
+    /// while `ristretto255` or `p256` select an elliptic curve
+    pub fn new_from_env() -> Self {
+        let backend = std::env::var(ENV_BACKEND)
+            .ok()
+            .filter(|b| !b.is_empty())
+            .unwrap_or("modp".to_string());
+
+        match backend.trim().to_ascii_lowercase().as_str() {
+            "modp" => Backend::ModP(ChaumPedersenParams::new_from_env()),
+            "ristretto255" => Backend::Ristretto255,
+            "p256" | "p-256" => Backend::P256,
+            _ => panic!(
+                "Failed to parse 'ENV_BACKEND' as a backend. supported backends are modp, ristretto255, p256"
+            ),
+        }
+    }
+
+    /// Fingerprint of the group, see [`Group::fingerprint`]
+    pub fn fingerprint(&self) -> [u8; 32] {
+        match self {
+            Backend::ModP(params) => params.fingerprint(),
+            Backend::Ristretto255 => Ristretto255.fingerprint(),
+            Backend::P256 => P256.fingerprint(),
+        }
+    }
+
+    /// Name of the configured group : the curve's, the named group's for its modp parameters, or "custom"
+    pub fn group_name(&self) -> String {
+        match self {
+            Backend::ModP(params) => NamedGroup::of(params)
+                .map_or("custom", |g| g.name())
+                .to_string(),
+            Backend::Ristretto255 => Ristretto255.name(),
+            Backend::P256 => P256.name(),
+        }
+    }
+
+    /// Validates the group parameters. Curve parameters are fixed, so are always valid
+    pub fn validate(&self) -> std::result::Result<(), ParamsError> {
+        match self {
+            Backend::ModP(params) => params.validate(),
+            Backend::Ristretto255 | Backend::P256 => Ok(()),
+        }
+    }
+}
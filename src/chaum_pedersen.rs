@@ -1,14 +1,67 @@
 use anyhow::{anyhow, Result};
-use crypto_primes::generate_prime;
-use num::{bigint::ToBigInt, BigInt, Integer, Num, One};
+use crypto_bigint::Uint;
+use crypto_primes::{
+    hazmat::{random_odd_uint, Sieve},
+    is_safe_prime_with_rng,
+};
+use num::{
+    bigint::{Sign, ToBigInt},
+    BigInt, Integer, Num, One, Signed, Zero,
+};
+use num_bigint::RandBigInt;
+use rand_core::{OsRng, RngCore};
+use sha2::{Digest, Sha256};
 use std::{fmt::Display, io::Write, ops::Sub, str::FromStr};
+use thiserror::Error;
 
-static MAX_GENERATION_ATTEMPTS: u8 = 50;
+pub(crate) mod ct;
+mod groups;
 
+pub use ct::FixedUint;
+pub use groups::NamedGroup;
+
+/// Modulus sizes, in bits, accepted by [`generate_params`]
+pub const SUPPORTED_PARAM_BITS: [usize; 3] = [2048, 3072, 4096];
+
+static ENV_PARAMS_GROUP: &str = "CP_GROUP";
 static ENV_PARAMS_P: &str = "CP_P";
 static ENV_PARAMS_Q: &str = "CP_Q";
 static ENV_PARAMS_G: &str = "CP_G";
 static ENV_PARAMS_H: &str = "CP_H";
+static ENV_PARAMS_H_SEED: &str = "CP_H_SEED";
+
+/// Size in bytes of the random seed used to derive H for freshly generated parameters
+const GENERATOR_SEED_LEN: usize = 32;
+
+/// Reasons a set of ChaumPedersenParams can be rejected by [`ChaumPedersenParams::validate`]
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ParamsError {
+    #[error("P is too large to validate ({0} bits, max {MAX_PARAM_BITS})")]
+    PTooLarge(u64),
+    #[error("P is not prime")]
+    PNotPrime,
+    #[error("Q is not prime")]
+    QNotPrime,
+    #[error("Q does not divide P-1")]
+    QDoesNotDividePMinusOne,
+    #[error("G is 1, which generates the trivial subgroup")]
+    GIsIdentity,
+    #[error("H is 1, which generates the trivial subgroup")]
+    HIsIdentity,
+    #[error("G is not an element of the order-Q subgroup of Z_P*")]
+    GNotInSubgroup,
+    #[error("H is not an element of the order-Q subgroup of Z_P*")]
+    HNotInSubgroup,
+    #[error("G and H must be distinct generators")]
+    GeneratorsEqual,
+    #[error("no seed is available to re-derive H")]
+    MissingGeneratorSeed,
+    #[error("H does not match the generator derived from its seed")]
+    GeneratorDerivationMismatch,
+}
+
+/// Largest modulus, in bits, that [`is_prime`] is able to test
+pub const MAX_PARAM_BITS: u64 = 8192;
 
 #[derive(Debug, Clone)]
 pub struct ChaumPedersenParams {
@@ -16,22 +69,56 @@ pub struct ChaumPedersenParams {
     pub q: BigInt,
     pub g: BigInt,
     pub h: BigInt,
+    /// Public seed H was derived from, if any. See [`derive_generator`]
+    pub h_seed: Option<Vec<u8>>,
 }
 
 impl Display for ChaumPedersenParams {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "P={}\nQ={}\nG={}\nH={}", self.p, self.q, self.g, self.h)
+        write!(f, "P={}\nQ={}\nG={}\nH={}", self.p, self.q, self.g, self.h)?;
+        if let Some(seed) = &self.h_seed {
+            write!(f, "\nH_SEED={}", hex::encode(seed))?;
+        }
+        Ok(())
     }
 }
 
 impl ChaumPedersenParams {
     /// Construct a new ChaumPedersenParams from provided parameters
     pub fn new(p: BigInt, q: BigInt, g: BigInt, h: BigInt) -> Self {
-        ChaumPedersenParams { p, q, g, h }
+        ChaumPedersenParams {
+            p,
+            q,
+            g,
+            h,
+            h_seed: None,
+        }
+    }
+
+    /// Construct a new ChaumPedersenParams where H is derived from a public seed, G and P, so it can be audited
+    pub fn new_with_derived_h(p: BigInt, q: BigInt, g: BigInt, seed: Vec<u8>) -> Self {
+        let h = derive_generator(&p, &q, &g, &seed);
+        ChaumPedersenParams {
+            p,
+            q,
+            g,
+            h,
+            h_seed: Some(seed),
+        }
     }
 
     /// Attempt to construct a new ChaumPedersenParams from environment variables
+    /// If CP_GROUP is set, the named group is used, otherwise P, Q, G and H are read individually
     pub fn new_from_env() -> Self {
+        if let Some(group) = std::env::var(ENV_PARAMS_GROUP)
+            .ok()
+            .filter(|g| !g.is_empty())
+        {
+            return NamedGroup::from_str(&group)
+                .expect("Failed to parse 'ENV_PARAMS_GROUP' as a named group")
+                .params();
+        }
+
         let p = BigInt::from_str(
             &std::env::var(ENV_PARAMS_P)
                 .expect("Environment variable for 'ENV_PARAMS_P' not found"),
@@ -56,7 +143,54 @@ impl ChaumPedersenParams {
         )
         .expect("Failed to parse 'ENV_PARAMS_H' as BigInt");
 
-        ChaumPedersenParams { p, q, g, h }
+        let h_seed = std::env::var(ENV_PARAMS_H_SEED)
+            .ok()
+            .filter(|s| !s.is_empty())
+            .map(|s| hex::decode(s).expect("Failed to parse 'ENV_PARAMS_H_SEED' as hex"));
+
+        ChaumPedersenParams { p, q, g, h, h_seed }
+    }
+
+    /// Checks that the parameters describe a sound Chaum-Pedersen group:
+    /// P and Q are prime, Q divides P-1, and G and H are distinct, non-identity elements of the order-Q subgroup
+    pub fn validate(&self) -> std::result::Result<(), ParamsError> {
+        if self.p.bits() > MAX_PARAM_BITS {
+            return Err(ParamsError::PTooLarge(self.p.bits()));
+        }
+        if !is_prime(&self.p) {
+            return Err(ParamsError::PNotPrime);
+        }
+        if !is_prime(&self.q) {
+            return Err(ParamsError::QNotPrime);
+        }
+        if !(&self.p - BigInt::one()).is_multiple_of(&self.q) {
+            return Err(ParamsError::QDoesNotDividePMinusOne);
+        }
+        if self.g.is_one() {
+            return Err(ParamsError::GIsIdentity);
+        }
+        if self.h.is_one() {
+            return Err(ParamsError::HIsIdentity);
+        }
+        if !self.in_subgroup(&self.g) {
+            return Err(ParamsError::GNotInSubgroup);
+        }
+        if !self.in_subgroup(&self.h) {
+            return Err(ParamsError::HNotInSubgroup);
+        }
+        if self.g == self.h {
+            return Err(ParamsError::GeneratorsEqual);
+        }
+        if self.h_seed.is_some() {
+            verify_generator_derivation(self)?;
+        }
+
+        Ok(())
+    }
+
+    /// Checks that v lies in [1, P) and is an element of the order-Q subgroup : v^q mod p == 1
+    pub(crate) fn in_subgroup(&self, v: &BigInt) -> bool {
+        v.sign() == Sign::Plus && v < &self.p && v.modpow(&self.q, &self.p).is_one()
     }
 
     /// Writes the parameters to the provided filename for use as a .env file
@@ -64,79 +198,162 @@ impl ChaumPedersenParams {
         &self,
         out: &mut std::fs::File,
     ) -> std::result::Result<usize, std::io::Error> {
-        out.write(
-            format!(
-                "{}={}\n{}={}\n{}={}\n{}={}",
-                ENV_PARAMS_P,
-                self.p,
-                ENV_PARAMS_Q,
-                self.q,
-                ENV_PARAMS_G,
-                self.g,
-                ENV_PARAMS_H,
-                self.h,
-            )
-            .as_bytes(),
-        )
+        let mut env = format!(
+            "{}={}\n{}={}\n{}={}\n{}={}",
+            ENV_PARAMS_P, self.p, ENV_PARAMS_Q, self.q, ENV_PARAMS_G, self.g, ENV_PARAMS_H, self.h,
+        );
+        if let Some(seed) = &self.h_seed {
+            env.push_str(&format!("\n{}={}", ENV_PARAMS_H_SEED, hex::encode(seed)));
+        }
+
+        out.write(env.as_bytes())
     }
 
-    /// Compute y1 and y2 : y1 = g^x mod p, y2 = h^x mod p
-    pub fn y1_y2(&self, x: &BigInt) -> (BigInt, BigInt) {
-        let y1 = self.g.modpow(x, &self.p);
-        let y2 = self.h.modpow(x, &self.p);
+    /// Size in bytes of an encoded group element : |p| rounded up to a whole byte
+    pub fn element_len(&self) -> usize {
+        (self.p.bits() as usize).div_ceil(8)
+    }
+
+    /// Size in bytes of an encoded scalar : |q| rounded up to a whole byte
+    pub fn scalar_len(&self) -> usize {
+        (self.q.bits() as usize).div_ceil(8)
+    }
+
+    /// Compute y1 and y2 : y1 = g^x mod p, y2 = h^x mod p, in constant time with respect to x
+    pub fn y1_y2(&self, x: &FixedUint) -> (BigInt, BigInt) {
+        let y1 = ct::modpow(&self.g, x, &self.p);
+        let y2 = ct::modpow(&self.h, x, &self.p);
 
         (y1, y2)
     }
 
-    /// Compute r1 and r2 : r1 = g^k mod p, r2 = h^k mod p
-    pub fn r1_r2(&self, k: &BigInt) -> (BigInt, BigInt) {
-        let r1 = self.g.modpow(k, &self.p);
-        let r2 = self.h.modpow(k, &self.p);
+    /// Compute r1 and r2 : r1 = g^k mod p, r2 = h^k mod p, in constant time with respect to k
+    pub fn r1_r2(&self, k: &FixedUint) -> (BigInt, BigInt) {
+        let r1 = ct::modpow(&self.g, k, &self.p);
+        let r2 = ct::modpow(&self.h, k, &self.p);
 
         (r1, r2)
     }
 
-    /// Compute s : s = k - (c * x) mod q
-    pub fn s(&self, k: &BigInt, c: &BigInt, x: &BigInt) -> BigInt {
-        let c_mul_x = c * x;
-        if k > &c_mul_x {
-            (k - &c_mul_x) % &self.q
-        } else {
-            &self.q - (c_mul_x - k) % &self.q
-        }
+    /// Compute s : s = k - (c * x) mod q, in constant time with respect to k and x
+    pub fn s(&self, k: &FixedUint, c: &FixedUint, x: &FixedUint) -> FixedUint {
+        let c_mul_x = ct::mul_mod(c, x, &self.q);
+        ct::sub_mod(k, &c_mul_x, &self.q)
     }
 }
 
-/// Generates a new set of ChaumPedersenParams
-/// Note: if it fails to generate a valid prime after MAX_GENERATION_ATTEMPTS, it will return an error.
-/// As it is a random process, this may occur if generated primes do not the satisfy cyclic group constraint. Usually, in this case running the function again will succeed.
-pub fn generate_params() -> Result<ChaumPedersenParams> {
-    let mut i = 0;
-    loop {
-        i += 1;
-        if i > MAX_GENERATION_ATTEMPTS {
-            Err(anyhow!("could not generate a valid prime after {} attempts. please try running the generator again", MAX_GENERATION_ATTEMPTS))?;
-        }
-        // Create prime - 16 x 64 bit = 1024 bit in size
-        let p: crypto_bigint::Uint<16> = generate_prime(None);
-        let p_hex_str = p.to_string();
+/// Encodes a non-negative integer as exactly `len` unsigned big-endian bytes, left-padded with zeros.
+/// This is the wire encoding of modp elements and scalars, see [`ChaumPedersenParams::element_len`].
+/// Panics if n is negative or doesn't fit in `len` bytes
+pub fn encode_fixed(n: &BigInt, len: usize) -> Vec<u8> {
+    assert!(
+        !n.is_negative(),
+        "only non-negative integers can be encoded"
+    );
+    let bytes = n.to_bytes_be().1;
+    let bytes = if n.is_zero() { &[][..] } else { &bytes[..] };
+    assert!(bytes.len() <= len, "integer does not fit in {} bytes", len);
+
+    let mut out = vec![0u8; len];
+    out[len - bytes.len()..].copy_from_slice(bytes);
+    out
+}
 
-        let p = BigInt::from_str_radix(&p_hex_str, 16).unwrap();
+/// Decodes an integer from exactly `len` unsigned big-endian bytes. Any other length is rejected,
+/// so every integer has a single encoding
+pub fn decode_fixed(bytes: &[u8], len: usize) -> Result<BigInt> {
+    if bytes.len() != len {
+        return Err(anyhow!(
+            "expected a {} byte encoding, got {} bytes",
+            len,
+            bytes.len()
+        ));
+    }
+    Ok(BigInt::from_bytes_be(Sign::Plus, bytes))
+}
 
-        let test = check_generators_cyclic_group_of_prime_order(&p);
-        log::debug!("is group prime order? {}", test);
-        if !test {
-            continue;
-        }
+/// Draws an integer uniformly from [0, bound) using the OS CSPRNG.
+/// Candidates of bound's bit length are rejected until one falls below bound, so there is no modulo bias.
+pub fn random_below(bound: &BigInt) -> BigInt {
+    assert!(bound.is_positive(), "bound must be positive");
+    OsRng.gen_bigint_range(&BigInt::zero(), bound)
+}
+
+/// Draws a secret nonce k uniformly from [0, q).
+/// k must never be reused or drawn from a smaller range, as the responses s = k - c * x would then leak x.
+pub fn random_nonce(q: &BigInt) -> FixedUint {
+    FixedUint::random(q)
+}
+
+/// Checks that a challenge of `bits` bits can be drawn for a group of order q : 1 <= bits <= |q|
+pub fn check_challenge_bits(q: &BigInt, bits: u64) -> Result<()> {
+    if bits == 0 || bits > q.bits() {
+        return Err(anyhow!(
+            "challenge size must be between 1 and {} bits, got {}",
+            q.bits(),
+            bits
+        ));
+    }
+    Ok(())
+}
+
+/// Draws a challenge c uniformly from [0, min(2^bits, q)).
+/// A cheating prover succeeds with probability 1 / min(2^bits, q), so `bits` sets the soundness of a single round.
+pub fn random_challenge(q: &BigInt, bits: u64) -> Result<BigInt> {
+    check_challenge_bits(q, bits)?;
+    let bound = (BigInt::one() << bits).min(q.clone());
+    Ok(random_below(&bound))
+}
+
+/// Generates a new set of ChaumPedersenParams over a safe prime group : p = 2q + 1, where both p and q are prime.
+/// `bits` must be one of SUPPORTED_PARAM_BITS. Generation can take several minutes for larger sizes;
+/// `progress` is called with the number of candidates tested so far, each time a candidate is rejected.
+pub fn generate_params(bits: usize, progress: impl FnMut(u64)) -> Result<ChaumPedersenParams> {
+    let p = match bits {
+        2048 => generate_safe_prime::<32>(bits, progress),
+        3072 => generate_safe_prime::<48>(bits, progress),
+        4096 => generate_safe_prime::<64>(bits, progress),
+        _ => Err(anyhow!(
+            "unsupported parameter size {} bits. supported sizes are {:?}",
+            bits,
+            SUPPORTED_PARAM_BITS
+        ))?,
+    };
+
+    let params = safe_prime_params(p);
+    params.validate()?;
+
+    Ok(params)
+}
+
+/// Builds ChaumPedersenParams from a safe prime p.
+/// The order-q subgroup is exactly the quadratic residues mod p, so squaring 2 always yields a generator of it.
+/// H is derived from a fresh random seed, which is kept alongside the params so the derivation can be audited.
+fn safe_prime_params(p: BigInt) -> ChaumPedersenParams {
+    let q = (&p)
+        .sub(&BigInt::one())
+        .div_floor(&2u128.to_bigint().unwrap());
 
-        let q = (&p)
-            .sub(&BigInt::one())
-            .div_floor(&2u128.to_bigint().unwrap());
+    let g = 4u128.to_bigint().unwrap();
 
-        let g = 2u128.to_bigint().unwrap();
-        let h = 3u128.to_bigint().unwrap();
+    let mut seed = vec![0u8; GENERATOR_SEED_LEN];
+    OsRng.fill_bytes(&mut seed);
+
+    ChaumPedersenParams::new_with_derived_h(p, q, g, seed)
+}
 
-        return Ok(ChaumPedersenParams::new(p, q, g, h));
+/// Searches for a random safe prime of exactly `bits` bits, sieving candidates with `crypto_primes`
+fn generate_safe_prime<const L: usize>(bits: usize, mut progress: impl FnMut(u64)) -> BigInt {
+    let mut tested = 0;
+    loop {
+        let start: Uint<L> = random_odd_uint(&mut OsRng, bits);
+        for candidate in Sieve::new(&start, bits, true) {
+            if is_safe_prime_with_rng(&mut OsRng, &candidate) {
+                return BigInt::from_str_radix(&candidate.to_string(), 16).unwrap();
+            }
+            tested += 1;
+            progress(tested);
+        }
     }
 }
 
@@ -152,6 +369,95 @@ pub fn check_generators_cyclic_group_of_prime_order(p: &BigInt) -> bool {
     g.modpow(&q, p) == BigInt::one() && h.modpow(&q, p) == BigInt::one()
 }
 
+/// Domain separation tag used when hashing into the order-q subgroup
+const GENERATOR_DERIVATION_DOMAIN: &[u8] = b"zkp-auth/chaum-pedersen/generator/v1";
+
+/// Re-derives H from the params' seed, G and P, and checks it matches H.
+/// This lets anyone confirm H was chosen "nothing up my sleeve", rather than as a known power of G.
+pub fn verify_generator_derivation(
+    params: &ChaumPedersenParams,
+) -> std::result::Result<(), ParamsError> {
+    let seed = params
+        .h_seed
+        .as_ref()
+        .ok_or(ParamsError::MissingGeneratorSeed)?;
+
+    if derive_generator(&params.p, &params.q, &params.g, seed) != params.h {
+        return Err(ParamsError::GeneratorDerivationMismatch);
+    }
+
+    Ok(())
+}
+
+/// Deterministically derives a generator of the order-q subgroup from a public seed, G and P.
+/// The seed is expanded with SHA-256 to |p| + 128 bits, reduced mod p, and raised to the cofactor (p-1)/q.
+/// A counter is appended and the process repeated in the (negligibly likely) event the result is 1 or G.
+pub fn derive_generator(p: &BigInt, q: &BigInt, g: &BigInt, seed: &[u8]) -> BigInt {
+    let cofactor = (p - BigInt::one()) / q;
+    let len = (p.bits() as usize + 128).div_ceil(8);
+
+    let mut prefix = Sha256::new();
+    for part in [
+        GENERATOR_DERIVATION_DOMAIN,
+        seed,
+        &g.to_bytes_be().1,
+        &p.to_bytes_be().1,
+    ] {
+        prefix.update((part.len() as u64).to_be_bytes());
+        prefix.update(part);
+    }
+
+    for counter in 0u32.. {
+        let mut expanded = Vec::with_capacity(len + 32);
+        for block in 0u32.. {
+            if expanded.len() >= len {
+                break;
+            }
+            let mut hasher = prefix.clone();
+            hasher.update(counter.to_be_bytes());
+            hasher.update(block.to_be_bytes());
+            expanded.extend_from_slice(&hasher.finalize());
+        }
+
+        let h = BigInt::from_bytes_be(Sign::Plus, &expanded[..len])
+            .mod_floor(p)
+            .modpow(&cofactor, p);
+        if h > BigInt::one() && &h != g {
+            return h;
+        }
+    }
+
+    unreachable!("exhausted generator derivation counter")
+}
+
+/// Checks primality of an arbitrary-size integer (up to MAX_PARAM_BITS) using the Baillie-PSW test from `crypto_primes`
+pub fn is_prime(n: &BigInt) -> bool {
+    if n.sign() != Sign::Plus {
+        return false;
+    }
+
+    let bytes = n.to_bytes_be().1;
+    match bytes.len().div_ceil(8) {
+        1 => is_prime_uint::<1>(&bytes),
+        2 => is_prime_uint::<2>(&bytes),
+        3..=4 => is_prime_uint::<4>(&bytes),
+        5..=8 => is_prime_uint::<8>(&bytes),
+        9..=16 => is_prime_uint::<16>(&bytes),
+        17..=32 => is_prime_uint::<32>(&bytes),
+        33..=48 => is_prime_uint::<48>(&bytes),
+        49..=64 => is_prime_uint::<64>(&bytes),
+        65..=128 => is_prime_uint::<128>(&bytes),
+        _ => false,
+    }
+}
+
+fn is_prime_uint<const L: usize>(bytes: &[u8]) -> bool {
+    let mut padded = vec![0u8; L * 8];
+    padded[L * 8 - bytes.len()..].copy_from_slice(bytes);
+
+    crypto_primes::is_prime(&Uint::<L>::from_be_slice(&padded))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -171,7 +477,7 @@ mod tests {
     #[test]
     fn test_y1_y2() {
         let params = create_test_params();
-        let x = BigInt::from_u64(3).unwrap();
+        let x = FixedUint::from_bigint(&BigInt::from_u64(3).unwrap(), &params.q);
         let (y1, y2) = params.y1_y2(&x);
 
         assert_eq!(y1, BigInt::from_u64(8).unwrap()); // 2 ^ 3 = 8
@@ -181,7 +487,7 @@ mod tests {
     #[test]
     fn test_r1_r2() {
         let params = create_test_params();
-        let k = BigInt::from_u64(4).unwrap();
+        let k = FixedUint::from_bigint(&BigInt::from_u64(4).unwrap(), &params.q);
         let (r1, r2) = params.r1_r2(&k);
 
         assert_eq!(r1, BigInt::from_u64(16).unwrap()); // 2 ^ 4
@@ -191,12 +497,10 @@ mod tests {
     #[test]
     fn test_s() {
         let params = create_test_params();
-        let k = BigInt::from_u64(4).unwrap();
-        let c = BigInt::from_u64(2).unwrap();
-        let x = BigInt::from_u64(3).unwrap();
-        let s = params.s(&k, &c, &x);
+        let fixed = |n: u64| FixedUint::from_bigint(&BigInt::from_u64(n).unwrap(), &params.q);
+        let s = params.s(&fixed(4), &fixed(2), &fixed(3));
 
-        assert_eq!(s, BigInt::from_u64(5002).unwrap()); // 4 - (2 * 3) % 5004 = 5002
+        assert_eq!(s.to_bigint(), BigInt::from_u64(5002).unwrap()); // 4 - (2 * 3) % 5004 = 5002
     }
 
     #[test]
@@ -212,4 +516,197 @@ mod tests {
         let prime = BigInt::from(31);
         assert!(!check_generators_cyclic_group_of_prime_order(&prime));
     }
+
+    fn create_valid_params() -> ChaumPedersenParams {
+        // 10007 is a safe prime : (10007-1)/2 = 5003 is also prime, and 2,3 are both quadratic residues
+        ChaumPedersenParams::new(
+            BigInt::from(10007),
+            BigInt::from(5003),
+            BigInt::from(2),
+            BigInt::from(3),
+        )
+    }
+
+    #[test]
+    fn test_is_prime() {
+        assert!(is_prime(&BigInt::from(2)));
+        assert!(is_prime(&BigInt::from(10007)));
+        assert!(!is_prime(&BigInt::from(1)));
+        assert!(!is_prime(&BigInt::from(5004)));
+        assert!(!is_prime(&BigInt::from(-7)));
+    }
+
+    #[test]
+    fn test_validate_valid_params() {
+        assert_eq!(create_valid_params().validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_validate_rejects_bad_params() {
+        let mut params = create_valid_params();
+        params.p = BigInt::from(10009 * 3);
+        assert_eq!(params.validate(), Err(ParamsError::PNotPrime));
+
+        // the original test params : 5004 is not prime
+        assert_eq!(create_test_params().validate(), Err(ParamsError::QNotPrime));
+
+        let mut params = create_valid_params();
+        params.q = BigInt::from(5011);
+        assert_eq!(params.validate(), Err(ParamsError::QDoesNotDividePMinusOne));
+
+        let mut params = create_valid_params();
+        params.g = BigInt::one();
+        assert_eq!(params.validate(), Err(ParamsError::GIsIdentity));
+
+        let mut params = create_valid_params();
+        params.h = BigInt::one();
+        assert_eq!(params.validate(), Err(ParamsError::HIsIdentity));
+
+        // 5 is a quadratic non-residue mod 10007, so it generates the full group of order 2q
+        let mut params = create_valid_params();
+        params.g = BigInt::from(5);
+        assert_eq!(params.validate(), Err(ParamsError::GNotInSubgroup));
+
+        let mut params = create_valid_params();
+        params.h = BigInt::from(10007 + 3);
+        assert_eq!(params.validate(), Err(ParamsError::HNotInSubgroup));
+
+        let mut params = create_valid_params();
+        params.h = params.g.clone();
+        assert_eq!(params.validate(), Err(ParamsError::GeneratorsEqual));
+    }
+
+    #[test]
+    fn test_generate_safe_prime_params() {
+        let p = generate_safe_prime::<2>(128, |_| {});
+        let params = safe_prime_params(p);
+
+        assert_eq!(params.p.bits(), 128);
+        assert_eq!(params.validate(), Ok(()));
+        assert_eq!(&params.q * 2 + 1, params.p);
+        assert_eq!(verify_generator_derivation(&params), Ok(()));
+    }
+
+    #[test]
+    fn test_generate_params_rejects_unsupported_bits() {
+        assert!(generate_params(1024, |_| {}).is_err());
+    }
+
+    #[test]
+    fn test_verify_generator_derivation() {
+        let valid = create_valid_params();
+        let params =
+            ChaumPedersenParams::new_with_derived_h(valid.p, valid.q, valid.g, b"seed".to_vec());
+        assert_eq!(params.validate(), Ok(()));
+        assert_eq!(verify_generator_derivation(&params), Ok(()));
+
+        // the derivation is deterministic
+        assert_eq!(
+            derive_generator(&params.p, &params.q, &params.g, b"seed"),
+            params.h
+        );
+
+        let mut tampered = params.clone();
+        tampered.h = BigInt::from(3);
+        assert_eq!(
+            verify_generator_derivation(&tampered),
+            Err(ParamsError::GeneratorDerivationMismatch)
+        );
+        assert_eq!(
+            tampered.validate(),
+            Err(ParamsError::GeneratorDerivationMismatch)
+        );
+
+        assert_eq!(
+            verify_generator_derivation(&create_valid_params()),
+            Err(ParamsError::MissingGeneratorSeed)
+        );
+    }
+
+    /// Pearson's chi-square statistic of `counts` against the `expected` count of each bucket
+    fn chi_square(counts: &[u64], expected: &[f64]) -> f64 {
+        counts
+            .iter()
+            .zip(expected)
+            .map(|(&o, &e)| (o as f64 - e).powi(2) / e)
+            .sum()
+    }
+
+    #[test]
+    fn test_random_below_is_uniform() {
+        // 12 is not a power of two, so a biased reduction would show up in the upper buckets
+        let bound = 12.to_bigint().unwrap();
+        let samples = 60_000;
+
+        let mut counts = [0u64; 12];
+        for _ in 0..samples {
+            let v = random_below(&bound);
+            assert!(v >= BigInt::zero() && v < bound);
+            counts[usize::try_from(&v).unwrap()] += 1;
+        }
+
+        // 11 degrees of freedom, critical value at p = 0.001
+        let expected = [samples as f64 / 12.0; 12];
+        assert!(chi_square(&counts, &expected) < 31.26);
+    }
+
+    #[test]
+    fn test_random_nonce_covers_q() {
+        let q = NamedGroup::Ffdhe2048.params().q;
+
+        let samples: Vec<BigInt> = (0..64).map(|_| random_nonce(&q).to_bigint()).collect();
+        assert!(samples.iter().all(|k| k < &q && k >= &BigInt::zero()));
+
+        // A 64-bit nonce would never get near |q|; a uniform one almost always has one of its top bits set
+        let max_bits = samples.iter().map(|k| k.bits()).max().unwrap();
+        assert!(max_bits >= q.bits() - 4);
+
+        // The top byte of k should be spread across its range, rather than always zero
+        let top_bytes: Vec<u64> = samples
+            .iter()
+            .map(|k| (k >> (q.bits() - 8)).try_into().unwrap())
+            .collect();
+        assert!(top_bytes.iter().any(|&b| b >= 64));
+    }
+
+    #[test]
+    fn test_random_challenge_is_uniform() {
+        let q = 5003.to_bigint().unwrap();
+        let samples = 32_000;
+
+        let mut counts = [0u64; 16];
+        for _ in 0..samples {
+            let c = random_challenge(&q, 4).unwrap();
+            counts[usize::try_from(&c).unwrap()] += 1;
+        }
+
+        // 15 degrees of freedom, critical value at p = 0.001
+        let expected = [samples as f64 / 16.0; 16];
+        assert!(chi_square(&counts, &expected) < 37.70);
+    }
+
+    #[test]
+    fn test_random_challenge_full_size() {
+        // With bits = |q|, 2^bits > q, so c must be drawn from [0, q) rather than reduced
+        let q = 11.to_bigint().unwrap();
+        let samples = 44_000;
+
+        let mut counts = [0u64; 11];
+        for _ in 0..samples {
+            let c = random_challenge(&q, q.bits()).unwrap();
+            counts[usize::try_from(&c).unwrap()] += 1;
+        }
+
+        // 10 degrees of freedom, critical value at p = 0.001
+        let expected = [samples as f64 / 11.0; 11];
+        assert!(chi_square(&counts, &expected) < 29.59);
+    }
+
+    #[test]
+    fn test_random_challenge_rejects_bad_sizes() {
+        let q = 5003.to_bigint().unwrap();
+        assert!(random_challenge(&q, 0).is_err());
+        assert!(random_challenge(&q, q.bits() + 1).is_err());
+        assert!(random_challenge(&q, q.bits()).is_ok());
+    }
 }
@@ -0,0 +1,341 @@
+//! Constant-time modular arithmetic for the operations that touch secrets (x and k).
+//!
+//! Secrets are held in [`FixedUint`], a fixed number of limbs chosen from the modulus they are reduced by, from the
+//! moment they are derived until the public response s is encoded; they never go through a `BigInt`. Every operation
+//! picks its `crypto_bigint` width from the modulus and the public length of its inputs alone, so the running time
+//! depends only on public sizes and never on a secret's value. Exponentiation uses Montgomery form (`DynResidue`),
+//! which requires an odd modulus; P is always an odd prime. Reductions mod Q use `const_rem`, which accepts any modulus.
+
+use std::fmt::Debug;
+
+use crypto_bigint::{
+    modular::runtime_mod::{DynResidue, DynResidueParams},
+    Limb, NonZero, RandomMod, Uint, Word,
+};
+use num::{bigint::Sign, BigInt, Integer, Signed};
+use rand_core::OsRng;
+use subtle::ConstantTimeEq;
+
+/// Dispatches to `$f::<L>` with the smallest supported limb count able to hold `$limbs` limbs.
+/// `$limbs` must only depend on public sizes, never on a secret's value
+macro_rules! with_limbs {
+    ($limbs:expr, $f:ident($($arg:expr),*)) => {
+        match $limbs {
+            0..=1 => $f::<1>($($arg),*),
+            2 => $f::<2>($($arg),*),
+            3..=4 => $f::<4>($($arg),*),
+            5..=8 => $f::<8>($($arg),*),
+            9..=16 => $f::<16>($($arg),*),
+            17..=32 => $f::<32>($($arg),*),
+            33..=48 => $f::<48>($($arg),*),
+            49..=64 => $f::<64>($($arg),*),
+            65..=128 => $f::<128>($($arg),*),
+            n => panic!("{} bit operand is larger than the supported maximum", n * Limb::BITS),
+        }
+    };
+}
+
+/// A non-negative integer below some modulus, held in exactly as many limbs as the modulus needs.
+/// Comparisons are constant time, and Debug doesn't print the value
+#[derive(Clone)]
+pub struct FixedUint {
+    /// Little-endian limbs
+    limbs: Vec<Word>,
+}
+
+impl Debug for FixedUint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FixedUint").finish_non_exhaustive()
+    }
+}
+
+impl PartialEq for FixedUint {
+    fn eq(&self, other: &Self) -> bool {
+        self.limbs.len() == other.limbs.len() && bool::from(self.limbs.ct_eq(&other.limbs))
+    }
+}
+
+impl Eq for FixedUint {}
+
+impl FixedUint {
+    /// Reduces the unsigned big-endian `bytes` mod modulus, in constant time with respect to their value.
+    /// This is how secrets enter : KDF output, hashes and random bytes
+    pub fn from_be_bytes(bytes: &[u8], modulus: &BigInt) -> Self {
+        let limbs = bytes
+            .len()
+            .div_ceil(Limb::BYTES)
+            .max(modulus_limbs(modulus));
+        with_limbs!(limbs, rem_bytes(bytes, modulus))
+    }
+
+    /// Reduces n mod modulus. n goes through BigInt arithmetic, so this is only for public values such as
+    /// challenges and received responses
+    pub fn from_bigint(n: &BigInt, modulus: &BigInt) -> Self {
+        Self::from_be_bytes(&n.mod_floor(modulus).to_bytes_be().1, modulus)
+    }
+
+    /// Draws a value uniformly from [0, modulus)
+    pub fn random(modulus: &BigInt) -> Self {
+        with_limbs!(modulus_limbs(modulus), random_uint(modulus))
+    }
+
+    /// The value as exactly `len` unsigned big-endian bytes, which must be enough to hold the modulus
+    pub fn to_be_bytes(&self, len: usize) -> Vec<u8> {
+        let bytes: Vec<u8> = self
+            .limbs
+            .iter()
+            .rev()
+            .flat_map(|w| w.to_be_bytes())
+            .collect();
+
+        let mut out = vec![0u8; len.saturating_sub(bytes.len())];
+        out.extend_from_slice(&bytes[bytes.len().saturating_sub(len)..]);
+        out
+    }
+
+    /// The value as a BigInt, for values that are public once computed, such as the response s
+    pub fn to_bigint(&self) -> BigInt {
+        BigInt::from_bytes_be(
+            Sign::Plus,
+            &self.to_be_bytes(self.limbs.len() * Limb::BYTES),
+        )
+    }
+}
+
+/// Computes base^exp mod modulus, in constant time with respect to exp. base is public. Panics if modulus is even
+pub fn modpow(base: &BigInt, exp: &FixedUint, modulus: &BigInt) -> BigInt {
+    let base = FixedUint::from_bigint(base, modulus);
+    let limbs = modulus_limbs(modulus).max(exp.limbs.len());
+    with_limbs!(limbs, modpow_uint(&base, exp, modulus)).to_bigint()
+}
+
+/// Computes a * b mod modulus, in constant time with respect to a and b, which must be reduced mod modulus
+pub fn mul_mod(a: &FixedUint, b: &FixedUint, modulus: &BigInt) -> FixedUint {
+    with_limbs!(modulus_limbs(modulus), mul_mod_uint(a, b, modulus))
+}
+
+/// Computes a - b mod modulus, in constant time with respect to a and b, which must be reduced mod modulus
+pub fn sub_mod(a: &FixedUint, b: &FixedUint, modulus: &BigInt) -> FixedUint {
+    with_limbs!(modulus_limbs(modulus), sub_mod_uint(a, b, modulus))
+}
+
+fn modulus_limbs(modulus: &BigInt) -> usize {
+    assert!(modulus.is_positive(), "modulus must be positive");
+    (modulus.bits() as usize).div_ceil(Limb::BITS)
+}
+
+/// The public modulus as a Uint<L>
+fn modulus_uint<const L: usize>(modulus: &BigInt) -> Uint<L> {
+    let bytes = modulus.to_bytes_be().1;
+    let mut padded = vec![0u8; L * Limb::BYTES];
+    padded[L * Limb::BYTES - bytes.len()..].copy_from_slice(&bytes);
+
+    Uint::<L>::from_be_slice(&padded)
+}
+
+/// Zero-extends n to L limbs. Only the lengths, which are public, decide how much is copied
+fn to_uint<const L: usize>(n: &FixedUint) -> Uint<L> {
+    let mut limbs = [0 as Word; L];
+    limbs[..n.limbs.len()].copy_from_slice(&n.limbs);
+
+    Uint::from_words(limbs)
+}
+
+/// Truncates a value known to be below modulus to the modulus's limb count
+fn from_uint<const L: usize>(n: &Uint<L>, modulus: &BigInt) -> FixedUint {
+    FixedUint {
+        limbs: n.as_words()[..modulus_limbs(modulus)].to_vec(),
+    }
+}
+
+fn rem_bytes<const L: usize>(bytes: &[u8], modulus: &BigInt) -> FixedUint {
+    let mut padded = vec![0u8; L * Limb::BYTES];
+    padded[L * Limb::BYTES - bytes.len()..].copy_from_slice(bytes);
+    let (r, _) = Uint::<L>::from_be_slice(&padded).const_rem(&modulus_uint(modulus));
+    padded.fill(0);
+
+    from_uint(&r, modulus)
+}
+
+fn random_uint<const L: usize>(modulus: &BigInt) -> FixedUint {
+    let bound = NonZero::new(modulus_uint::<L>(modulus)).unwrap();
+
+    from_uint(&Uint::<L>::random_mod(&mut OsRng, &bound), modulus)
+}
+
+fn modpow_uint<const L: usize>(base: &FixedUint, exp: &FixedUint, modulus: &BigInt) -> FixedUint {
+    let params = DynResidueParams::new(&modulus_uint::<L>(modulus));
+    let base = DynResidue::new(&to_uint::<L>(base), params);
+
+    from_uint(&base.pow(&to_uint::<L>(exp)).retrieve(), modulus)
+}
+
+fn mul_mod_uint<const L: usize>(a: &FixedUint, b: &FixedUint, modulus: &BigInt) -> FixedUint {
+    let (lo, hi) = to_uint::<L>(a).mul_wide(&to_uint::<L>(b));
+    let (r, _) = Uint::const_rem_wide((lo, hi), &modulus_uint::<L>(modulus));
+
+    from_uint(&r, modulus)
+}
+
+fn sub_mod_uint<const L: usize>(a: &FixedUint, b: &FixedUint, modulus: &BigInt) -> FixedUint {
+    let r = to_uint::<L>(a).sub_mod(&to_uint::<L>(b), &modulus_uint::<L>(modulus));
+
+    from_uint(&r, modulus)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use num::One;
+
+    use super::*;
+    use crate::chaum_pedersen::{random_below, NamedGroup};
+
+    #[test]
+    fn test_matches_bigint_arithmetic() {
+        let params = NamedGroup::Rfc5114_2048_256.params();
+        let (p, q) = (&params.p, &params.q);
+        let fixed = |n: &BigInt| FixedUint::from_bigint(n, q);
+
+        for _ in 0..8 {
+            let (x, k, c) = (random_below(q), random_below(q), random_below(q));
+
+            assert_eq!(modpow(&params.g, &fixed(&x), p), params.g.modpow(&x, p));
+            assert_eq!(
+                mul_mod(&fixed(&c), &fixed(&x), q).to_bigint(),
+                (&c * &x).mod_floor(q)
+            );
+            assert_eq!(
+                sub_mod(&fixed(&k), &fixed(&x), q).to_bigint(),
+                (&k - &x).mod_floor(q)
+            );
+
+            // Wide inputs, as from the KDF, are reduced like BigInts
+            let wide = random_below(&(BigInt::one() << (q.bits() + 128)));
+            let bytes = wide.to_bytes_be().1;
+            assert_eq!(
+                FixedUint::from_be_bytes(&bytes, q).to_bigint(),
+                wide.mod_floor(q)
+            );
+        }
+    }
+
+    #[test]
+    fn test_small_and_edge_values() {
+        let m = BigInt::from(5004);
+        let fixed = |n: i64| FixedUint::from_bigint(&BigInt::from(n), &m);
+        assert_eq!(sub_mod(&fixed(4), &fixed(6), &m), fixed(5002));
+        assert_eq!(mul_mod(&fixed(5003), &fixed(5003), &m), fixed(1));
+        assert_eq!(fixed(-1).to_bigint(), BigInt::from(5003));
+        assert_eq!(fixed(10009).to_bigint(), BigInt::one());
+        assert_ne!(fixed(4), fixed(5));
+
+        // Encodings are fixed width, whatever the value
+        assert_eq!(fixed(0).to_be_bytes(2), vec![0, 0]);
+        assert_eq!(fixed(5003).to_be_bytes(2), vec![0x13, 0x8b]);
+        assert_eq!(
+            FixedUint::from_be_bytes(&[0, 0, 0x13, 0x8b], &m),
+            fixed(5003)
+        );
+
+        let p = BigInt::from(10009);
+        let exp = |n: i64| FixedUint::from_bigint(&BigInt::from(n), &p);
+        assert_eq!(modpow(&BigInt::from(3), &exp(0), &p), BigInt::one());
+        assert_eq!(modpow(&BigInt::from(3), &exp(4), &p), BigInt::from(81));
+        assert_eq!(modpow(&BigInt::from(20018), &exp(5), &p), BigInt::from(0));
+    }
+
+    #[test]
+    fn test_random_is_below_modulus() {
+        let q = NamedGroup::Rfc5114_2048_256.params().q;
+        let samples: Vec<BigInt> = (0..32).map(|_| FixedUint::random(&q).to_bigint()).collect();
+        assert!(samples.iter().all(|k| k < &q));
+        assert!(samples.iter().any(|k| k.bits() >= q.bits() - 4));
+    }
+
+    /// Times `f` on each input class in turn, interleaved to spread out noise, returning the samples for each class
+    fn sample_timings(
+        classes: &[FixedUint],
+        rounds: usize,
+        f: impl Fn(&FixedUint),
+    ) -> Vec<Vec<f64>> {
+        let mut samples = vec![Vec::with_capacity(rounds); classes.len()];
+        for _ in 0..rounds {
+            for (i, input) in classes.iter().enumerate() {
+                let start = Instant::now();
+                f(input);
+                samples[i].push(start.elapsed().as_nanos() as f64);
+            }
+        }
+        samples
+    }
+
+    /// Welch's t statistic between two sets of samples, with the slowest 10% of each discarded as scheduler noise
+    fn welch_t(a: &[f64], b: &[f64]) -> f64 {
+        fn trimmed(v: &[f64]) -> Vec<f64> {
+            let mut v = v.to_vec();
+            v.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            v.truncate(v.len() * 9 / 10);
+            v
+        }
+        fn mean_var(v: &[f64]) -> (f64, f64) {
+            let n = v.len() as f64;
+            let mean = v.iter().sum::<f64>() / n;
+            let var = v.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1.0);
+            (mean, var)
+        }
+
+        let (a, b) = (trimmed(a), trimmed(b));
+        let ((ma, va), (mb, vb)) = (mean_var(&a), mean_var(&b));
+        (ma - mb) / (va / a.len() as f64 + vb / b.len() as f64).sqrt()
+    }
+
+    /// Timing-variance harness in the style of dudect : secrets with very different values (zero, a single low
+    /// bit, a single high bit, almost all bits set, random) must not be distinguishable by running time.
+    /// |t| > 10 is treated as clear leakage. This only catches gross leaks such as skipped multiplications,
+    /// early exits or sizes taken from the value; it can't show the absence of cache-timing leaks.
+    /// Timing is noisy under a debug build or a loaded machine, so run explicitly with
+    /// `cargo test --release -- --ignored test_constant_time`
+    #[test]
+    #[ignore]
+    fn test_constant_time_secret_operations() {
+        let params = NamedGroup::Rfc5114_2048_256.params();
+        let (p, q) = (&params.p, &params.q);
+        let top_bit = BigInt::one() << (q.bits() - 1);
+        let classes = [
+            BigInt::from(0),
+            BigInt::one(),
+            top_bit.clone(),
+            q - 1,
+            &top_bit + (random_below(q) >> 1),
+        ]
+        .map(|n| FixedUint::from_bigint(&n, q));
+        let rounds = 1000;
+
+        let exp = sample_timings(&classes, rounds, |x| {
+            modpow(&params.g, x, p);
+        });
+        let c = FixedUint::random(q);
+        let mul = sample_timings(&classes, rounds * 10, |x| {
+            mul_mod(&c, x, q);
+        });
+        let k = FixedUint::random(q);
+        let sub = sample_timings(&classes, rounds * 10, |x| {
+            sub_mod(&k, x, q);
+        });
+
+        // Every comparison goes in the report, so a failure shows how far each operation is from the threshold
+        let mut report = Vec::new();
+        for (name, samples) in [("modpow", exp), ("mul_mod", mul), ("sub_mod", sub)] {
+            for i in 1..samples.len() {
+                report.push((name, i, welch_t(&samples[0], &samples[i])));
+            }
+        }
+        assert!(
+            report.iter().all(|(_, _, t)| t.abs() < 10.0),
+            "timing depends on the secret : {:.2?}",
+            report
+        );
+    }
+}
@@ -0,0 +1,306 @@
+use std::{fmt::Display, str::FromStr};
+
+use anyhow::{anyhow, Result};
+use num::{BigInt, Num, One};
+
+use super::ChaumPedersenParams;
+use crate::group::Group;
+
+/// Standardized finite-field groups which can be used in place of explicit P/Q/G/H parameters
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NamedGroup {
+    /// RFC 7919 Appendix A.1, 2048-bit safe prime
+    Ffdhe2048,
+    /// RFC 7919 Appendix A.2, 3072-bit safe prime
+    Ffdhe3072,
+    /// RFC 7919 Appendix A.3, 4096-bit safe prime
+    Ffdhe4096,
+    /// RFC 3526 Section 3, 2048-bit MODP safe prime
+    Modp2048,
+    /// RFC 3526 Section 4, 3072-bit MODP safe prime
+    Modp3072,
+    /// RFC 5114 Section 2.3, 2048-bit MODP group with a 256-bit prime order subgroup
+    Rfc5114_2048_256,
+}
+
+impl NamedGroup {
+    pub const ALL: [NamedGroup; 6] = [
+        NamedGroup::Ffdhe2048,
+        NamedGroup::Ffdhe3072,
+        NamedGroup::Ffdhe4096,
+        NamedGroup::Modp2048,
+        NamedGroup::Modp3072,
+        NamedGroup::Rfc5114_2048_256,
+    ];
+
+    /// The name used to select this group, i.e. via CP_GROUP
+    pub fn name(&self) -> &'static str {
+        match self {
+            NamedGroup::Ffdhe2048 => "ffdhe2048",
+            NamedGroup::Ffdhe3072 => "ffdhe3072",
+            NamedGroup::Ffdhe4096 => "ffdhe4096",
+            NamedGroup::Modp2048 => "modp2048",
+            NamedGroup::Modp3072 => "modp3072",
+            NamedGroup::Rfc5114_2048_256 => "rfc5114-2048-256",
+        }
+    }
+
+    /// Builds the ChaumPedersenParams for this group.
+    /// G is the generator published with the group, H is derived from G, P and the group name by hashing into the order-Q subgroup
+    pub fn params(&self) -> ChaumPedersenParams {
+        let (p, q, g) = match self {
+            NamedGroup::Ffdhe2048 => safe_prime_group(FFDHE2048_P),
+            NamedGroup::Ffdhe3072 => safe_prime_group(FFDHE3072_P),
+            NamedGroup::Ffdhe4096 => safe_prime_group(FFDHE4096_P),
+            NamedGroup::Modp2048 => safe_prime_group(MODP2048_P),
+            NamedGroup::Modp3072 => safe_prime_group(MODP3072_P),
+            NamedGroup::Rfc5114_2048_256 => (
+                parse_hex(RFC5114_2048_256_P),
+                parse_hex(RFC5114_2048_256_Q),
+                parse_hex(RFC5114_2048_256_G),
+            ),
+        };
+
+        ChaumPedersenParams::new_with_derived_h(p, q, g, self.name().as_bytes().to_vec())
+    }
+
+    /// The named group params are, if any. Named groups derive H from their name, so only that one is compared
+    pub fn of(params: &ChaumPedersenParams) -> Option<NamedGroup> {
+        let seed = params.h_seed.as_deref()?;
+        NamedGroup::ALL
+            .into_iter()
+            .find(|g| g.name().as_bytes() == seed)
+            .filter(|g| g.params().encode_params() == params.encode_params())
+    }
+}
+
+impl Display for NamedGroup {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for NamedGroup {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        NamedGroup::ALL
+            .into_iter()
+            .find(|g| g.name().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| {
+                anyhow!(
+                    "unknown group '{}'. supported groups are {}",
+                    s,
+                    NamedGroup::ALL.map(|g| g.name()).join(", ")
+                )
+            })
+    }
+}
+
+/// Parses a hex constant as printed in the RFCs, ignoring whitespace
+fn parse_hex(hex: &str) -> BigInt {
+    let digits: String = hex.split_whitespace().collect();
+    BigInt::from_str_radix(&digits, 16).expect("named group constants are valid hex")
+}
+
+/// RFC 3526 and RFC 7919 groups are safe primes with generator 2, so q = (p-1)/2
+fn safe_prime_group(p_hex: &str) -> (BigInt, BigInt, BigInt) {
+    let p = parse_hex(p_hex);
+    let q = (&p - BigInt::one()) / 2;
+
+    (p, q, BigInt::from(2))
+}
+
+const FFDHE2048_P: &str = "
+    FFFFFFFF FFFFFFFF ADF85458 A2BB4A9A AFDC5620 273D3CF1 D8B9C583 CE2D3695
+    A9E13641 146433FB CC939DCE 249B3EF9 7D2FE363 630C75D8 F681B202 AEC4617A
+    D3DF1ED5 D5FD6561 2433F51F 5F066ED0 85636555 3DED1AF3 B557135E 7F57C935
+    984F0C70 E0E68B77 E2A689DA F3EFE872 1DF158A1 36ADE735 30ACCA4F 483A797A
+    BC0AB182 B324FB61 D108A94B B2C8E3FB B96ADAB7 60D7F468 1D4F42A3 DE394DF4
+    AE56EDE7 6372BB19 0B07A7C8 EE0A6D70 9E02FCE1 CDF7E2EC C03404CD 28342F61
+    9172FE9C E98583FF 8E4F1232 EEF28183 C3FE3B1B 4C6FAD73 3BB5FCBC 2EC22005
+    C58EF183 7D1683B2 C6F34A26 C1B2EFFA 886B4238 61285C97 FFFFFFFF FFFFFFFF";
+
+const FFDHE3072_P: &str = "
+    FFFFFFFF FFFFFFFF ADF85458 A2BB4A9A AFDC5620 273D3CF1 D8B9C583 CE2D3695
+    A9E13641 146433FB CC939DCE 249B3EF9 7D2FE363 630C75D8 F681B202 AEC4617A
+    D3DF1ED5 D5FD6561 2433F51F 5F066ED0 85636555 3DED1AF3 B557135E 7F57C935
+    984F0C70 E0E68B77 E2A689DA F3EFE872 1DF158A1 36ADE735 30ACCA4F 483A797A
+    BC0AB182 B324FB61 D108A94B B2C8E3FB B96ADAB7 60D7F468 1D4F42A3 DE394DF4
+    AE56EDE7 6372BB19 0B07A7C8 EE0A6D70 9E02FCE1 CDF7E2EC C03404CD 28342F61
+    9172FE9C E98583FF 8E4F1232 EEF28183 C3FE3B1B 4C6FAD73 3BB5FCBC 2EC22005
+    C58EF183 7D1683B2 C6F34A26 C1B2EFFA 886B4238 611FCFDC DE355B3B 6519035B
+    BC34F4DE F99C0238 61B46FC9 D6E6C907 7AD91D26 91F7F7EE 598CB0FA C186D91C
+    AEFE1309 85139270 B4130C93 BC437944 F4FD4452 E2D74DD3 64F2E21E 71F54BFF
+    5CAE82AB 9C9DF69E E86D2BC5 22363A0D ABC52197 9B0DEADA 1DBF9A42 D5C4484E
+    0ABCD06B FA53DDEF 3C1B20EE 3FD59D7C 25E41D2B 66C62E37 FFFFFFFF FFFFFFFF";
+
+const FFDHE4096_P: &str = "
+    FFFFFFFF FFFFFFFF ADF85458 A2BB4A9A AFDC5620 273D3CF1 D8B9C583 CE2D3695
+    A9E13641 146433FB CC939DCE 249B3EF9 7D2FE363 630C75D8 F681B202 AEC4617A
+    D3DF1ED5 D5FD6561 2433F51F 5F066ED0 85636555 3DED1AF3 B557135E 7F57C935
+    984F0C70 E0E68B77 E2A689DA F3EFE872 1DF158A1 36ADE735 30ACCA4F 483A797A
+    BC0AB182 B324FB61 D108A94B B2C8E3FB B96ADAB7 60D7F468 1D4F42A3 DE394DF4
+    AE56EDE7 6372BB19 0B07A7C8 EE0A6D70 9E02FCE1 CDF7E2EC C03404CD 28342F61
+    9172FE9C E98583FF 8E4F1232 EEF28183 C3FE3B1B 4C6FAD73 3BB5FCBC 2EC22005
+    C58EF183 7D1683B2 C6F34A26 C1B2EFFA 886B4238 611FCFDC DE355B3B 6519035B
+    BC34F4DE F99C0238 61B46FC9 D6E6C907 7AD91D26 91F7F7EE 598CB0FA C186D91C
+    AEFE1309 85139270 B4130C93 BC437944 F4FD4452 E2D74DD3 64F2E21E 71F54BFF
+    5CAE82AB 9C9DF69E E86D2BC5 22363A0D ABC52197 9B0DEADA 1DBF9A42 D5C4484E
+    0ABCD06B FA53DDEF 3C1B20EE 3FD59D7C 25E41D2B 669E1EF1 6E6F52C3 164DF4FB
+    7930E9E4 E58857B6 AC7D5F42 D69F6D18 7763CF1D 55034004 87F55BA5 7E31CC7A
+    7135C886 EFB4318A ED6A1E01 2D9E6832 A907600A 918130C4 6DC778F9 71AD0038
+    092999A3 33CB8B7A 1A1DB93D 7140003C 2A4ECEA9 F98D0ACC 0A8291CD CEC97DCF
+    8EC9B55A 7F88A46B 4DB5A851 F44182E1 C68A007E 5E655F6A FFFFFFFF FFFFFFFF";
+
+const MODP2048_P: &str = "
+    FFFFFFFF FFFFFFFF C90FDAA2 2168C234 C4C6628B 80DC1CD1 29024E08 8A67CC74
+    020BBEA6 3B139B22 514A0879 8E3404DD EF9519B3 CD3A431B 302B0A6D F25F1437
+    4FE1356D 6D51C245 E485B576 625E7EC6 F44C42E9 A637ED6B 0BFF5CB6 F406B7ED
+    EE386BFB 5A899FA5 AE9F2411 7C4B1FE6 49286651 ECE45B3D C2007CB8 A163BF05
+    98DA4836 1C55D39A 69163FA8 FD24CF5F 83655D23 DCA3AD96 1C62F356 208552BB
+    9ED52907 7096966D 670C354E 4ABC9804 F1746C08 CA18217C 32905E46 2E36CE3B
+    E39E772C 180E8603 9B2783A2 EC07A28F B5C55DF0 6F4C52C9 DE2BCBF6 95581718
+    3995497C EA956AE5 15D22618 98FA0510 15728E5A 8AACAA68 FFFFFFFF FFFFFFFF";
+
+const MODP3072_P: &str = "
+    FFFFFFFF FFFFFFFF C90FDAA2 2168C234 C4C6628B 80DC1CD1 29024E08 8A67CC74
+    020BBEA6 3B139B22 514A0879 8E3404DD EF9519B3 CD3A431B 302B0A6D F25F1437
+    4FE1356D 6D51C245 E485B576 625E7EC6 F44C42E9 A637ED6B 0BFF5CB6 F406B7ED
+    EE386BFB 5A899FA5 AE9F2411 7C4B1FE6 49286651 ECE45B3D C2007CB8 A163BF05
+    98DA4836 1C55D39A 69163FA8 FD24CF5F 83655D23 DCA3AD96 1C62F356 208552BB
+    9ED52907 7096966D 670C354E 4ABC9804 F1746C08 CA18217C 32905E46 2E36CE3B
+    E39E772C 180E8603 9B2783A2 EC07A28F B5C55DF0 6F4C52C9 DE2BCBF6 95581718
+    3995497C EA956AE5 15D22618 98FA0510 15728E5A 8AAAC42D AD33170D 04507A33
+    A85521AB DF1CBA64 ECFB8504 58DBEF0A 8AEA7157 5D060C7D B3970F85 A6E1E4C7
+    ABF5AE8C DB0933D7 1E8C94E0 4A25619D CEE3D226 1AD2EE6B F12FFA06 D98A0864
+    D8760273 3EC86A64 521F2B18 177B200C BBE11757 7A615D6C 770988C0 BAD946E2
+    08E24FA0 74E5AB31 43DB5BFC E0FD108E 4B82D120 A93AD2CA FFFFFFFF FFFFFFFF";
+
+const RFC5114_2048_256_P: &str = "
+    87A8E61D B4B6663C FFBBD19C 65195999 8CEEF608 660DD0F2 5D2CEED4 435E3B00
+    E00DF8F1 D61957D4 FAF7DF45 61B2AA30 16C3D911 34096FAA 3BF4296D 830E9A7C
+    209E0C64 97517ABD 5A8A9D30 6BCF67ED 91F9E672 5B4758C0 22E0B1EF 4275BF7B
+    6C5BFC11 D45F9088 B941F54E B1E59BB8 BC39A0BF 12307F5C 4FDB70C5 81B23F76
+    B63ACAE1 CAA6B790 2D525267 35488A0E F13C6D9A 51BFA4AB 3AD83477 96524D8E
+    F6A167B5 A41825D9 67E144E5 14056425 1CCACB83 E6B486F6 B3CA3F79 71506026
+    C0B857F6 89962856 DED4010A BD0BE621 C3A3960A 54E710C3 75F26375 D7014103
+    A4B54330 C198AF12 6116D227 6E11715F 693877FA D7EF09CA DB094AE9 1E1A1597";
+
+const RFC5114_2048_256_Q: &str = "
+    8CF83642 A709A097 B4479976 40129DA2 99B1A47D 1EB3750B A308B0FE 64F5FBD3";
+
+const RFC5114_2048_256_G: &str = "
+    3FB32C9B 73134D0B 2E775066 60EDBD48 4CA7B18F 21EF2054 07F4793A 1A0BA125
+    10DBC150 77BE463F FF4FED4A AC0BB555 BE3A6C1B 0C6B47B1 BC3773BF 7E8C6F62
+    901228F8 C28CBB18 A55AE313 41000A65 0196F931 C77A57F2 DDF463E5 E9EC144B
+    777DE62A AAB8A862 8AC376D2 82D6ED38 64E67982 428EBC83 1D14348F 6F2F9193
+    B5045AF2 767164E1 DFC967C1 FB3F2E55 A4BD1BFF E83B9C80 D052B985 D182EA0A
+    DB2A3B73 13D3FE14 C8484B1E 052588B9 B7D2BBD2 DF016199 ECD06E15 57CD0915
+    B3353BBB 64E0EC37 7FD02837 0DF92B52 C7891428 CDC67EB6 184B523D 1DB246C3
+    2F630784 90F00EF8 D647D148 D4795451 5E2327CF EF98C582 664B4C0F 6CC41659";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_named_groups_are_valid() {
+        for group in NamedGroup::ALL {
+            let params = group.params();
+            assert_eq!(params.validate(), Ok(()), "group {}", group);
+        }
+    }
+
+    #[test]
+    fn test_named_group_of_params() {
+        for group in NamedGroup::ALL {
+            assert_eq!(NamedGroup::of(&group.params()), Some(group));
+        }
+
+        // The same seed over other parameters isn't the named group
+        let ffdhe2048 = NamedGroup::Ffdhe2048.params();
+        let params = ChaumPedersenParams {
+            h_seed: Some(b"ffdhe2048".to_vec()),
+            ..NamedGroup::Modp2048.params()
+        };
+        assert_eq!(NamedGroup::of(&params), None);
+        let params = ChaumPedersenParams {
+            h_seed: None,
+            ..ffdhe2048
+        };
+        assert_eq!(NamedGroup::of(&params), None);
+    }
+
+    #[test]
+    fn test_named_group_sizes() {
+        let bits = |g: NamedGroup| g.params().p.bits();
+        assert_eq!(bits(NamedGroup::Ffdhe2048), 2048);
+        assert_eq!(bits(NamedGroup::Ffdhe3072), 3072);
+        assert_eq!(bits(NamedGroup::Ffdhe4096), 4096);
+        assert_eq!(bits(NamedGroup::Modp2048), 2048);
+        assert_eq!(bits(NamedGroup::Modp3072), 3072);
+        assert_eq!(bits(NamedGroup::Rfc5114_2048_256), 2048);
+        assert_eq!(NamedGroup::Rfc5114_2048_256.params().q.bits(), 256);
+    }
+
+    /// SHA-256 of the big-endian bytes of p and q. The RFC 3526 and RFC 7919 primes were recomputed from the
+    /// formulas given in the RFCs (e.g. `p = 2^2048 - 2^1984 - 1 + 2^64 * ([2^1918 pi] + 124476)`), and the
+    /// RFC 5114 values hashed as published
+    const KNOWN_DIGESTS: [(NamedGroup, &str, &str); 6] = [
+        (
+            NamedGroup::Ffdhe2048,
+            "9cd3b7f336872f46c09428d1bbc19877a4d440512cda8d1c1cf0cd6e33698966",
+            "3072ed775345bbd8721aeb890833aa381bf5a19a889fb8c2b71ebd5cead60fe1",
+        ),
+        (
+            NamedGroup::Ffdhe3072,
+            "0eaf67db3a839156d5013494a5318a772b5697d270d721f37f092efc69ea5a17",
+            "181f68e06ad73585303cbcab43a6ac96ce2ce8e4927e8dbf2251a469108b72a3",
+        ),
+        (
+            NamedGroup::Ffdhe4096,
+            "4648414224ac881b3d0dc59b466f96d06a558278776807797ecf1f66ff397b3e",
+            "657f638a3039e247a4b0506feac04910ce72777b6ebbeccbc08d0db08e4c6302",
+        ),
+        (
+            NamedGroup::Modp2048,
+            "d66436f79bbd6b2e38c0ffbd079be904d2641415e2e67140e09448be9a60890e",
+            "a638f55d63c78f6d9ca04f0de138b1699fd6ff9737d926f4c24f318fc1d0f572",
+        ),
+        (
+            NamedGroup::Modp3072,
+            "48cf8b092fbce4359d9871abf74f98e25b6163379eaa15cd9087e800c6d1c55c",
+            "018089836b3704979e9c4ed47a717e61440ff98b4deba0e7e95e56514442e52c",
+        ),
+        (
+            NamedGroup::Rfc5114_2048_256,
+            "0b7835722cb619827610c2549fdda5587421686c4409a13865e76225522ddcc9",
+            "f0db115b384f879f40f2f4dbae5dbd1681afdabdcd1be002dca609e6869aae0f",
+        ),
+    ];
+
+    #[test]
+    fn test_named_group_known_values() {
+        use sha2::{Digest, Sha256};
+
+        let digest = |n: &BigInt| hex::encode(Sha256::digest(n.to_bytes_be().1));
+        for (group, p, q) in KNOWN_DIGESTS {
+            let params = group.params();
+            assert_eq!(digest(&params.p), p, "p of group {}", group);
+            assert_eq!(digest(&params.q), q, "q of group {}", group);
+        }
+    }
+
+    #[test]
+    fn test_named_group_from_str() {
+        for group in NamedGroup::ALL {
+            assert_eq!(NamedGroup::from_str(group.name()).unwrap(), group);
+        }
+        assert_eq!(
+            NamedGroup::from_str("FFDHE3072").unwrap(),
+            NamedGroup::Ffdhe3072
+        );
+        assert!(NamedGroup::from_str("ffdhe1024").is_err());
+    }
+}
@@ -1,98 +1,542 @@
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
 
-use num::{bigint::ToBigInt, traits::ToBytes};
-use num_bigint::{BigInt, Sign};
-use rand_core::{OsRng, RngCore};
-use tonic::transport::Channel;
+use tonic::transport::{Channel, ClientTlsConfig, Endpoint};
 
-use crate::chaum_pedersen::ChaumPedersenParams;
+use crate::{
+    backend::Backend,
+    error::ZkpAuthError,
+    group::{ChaumPedersen, Group, ProofContext, ProofMode, Ristretto255, P256},
+    kdf::{KdfParams, ARGON2ID},
+    server::{
+        credentials_context, LOGIN_PURPOSE, PROTOCOL_VERSION, REGISTER_PURPOSE, ROTATE_PURPOSE,
+    },
+    token::TokenPublicKey,
+};
 
 use self::zkp_auth::{
     auth_client::AuthClient, AuthenticationAnswerRequest, AuthenticationAnswerResponse,
-    AuthenticationChallengeRequest, AuthenticationChallengeResponse, RegisterRequest,
-    RegisterResponse,
+    AuthenticationChallengeRequest, AuthenticationChallengeResponse, GetPublicKeysRequest,
+    KdfParametersRequest, ListSessionsRequest, LogoutRequest, ProofNonceRequest,
+    ProofNonceResponse, ProveRequest, ProveResponse, RefreshSessionRequest, RefreshSessionResponse,
+    RegisterRequest, RegisterResponse, RotateCredentialsRequest, RotateCredentialsResponse,
+    ServerInfoRequest, ServerInfoResponse, SessionInfo, ValidateSessionRequest,
+    ValidateSessionResponse,
 };
 
+mod admin;
+
+pub use admin::AdminClient;
+
+/// The gRPC messages and client, shared with the server so conversions such as the one from [`KdfParams`] are
+/// only defined once
 pub mod zkp_auth {
-    tonic::include_proto!("zkp_auth");
+    pub use crate::server::zkp_auth::*;
 }
 
 /// Runs a Chaum-Pedersen ZKP Protocol client with the given parameters, against the server at the given address.
 /// This function will go through the entire protocol, including user registration and auth challenge and return the session ID if successful.
+/// The secret x is derived from `password` with Argon2id, using a per-user salt stored by the server.
+/// `mode` selects between the interactive challenge-response flow and a single non-interactive Fiat-Shamir proof.
 pub async fn run_client_auth_check(
     addr: &str,
     user: &str,
-    secret: &BigInt,
-    params: ChaumPedersenParams,
+    password: &str,
+    backend: impl Into<Backend>,
+    mode: ProofMode,
 ) -> Result<String> {
-    let mut client = Client::new(addr, user.to_string()).await;
+    run_client_auth_check_with_options(
+        addr,
+        user,
+        password,
+        backend,
+        mode,
+        ClientOptions::default(),
+    )
+    .await
+}
 
-    let (y1, y2) = params.y1_y2(secret);
+/// Runs the client, as [`run_client_auth_check`], with the given options
+pub async fn run_client_auth_check_with_options(
+    addr: &str,
+    user: &str,
+    password: &str,
+    backend: impl Into<Backend>,
+    mode: ProofMode,
+    options: ClientOptions,
+) -> Result<String> {
+    let backend = backend.into();
+    backend
+        .validate()
+        .context("refusing to authenticate with invalid Chaum-Pedersen parameters")?;
 
-    let res = client
-        .register(user, y1.to_bytes_be().1, y2.to_bytes_be().1)
-        .await;
+    let mut client = Client::connect(addr, user.to_string(), options.tls).await?;
+    client.enrollment_code = options.enrollment_code;
 
-    log::trace!("RegisterResponse: {:?}", res);
+    let info = client
+        .check_server(&backend, options.pinned_fingerprint.as_ref())
+        .await?;
+    if !info.proof_modes.iter().any(|m| m == mode.name()) {
+        return Err(anyhow!(
+            "server does not accept {} proofs. it accepts {}",
+            mode.name(),
+            info.proof_modes.join(", ")
+        ));
+    }
 
-    let k = OsRng.next_u64().to_bigint().unwrap();
-    let (r1, r2) = params.r1_r2(&k);
+    let session_id = match backend {
+        Backend::ModP(params) => {
+            client
+                .register_and_authenticate(params, password, mode)
+                .await?
+        }
+        Backend::Ristretto255 => {
+            client
+                .register_and_authenticate(Ristretto255, password, mode)
+                .await?
+        }
+        Backend::P256 => {
+            client
+                .register_and_authenticate(P256, password, mode)
+                .await?
+        }
+    };
 
-    let res = client
-        .create_authentication_challenge(r1.to_be_bytes().to_vec(), r2.to_be_bytes().to_vec())
-        .await?;
+    println!("Authentication successful. Session {}", session_id);
+    Ok(session_id)
+}
 
-    log::trace!("AuthenticationChallengeResponse: {:?}", res);
+/// Changes the user's password on the server at the given address, proving knowledge of the current one.
+/// Returns how many of the user's sessions the server ended
+pub async fn run_client_rotate(
+    addr: &str,
+    user: &str,
+    password: &str,
+    new_password: &str,
+    backend: impl Into<Backend>,
+) -> Result<u64> {
+    run_client_rotate_with_options(
+        addr,
+        user,
+        password,
+        new_password,
+        backend,
+        ClientOptions::default(),
+    )
+    .await
+}
 
-    let auth_id = res.auth_id;
-    let c = BigInt::from_bytes_be(Sign::Plus, &res.c);
-    let s = params.s(&k, &c, secret);
+/// Changes the user's password, as [`run_client_rotate`], with the given options
+pub async fn run_client_rotate_with_options(
+    addr: &str,
+    user: &str,
+    password: &str,
+    new_password: &str,
+    backend: impl Into<Backend>,
+    options: ClientOptions,
+) -> Result<u64> {
+    let backend = backend.into();
+    backend
+        .validate()
+        .context("refusing to rotate credentials with invalid Chaum-Pedersen parameters")?;
 
-    let res = client
-        .verify_authentication(s.to_be_bytes().to_vec(), auth_id)
+    let mut client = Client::connect(addr, user.to_string(), options.tls).await?;
+    client
+        .check_server(&backend, options.pinned_fingerprint.as_ref())
         .await?;
 
-    log::trace!("AuthenticationAnswerResponse: {:?}", res);
+    let revoked = match backend {
+        Backend::ModP(params) => {
+            client
+                .rotate_password(params, password, new_password)
+                .await?
+        }
+        Backend::Ristretto255 => {
+            client
+                .rotate_password(Ristretto255, password, new_password)
+                .await?
+        }
+        Backend::P256 => client.rotate_password(P256, password, new_password).await?,
+    };
 
-    println!("Authentication successful. Session {}", res.session_id);
-    Ok(res.session_id)
+    println!("Password changed. {} sessions ended", revoked);
+    Ok(revoked)
+}
+
+/// Opens a channel to the server at addr, over TLS if `tls` is set. The server's certificate must be valid for
+/// the host in addr
+async fn connect_channel(addr: &str, tls: Option<ClientTlsConfig>) -> Result<Channel> {
+    let scheme = if tls.is_some() { "https" } else { "http" };
+    let mut endpoint = Endpoint::from_shared(format!("{}://{}", scheme, addr))
+        .with_context(|| format!("invalid server address {}", addr))?;
+    if let Some(tls) = tls {
+        endpoint = endpoint.tls_config(tls).context("invalid TLS settings")?;
+    }
+
+    endpoint
+        .connect()
+        .await
+        .with_context(|| format!("Failed to connect to {}", addr))
+}
+
+/// Decodes a [`ZkpAuthError`] from a status returned by the server, so callers can branch on it with
+/// `downcast_ref::<ZkpAuthError>()`. Other statuses are returned as they are
+fn rpc_error(status: tonic::Status) -> anyhow::Error {
+    match ZkpAuthError::from_status(&status) {
+        Some(e) => e.into(),
+        None => status.into(),
+    }
+}
+
+/// Optional client settings
+#[derive(Debug, Clone, Default)]
+pub struct ClientOptions {
+    /// Connects over TLS, see [`crate::tls::client_tls_config`]. The transport is plaintext if unset
+    pub tls: Option<ClientTlsConfig>,
+    /// Enrollment token or invite code, presented when registering with a server that restricts registration
+    pub enrollment_code: Option<String>,
+    /// Refuses to authenticate unless the group's fingerprint is this one, see [`Client::check_server`]
+    pub pinned_fingerprint: Option<[u8; 32]>,
 }
 
 pub struct Client {
     c: AuthClient<Channel>,
     user: String,
+    enrollment_code: Option<String>,
 }
 
 impl Client {
     pub async fn new(addr: &str, user: String) -> Self {
-        let c = AuthClient::connect(format!("http://{}", addr))
-            .await
-            .unwrap();
+        Self::connect(addr, user, None).await.unwrap()
+    }
+
+    /// Connects to the server at addr, over TLS if `tls` is set
+    pub async fn connect(addr: &str, user: String, tls: Option<ClientTlsConfig>) -> Result<Self> {
+        let c = AuthClient::new(connect_channel(addr, tls).await?);
+        Ok(Client {
+            c,
+            user,
+            enrollment_code: None,
+        })
+    }
+
+    /// Sets the enrollment token or invite code presented by later registrations
+    pub fn set_enrollment_code(&mut self, code: impl Into<String>) {
+        self.enrollment_code = Some(code.into());
+    }
 
-        Client { c, user }
+    /// Registers the client's user with a secret derived from the password, then proves knowledge of it, returning the session ID.
+    /// A failed registration is only logged, so an existing user can still log in
+    pub async fn register_and_authenticate<G: Group>(
+        &mut self,
+        group: G,
+        password: &str,
+        mode: ProofMode,
+    ) -> Result<String> {
+        let res = self.register_with_password(&group, password).await;
+
+        log::trace!("RegisterResponse: {:?}", res);
+
+        self.login(group, password, mode).await
+    }
+
+    /// Registers the client's user with a fresh salt, and y1, y2 computed from the derived secret
+    pub async fn register_with_password<G: Group>(
+        &mut self,
+        group: &G,
+        password: &str,
+    ) -> Result<RegisterResponse> {
+        let kdf = KdfParams::generate();
+        let x = kdf.derive_secret(password, group)?;
+
+        self.register_secret(&ChaumPedersen::new(group.clone()), &x, &kdf)
+            .await
     }
 
-    pub async fn register(
+    /// Registers the client's user with y1, y2 computed from the secret x, proving knowledge of it.
+    /// `kdf` are the parameters x was derived with
+    pub async fn register_secret<G: Group>(
         &mut self,
-        user: &str,
-        y1: Vec<u8>,
-        y2: Vec<u8>,
+        cp: &ChaumPedersen<G>,
+        x: &G::Scalar,
+        kdf: &KdfParams,
     ) -> Result<RegisterResponse> {
-        let request = tonic::Request::new(RegisterRequest {
-            user: user.to_string(),
+        let group = cp.group();
+        let (y1, y2) = cp.y1_y2(x);
+        let y1 = group.encode_element(&y1);
+        let y2 = group.encode_element(&y2);
+
+        let nonce = self.get_proof_nonce().await?.nonce;
+        let user = self.user.clone();
+        let context = credentials_context(&y1, &y2, kdf);
+        let ctx = ProofContext {
+            purpose: REGISTER_PURPOSE,
+            user: &user,
+            nonce: &nonce,
+            context: &context,
+        };
+        let proof = cp.prove_non_interactive(x, &ctx);
+
+        self.register(RegisterRequest {
+            user,
             y1,
             y2,
+            kdf: Some(kdf.into()),
+            nonce,
+            c: group.encode_scalar(&proof.c),
+            s: group.encode_scalar(&proof.s),
+            enrollment_code: self.enrollment_code.clone().unwrap_or_default(),
+            ..Default::default()
+        })
+        .await
+    }
+
+    /// Fetches the user's KDF parameters, re-derives the secret from the password and proves knowledge of it
+    pub async fn login<G: Group>(
+        &mut self,
+        group: G,
+        password: &str,
+        mode: ProofMode,
+    ) -> Result<String> {
+        let kdf = self.get_kdf_parameters().await?;
+        let x = kdf.derive_secret(password, &group)?;
+
+        let cp = ChaumPedersen::new(group);
+        match mode {
+            ProofMode::Interactive => self.authenticate(&cp, &x).await,
+            ProofMode::FiatShamir => self.authenticate_non_interactive(&cp, &x, false).await,
+            ProofMode::FiatShamirCompact => self.authenticate_non_interactive(&cp, &x, true).await,
+        }
+    }
+
+    /// Replaces the user's secret with one derived from new_password under a fresh salt, proving knowledge of
+    /// the current secret. Returns how many of the user's sessions the server ended
+    pub async fn rotate_password<G: Group>(
+        &mut self,
+        group: G,
+        password: &str,
+        new_password: &str,
+    ) -> Result<u64> {
+        let kdf = self.get_kdf_parameters().await?;
+        let x = kdf.derive_secret(password, &group)?;
+
+        let new_kdf = KdfParams::generate();
+        let new_x = new_kdf.derive_secret(new_password, &group)?;
+
+        let cp = ChaumPedersen::new(group);
+        let group = cp.group();
+        let (new_y1, new_y2) = cp.y1_y2(&new_x);
+        let new_y1 = group.encode_element(&new_y1);
+        let new_y2 = group.encode_element(&new_y2);
+
+        let nonce = self.get_proof_nonce().await?.nonce;
+        let user = self.user.clone();
+        let context = credentials_context(&new_y1, &new_y2, &new_kdf);
+        let ctx = ProofContext {
+            purpose: ROTATE_PURPOSE,
+            user: &user,
+            nonce: &nonce,
+            context: &context,
+        };
+        let proof = cp.prove_non_interactive(&x, &ctx);
+
+        let res = self
+            .rotate_credentials(RotateCredentialsRequest {
+                user,
+                nonce,
+                c: group.encode_scalar(&proof.c),
+                s: group.encode_scalar(&proof.s),
+                new_y1,
+                new_y2,
+                new_kdf: Some((&new_kdf).into()),
+                ..Default::default()
+            })
+            .await?;
+
+        log::trace!("RotateCredentialsResponse: {:?}", res);
+
+        Ok(res.revoked_sessions)
+    }
+
+    /// Proves knowledge of x with the interactive challenge-response flow, returning the session ID
+    pub async fn authenticate<G: Group>(
+        &mut self,
+        cp: &ChaumPedersen<G>,
+        x: &G::Scalar,
+    ) -> Result<String> {
+        let group = cp.group();
+        let k = cp.random_nonce();
+        let (r1, r2) = cp.r1_r2(&k);
+
+        let res = self
+            .create_authentication_challenge(group.encode_element(&r1), group.encode_element(&r2))
+            .await?;
+
+        log::trace!("AuthenticationChallengeResponse: {:?}", res);
+
+        let auth_id = res.auth_id;
+        let c = group.decode_scalar(&res.c)?;
+        let s = cp.s(&k, &c, x);
+
+        let res = self
+            .verify_authentication(group.encode_scalar(&s), auth_id)
+            .await?;
+
+        log::trace!("AuthenticationAnswerResponse: {:?}", res);
+
+        Ok(res.session_id)
+    }
+
+    /// Proves knowledge of x with a single Fiat-Shamir proof, bound to a fresh server nonce, returning the session ID.
+    /// When `compact` is set, the proof is sent as (c, s) rather than (r1, r2, s)
+    pub async fn authenticate_non_interactive<G: Group>(
+        &mut self,
+        cp: &ChaumPedersen<G>,
+        x: &G::Scalar,
+        compact: bool,
+    ) -> Result<String> {
+        let group = cp.group();
+        let nonce = self.get_proof_nonce().await?.nonce;
+
+        let user = self.user.clone();
+        let ctx = ProofContext {
+            purpose: LOGIN_PURPOSE,
+            user: &user,
+            nonce: &nonce,
+            context: &[],
+        };
+        let proof = cp.prove_non_interactive(x, &ctx);
+
+        let request = if compact {
+            ProveRequest {
+                c: group.encode_scalar(&proof.c),
+                ..Default::default()
+            }
+        } else {
+            ProveRequest {
+                r1: group.encode_element(&proof.r1),
+                r2: group.encode_element(&proof.r2),
+                ..Default::default()
+            }
+        };
+
+        let res = self
+            .prove(ProveRequest {
+                user,
+                nonce,
+                s: group.encode_scalar(&proof.s),
+                ..request
+            })
+            .await?;
+
+        log::trace!("ProveResponse: {:?}", res);
+
+        Ok(res.session_id)
+    }
+
+    pub async fn get_server_info(&mut self) -> Result<ServerInfoResponse> {
+        let request = tonic::Request::new(ServerInfoRequest {
+            protocol_version: PROTOCOL_VERSION,
         });
 
         let response = self
             .c
-            .register(request)
+            .get_server_info(request)
             .await
+            .map_err(rpc_error)
+            .context("Failed to get server info")?;
+
+        Ok(response.into_inner())
+    }
+
+    /// Fetches the server's info, and checks that it speaks this client's protocol version over the same group as
+    /// `backend`, so mismatched parameters fail here rather than as failed proofs.
+    /// With `pinned`, the group's fingerprint must also be that one
+    pub async fn check_server(
+        &mut self,
+        backend: &Backend,
+        pinned: Option<&[u8; 32]>,
+    ) -> Result<ServerInfoResponse> {
+        let info = self.get_server_info().await?;
+        if info.protocol_version != PROTOCOL_VERSION {
+            return Err(anyhow!(
+                "server speaks protocol version {}, but this client speaks version {}",
+                info.protocol_version,
+                PROTOCOL_VERSION
+            ));
+        }
+
+        let fingerprint = backend.fingerprint();
+        if info.fingerprint != fingerprint {
+            return Err(anyhow!(
+                "server uses group {} with fingerprint {}, but the client is configured with {} (fingerprint {}). check the CP_ parameters match the server's",
+                info.group,
+                hex::encode(&info.fingerprint),
+                backend,
+                hex::encode(fingerprint)
+            ));
+        }
+        if let Some(pinned) = pinned {
+            if &fingerprint != pinned {
+                return Err(anyhow!(
+                    "group fingerprint {} does not match the pinned fingerprint {}",
+                    hex::encode(fingerprint),
+                    hex::encode(pinned)
+                ));
+            }
+        }
+
+        Ok(info)
+    }
+
+    pub async fn register(&mut self, request: RegisterRequest) -> Result<RegisterResponse> {
+        let user = request.user.clone();
+        let response = self
+            .c
+            .register(tonic::Request::new(request))
+            .await
+            .map_err(rpc_error)
             .with_context(|| format!("Failed to register user {}", user))?;
 
         Ok(response.into_inner())
     }
 
+    /// Fetches the KDF parameters the user registered with
+    pub async fn get_kdf_parameters(&mut self) -> Result<KdfParams> {
+        let request = tonic::Request::new(KdfParametersRequest {
+            user: self.user.to_string(),
+        });
+
+        let response = self
+            .c
+            .get_kdf_parameters(request)
+            .await
+            .map_err(rpc_error)
+            .with_context(|| format!("Failed to get KDF parameters for user {}", self.user))?;
+
+        let kdf = response
+            .into_inner()
+            .kdf
+            .ok_or_else(|| anyhow!("server did not return KDF parameters"))?;
+        if kdf.algorithm != ARGON2ID {
+            return Err(anyhow!(
+                "server requested unsupported KDF '{}'",
+                kdf.algorithm
+            ));
+        }
+
+        let kdf = KdfParams {
+            salt: kdf.salt,
+            memory_kib: kdf.memory_kib,
+            iterations: kdf.iterations,
+            parallelism: kdf.parallelism,
+        };
+        // Checked before deriving anything, so a hostile server can't ask for gigabytes of memory or hours of work
+        kdf.validate()
+            .context("server sent unacceptable KDF parameters")?;
+
+        Ok(kdf)
+    }
+
     pub async fn create_authentication_challenge(
         &mut self,
         r1: Vec<u8>,
@@ -108,6 +552,7 @@ impl Client {
             .c
             .create_authentication_challenge(request)
             .await
+            .map_err(rpc_error)
             .with_context(|| {
                 format!(
                     "Failed to create authentication challenge for user {}",
@@ -129,8 +574,128 @@ impl Client {
             .c
             .verify_authentication(request)
             .await
+            .map_err(rpc_error)
             .with_context(|| format!("Failed to verify authentication for user {}", self.user))?;
 
         Ok(response.into_inner())
     }
+
+    pub async fn get_proof_nonce(&mut self) -> Result<ProofNonceResponse> {
+        let request = tonic::Request::new(ProofNonceRequest {});
+
+        let response = self
+            .c
+            .get_proof_nonce(request)
+            .await
+            .map_err(rpc_error)
+            .context("Failed to get a proof nonce")?;
+
+        Ok(response.into_inner())
+    }
+
+    pub async fn prove(&mut self, request: ProveRequest) -> Result<ProveResponse> {
+        let response = self
+            .c
+            .prove(tonic::Request::new(request))
+            .await
+            .map_err(rpc_error)
+            .with_context(|| format!("Failed to prove knowledge for user {}", self.user))?;
+
+        Ok(response.into_inner())
+    }
+
+    pub async fn validate_session(&mut self, session_id: &str) -> Result<ValidateSessionResponse> {
+        let request = tonic::Request::new(ValidateSessionRequest {
+            session_id: session_id.to_string(),
+        });
+
+        let response = self
+            .c
+            .validate_session(request)
+            .await
+            .map_err(rpc_error)
+            .context("Failed to validate session")?;
+
+        Ok(response.into_inner())
+    }
+
+    pub async fn refresh_session(&mut self, session_id: &str) -> Result<RefreshSessionResponse> {
+        let request = tonic::Request::new(RefreshSessionRequest {
+            session_id: session_id.to_string(),
+        });
+
+        let response = self
+            .c
+            .refresh_session(request)
+            .await
+            .map_err(rpc_error)
+            .context("Failed to refresh session")?;
+
+        Ok(response.into_inner())
+    }
+
+    /// Ends the session, or all of the user's sessions if `all_sessions` is set, returning how many were revoked
+    pub async fn logout(&mut self, session_id: &str, all_sessions: bool) -> Result<u64> {
+        let request = tonic::Request::new(LogoutRequest {
+            session_id: session_id.to_string(),
+            all_sessions,
+        });
+
+        let response = self
+            .c
+            .logout(request)
+            .await
+            .map_err(rpc_error)
+            .context("Failed to log out")?;
+
+        Ok(response.into_inner().revoked)
+    }
+
+    pub async fn list_sessions(&mut self, session_id: &str) -> Result<Vec<SessionInfo>> {
+        let request = tonic::Request::new(ListSessionsRequest {
+            session_id: session_id.to_string(),
+        });
+
+        let response = self
+            .c
+            .list_sessions(request)
+            .await
+            .map_err(rpc_error)
+            .context("Failed to list sessions")?;
+
+        Ok(response.into_inner().sessions)
+    }
+
+    /// Fetches the keys the server signs session tokens with, for use with [`crate::token::verify_token`]
+    pub async fn get_public_keys(&mut self) -> Result<Vec<TokenPublicKey>> {
+        let request = tonic::Request::new(GetPublicKeysRequest {});
+
+        let response = self
+            .c
+            .get_public_keys(request)
+            .await
+            .map_err(rpc_error)
+            .context("Failed to get token public keys")?;
+
+        response
+            .into_inner()
+            .keys
+            .into_iter()
+            .map(|k| TokenPublicKey::from_bytes(&k.kid, &k.algorithm, &k.public_key))
+            .collect()
+    }
+
+    pub async fn rotate_credentials(
+        &mut self,
+        request: RotateCredentialsRequest,
+    ) -> Result<RotateCredentialsResponse> {
+        let response = self
+            .c
+            .rotate_credentials(tonic::Request::new(request))
+            .await
+            .map_err(rpc_error)
+            .with_context(|| format!("Failed to rotate credentials for user {}", self.user))?;
+
+        Ok(response.into_inner())
+    }
 }
@@ -0,0 +1,152 @@
+use anyhow::{anyhow, Context, Result};
+use tonic::{
+    metadata::MetadataValue,
+    transport::{Channel, ClientTlsConfig},
+    Request,
+};
+
+use super::{
+    rpc_error,
+    zkp_auth::{
+        admin_client, AdminUser, CreateInviteRequest, CreateInviteResponse, DeleteUserRequest,
+        GetUserRequest, ListUsersRequest, LockUserRequest, RevokeAllSessionsRequest,
+        UnlockUserRequest,
+    },
+};
+
+/// Client for the server's Admin service. Every call is authorized with the admin token
+pub struct AdminClient {
+    c: admin_client::AdminClient<Channel>,
+    authorization: MetadataValue<tonic::metadata::Ascii>,
+}
+
+impl AdminClient {
+    pub async fn new(addr: &str, token: &str) -> Result<Self> {
+        Self::connect(addr, token, None).await
+    }
+
+    /// Connects to the server at addr, over TLS if `tls` is set
+    pub async fn connect(addr: &str, token: &str, tls: Option<ClientTlsConfig>) -> Result<Self> {
+        let c = admin_client::AdminClient::new(super::connect_channel(addr, tls).await?);
+        let authorization = format!("Bearer {}", token)
+            .parse()
+            .map_err(|_| anyhow!("admin token must be printable ASCII"))?;
+
+        Ok(AdminClient { c, authorization })
+    }
+
+    fn request<T>(&self, message: T) -> Request<T> {
+        let mut request = Request::new(message);
+        request
+            .metadata_mut()
+            .insert("authorization", self.authorization.clone());
+        request
+    }
+
+    pub async fn list_users(&mut self) -> Result<Vec<AdminUser>> {
+        let request = self.request(ListUsersRequest {});
+
+        let response = self
+            .c
+            .list_users(request)
+            .await
+            .map_err(rpc_error)
+            .context("Failed to list users")?;
+
+        Ok(response.into_inner().users)
+    }
+
+    pub async fn get_user(&mut self, user: &str) -> Result<AdminUser> {
+        let request = self.request(GetUserRequest {
+            user: user.to_string(),
+        });
+
+        let response = self
+            .c
+            .get_user(request)
+            .await
+            .map_err(rpc_error)
+            .with_context(|| format!("Failed to get user {}", user))?;
+
+        response
+            .into_inner()
+            .user
+            .ok_or_else(|| anyhow!("server returned no user"))
+    }
+
+    /// Deletes the user, returning how many of their sessions were revoked
+    pub async fn delete_user(&mut self, user: &str) -> Result<u64> {
+        let request = self.request(DeleteUserRequest {
+            user: user.to_string(),
+        });
+
+        let response = self
+            .c
+            .delete_user(request)
+            .await
+            .map_err(rpc_error)
+            .with_context(|| format!("Failed to delete user {}", user))?;
+
+        Ok(response.into_inner().revoked_sessions)
+    }
+
+    /// Issues an invite code valid for ttl_secs, or the server's default if 0
+    pub async fn create_invite(&mut self, ttl_secs: u64) -> Result<CreateInviteResponse> {
+        let request = self.request(CreateInviteRequest { ttl_secs });
+
+        let response = self
+            .c
+            .create_invite(request)
+            .await
+            .map_err(rpc_error)
+            .context("Failed to create invite")?;
+
+        Ok(response.into_inner())
+    }
+
+    /// Locks the user, returning how many of their sessions were revoked
+    pub async fn lock_user(&mut self, user: &str) -> Result<u64> {
+        let request = self.request(LockUserRequest {
+            user: user.to_string(),
+        });
+
+        let response = self
+            .c
+            .lock_user(request)
+            .await
+            .map_err(rpc_error)
+            .with_context(|| format!("Failed to lock user {}", user))?;
+
+        Ok(response.into_inner().revoked_sessions)
+    }
+
+    pub async fn unlock_user(&mut self, user: &str) -> Result<()> {
+        let request = self.request(UnlockUserRequest {
+            user: user.to_string(),
+        });
+
+        self.c
+            .unlock_user(request)
+            .await
+            .map_err(rpc_error)
+            .with_context(|| format!("Failed to unlock user {}", user))?;
+
+        Ok(())
+    }
+
+    /// Ends all of the user's sessions, returning how many were revoked
+    pub async fn revoke_all_sessions(&mut self, user: &str) -> Result<u64> {
+        let request = self.request(RevokeAllSessionsRequest {
+            user: user.to_string(),
+        });
+
+        let response = self
+            .c
+            .revoke_all_sessions(request)
+            .await
+            .map_err(rpc_error)
+            .with_context(|| format!("Failed to revoke sessions of user {}", user))?;
+
+        Ok(response.into_inner().revoked_sessions)
+    }
+}
@@ -1,42 +1,75 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    fmt::Debug,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
 
-use num_bigint::BigInt;
-use tokio::sync::Mutex;
+use anyhow::anyhow;
+use thiserror::Error;
+
+use crate::kdf::KdfParams;
+
+pub mod conformance;
+mod memory;
+mod sqlite;
+
+pub use memory::InMemoryDB;
+pub use sqlite::SqliteDB;
+
+/// Opens the store described by url : `memory` for an [`InMemoryDB`], or `sqlite://<path>` for a [`SqliteDB`]
+/// (i.e. `sqlite:///var/lib/zkp-auth.db` for an absolute path, `sqlite://zkp-auth.db` for a relative one)
+pub fn open_store(url: &str) -> anyhow::Result<Arc<dyn AuthStore>> {
+    if url == "memory" || url == "memory://" {
+        return Ok(Arc::new(InMemoryDB::new()));
+    }
+
+    match url.strip_prefix("sqlite://") {
+        Some(path) if !path.is_empty() => Ok(Arc::new(SqliteDB::open(path)?)),
+        _ => Err(anyhow!(
+            "unsupported database '{}'. expected 'memory' or 'sqlite://<path>'",
+            url
+        )),
+    }
+}
 
 fn generate_uuid() -> String {
     uuid::Uuid::new_v4().to_string()
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct User {
     pub user_id: String,
-    pub y1: BigInt,
-    pub y2: BigInt,
+    pub y1: Vec<u8>,
+    pub y2: Vec<u8>,
+    pub kdf: KdfParams,
+    /// Locked users can't authenticate until an admin unlocks them
+    pub locked: bool,
 }
 
 impl User {
-    pub fn new(user: String, y1: BigInt, y2: BigInt) -> Self {
+    pub fn new(user: String, y1: Vec<u8>, y2: Vec<u8>, kdf: KdfParams) -> Self {
         User {
             user_id: user,
             y1,
             y2,
+            kdf,
+            locked: false,
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct AuthChallenge {
     pub auth_id: String,
     pub user_id: String,
-    pub r1: BigInt,
-    pub r2: BigInt,
-    pub c: BigInt,
-    pub s: Option<BigInt>,
-    pub session_id: Option<String>,
+    pub r1: Vec<u8>,
+    pub r2: Vec<u8>,
+    pub c: Vec<u8>,
+    pub created_at: SystemTime,
 }
 
 impl AuthChallenge {
-    pub fn new(user_id: String, r1: BigInt, r2: BigInt, c: BigInt) -> Self {
+    pub fn new(user_id: String, r1: Vec<u8>, r2: Vec<u8>, c: Vec<u8>) -> Self {
         let auth_id = generate_uuid();
         AuthChallenge {
             auth_id,
@@ -44,59 +77,195 @@ impl AuthChallenge {
             r1,
             r2,
             c,
-            s: None,
-            session_id: None,
+            created_at: SystemTime::now(),
         }
     }
 
-    pub fn finalize_challenge(&mut self, s: BigInt) {
-        let session_id = generate_uuid();
-        self.s = Some(s);
-        self.session_id = Some(session_id);
+    /// Whether the challenge is older than ttl, and can no longer be answered
+    pub fn is_expired(&self, ttl: Duration) -> bool {
+        self.created_at.elapsed().unwrap_or_default() > ttl
     }
 }
 
-/// A simple in-memory database for storing users and challenges
-#[derive(Debug)]
-pub struct InMemoryDB {
-    // Note: Mutex is used instead of RWLock for managing access to User and AuthChallenge.
-    // This decision is based on the expected workflow of the Chaum-Pedersen protocol, where
-    // a single User or AuthChallenge is unlikely to require concurrent read access.
-    // Concurrent writes are infrequent, and the simplicity of Mutex is preferred over the
-    // slight performance advantage of RWLock in read-heavy scenarios.
-    users: HashMap<String, Arc<Mutex<User>>>,
-    challenges: HashMap<String, Arc<Mutex<AuthChallenge>>>,
+/// A record of a user replacing their credentials
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CredentialRotation {
+    pub user_id: String,
+    pub rotated_at: SystemTime,
+}
+
+/// Failed authentication attempts of a user, and whether they are locked out because of them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lockout {
+    pub user_id: String,
+    /// Failed attempts since window_started_at
+    pub failures: u32,
+    pub window_started_at: SystemTime,
+    /// How many times in a row the user has been locked out, so each lockout can last longer than the last
+    pub lockouts: u32,
+    pub locked_until: Option<SystemTime>,
+}
+
+/// Computes a user's new lockout state from their current one. Returning None deletes it
+pub type LockoutUpdate = Box<dyn FnOnce(Option<Lockout>) -> Option<Lockout> + Send>;
+
+/// A single-use code that lets someone register while registration is restricted.
+/// Only a hash of the code is stored, so the codes can't be read back from the store
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Invite {
+    pub code_hash: Vec<u8>,
+    pub created_at: SystemTime,
+    pub expires_at: SystemTime,
 }
 
-impl InMemoryDB {
-    pub fn new() -> Self {
-        InMemoryDB {
-            users: HashMap::new(),
-            challenges: HashMap::new(),
+/// A session minted after a successful authentication.
+/// It ends at `expires_at`, or earlier if it goes unused for longer than the server's idle timeout.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Session {
+    pub session_id: String,
+    pub user_id: String,
+    pub created_at: SystemTime,
+    pub expires_at: SystemTime,
+    pub last_used_at: SystemTime,
+}
+
+impl Session {
+    pub fn new(user_id: String, lifetime: Duration) -> Self {
+        let now = SystemTime::now();
+        Session {
+            session_id: generate_uuid(),
+            user_id,
+            created_at: now,
+            expires_at: now + lifetime,
+            last_used_at: now,
         }
     }
 
-    /// Create a new user
-    pub fn create_user(&mut self, username: String, y1: BigInt, y2: BigInt) {
-        self.users.insert(
-            username.clone(),
-            Arc::new(Mutex::new(User::new(username, y1, y2))),
-        );
+    /// Whether the session has reached its expiry, or has been unused for longer than idle_timeout
+    pub fn is_expired(&self, idle_timeout: Duration) -> bool {
+        SystemTime::now() >= self.expires_at
+            || self.last_used_at.elapsed().unwrap_or_default() > idle_timeout
     }
+}
 
-    /// Create a new challenge
-    pub fn create_challenge(&mut self, challenge: AuthChallenge) {
-        self.challenges
-            .insert(challenge.auth_id.clone(), Arc::new(Mutex::new(challenge)));
-    }
+/// Errors returned by an [`AuthStore`]
+#[derive(Debug, Error)]
+pub enum StoreError {
+    #[error("{0} already exists")]
+    AlreadyExists(String),
+    #[error("{0} does not exist")]
+    NotFound(String),
+    /// The underlying storage failed, i.e. a lost database connection
+    #[error(transparent)]
+    Backend(#[from] anyhow::Error),
+}
+
+pub type StoreResult<T> = std::result::Result<T, StoreError>;
+
+/// Storage for users, authentication challenges, sessions, proof nonces and invites.
+/// Implementations must be safe to share between concurrent requests, and every method must be atomic.
+/// [`conformance::run_all`] checks the behaviour every implementation must have.
+#[tonic::async_trait]
+pub trait AuthStore: Debug + Send + Sync {
+    /// Create a new user. Fails with AlreadyExists if the username is taken
+    async fn create_user(&self, user: User) -> StoreResult<()>;
 
     /// Get a user by username
-    pub async fn get_user(&self, s: &str) -> Option<&Arc<Mutex<User>>> {
-        self.users.get(s)
-    }
+    async fn get_user(&self, user_id: &str) -> StoreResult<Option<User>>;
 
-    /// Get a challenge by its auth_id
-    pub async fn get_challenge(&self, s: &str) -> Option<&Arc<Mutex<AuthChallenge>>> {
-        self.challenges.get(s)
-    }
+    /// List all users
+    async fn list_users(&self) -> StoreResult<Vec<User>>;
+
+    /// Delete a user with their sessions, credential rotations and lockout state, returning whether they existed
+    async fn delete_user(&self, user_id: &str) -> StoreResult<bool>;
+
+    /// Lock or unlock a user, returning whether they exist
+    async fn set_user_locked(&self, user_id: &str, locked: bool) -> StoreResult<bool>;
+
+    /// Replace a user's y1, y2 and KDF parameters with new's, and record the rotation, but only if the stored
+    /// y1 and y2 still match current's. Returns false if they don't, i.e. after a concurrent rotation.
+    /// Fails with NotFound if the user doesn't exist
+    async fn rotate_credentials(
+        &self,
+        current: &User,
+        new: User,
+        rotated_at: SystemTime,
+    ) -> StoreResult<bool>;
+
+    /// List a user's credential rotations, oldest first
+    async fn list_credential_rotations(
+        &self,
+        user_id: &str,
+    ) -> StoreResult<Vec<CredentialRotation>>;
+
+    /// Get a user's lockout state
+    async fn get_lockout(&self, user_id: &str) -> StoreResult<Option<Lockout>>;
+
+    /// Atomically replace a user's lockout state with update's result, returning it.
+    /// Concurrent updates of the same user must not be lost
+    async fn update_lockout(
+        &self,
+        user_id: &str,
+        update: LockoutUpdate,
+    ) -> StoreResult<Option<Lockout>>;
+
+    /// Delete lockout states whose failure window started before the cutoff, returning how many were deleted
+    async fn delete_lockouts_before(&self, cutoff: SystemTime) -> StoreResult<u64>;
+
+    /// Create a new challenge
+    async fn create_challenge(&self, challenge: AuthChallenge) -> StoreResult<()>;
+
+    /// Get a challenge by its auth_id, without consuming it
+    async fn get_challenge(&self, auth_id: &str) -> StoreResult<Option<AuthChallenge>>;
+
+    /// Remove a challenge, returning it if it existed.
+    /// Challenges are single-use : concurrent calls with the same auth_id must return it to at most one caller.
+    async fn take_challenge(&self, auth_id: &str) -> StoreResult<Option<AuthChallenge>>;
+
+    /// Delete challenges created before the cutoff, returning how many were deleted
+    async fn delete_challenges_before(&self, cutoff: SystemTime) -> StoreResult<u64>;
+
+    /// Store a newly minted session
+    async fn create_session(&self, session: Session) -> StoreResult<()>;
+
+    /// Get a session by its session_id
+    async fn get_session(&self, session_id: &str) -> StoreResult<Option<Session>>;
+
+    /// List all of a user's sessions
+    async fn list_sessions(&self, user_id: &str) -> StoreResult<Vec<Session>>;
+
+    /// Record that a session was used at last_used_at, returning whether it existed
+    async fn touch_session(&self, session_id: &str, last_used_at: SystemTime) -> StoreResult<bool>;
+
+    /// Delete a session, returning whether it existed
+    async fn delete_session(&self, session_id: &str) -> StoreResult<bool>;
+
+    /// Delete all of a user's sessions, returning how many were deleted
+    async fn delete_user_sessions(&self, user_id: &str) -> StoreResult<u64>;
+
+    /// Delete sessions that expired before now, or were last used before idle_cutoff, returning how many were deleted
+    async fn delete_expired_sessions(
+        &self,
+        now: SystemTime,
+        idle_cutoff: SystemTime,
+    ) -> StoreResult<u64>;
+
+    /// Record a newly issued proof nonce
+    async fn create_nonce(&self, nonce: &[u8], issued_at: SystemTime) -> StoreResult<()>;
+
+    /// Remove a proof nonce, returning the time it was issued if it existed.
+    /// Nonces are single-use, so this is the only way to read one.
+    async fn take_nonce(&self, nonce: &[u8]) -> StoreResult<Option<SystemTime>>;
+
+    /// Delete nonces issued before the cutoff, returning how many were deleted
+    async fn delete_nonces_before(&self, cutoff: SystemTime) -> StoreResult<u64>;
+
+    /// Record a newly issued invite
+    async fn create_invite(&self, invite: Invite) -> StoreResult<()>;
+
+    /// Remove an invite, returning it if it existed. Invites are single-use, so this is the only way to read one
+    async fn take_invite(&self, code_hash: &[u8]) -> StoreResult<Option<Invite>>;
+
+    /// Delete invites that expired before now, returning how many were deleted
+    async fn delete_expired_invites(&self, now: SystemTime) -> StoreResult<u64>;
 }
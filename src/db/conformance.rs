@@ -0,0 +1,538 @@
+//! Behaviour every [`AuthStore`] implementation must have.
+//!
+//! A new backend should call [`run_all`] from its own tests, with a fresh, empty store :
+//!
+//! ```ignore
+//! #[tokio::test]
+//! async fn test_conformance() {
+//!     zkp_auth::db::conformance::run_all(&MyStore::connect().await).await;
+//! }
+//! ```
+//!
+//! Each check panics with a description of the failure. Checks use random identifiers, so they don't interfere
+//! with each other or with data already in the store.
+
+use std::time::{Duration, SystemTime};
+
+use tokio::join;
+
+use super::{
+    AuthChallenge, AuthStore, CredentialRotation, Invite, Lockout, Session, StoreError, User,
+};
+use crate::kdf::KdfParams;
+
+/// Runs every conformance check against the store
+pub async fn run_all(store: &dyn AuthStore) {
+    users(store).await;
+    manage_users(store).await;
+    rotate_credentials(store).await;
+    lockouts(store).await;
+    challenges(store).await;
+    take_challenge(store).await;
+    delete_challenges(store).await;
+    sessions(store).await;
+    session_expiry(store).await;
+    nonces(store).await;
+    invites(store).await;
+}
+
+fn unique(prefix: &str) -> String {
+    format!("{}-{}", prefix, uuid::Uuid::new_v4())
+}
+
+fn test_user() -> User {
+    User::new(
+        unique("user"),
+        vec![1, 2, 3],
+        vec![4, 5, 6],
+        KdfParams::generate(),
+    )
+}
+
+/// Users round-trip, and a username can only be registered once
+pub async fn users(store: &dyn AuthStore) {
+    let user = test_user();
+    assert_eq!(store.get_user(&user.user_id).await.unwrap(), None);
+
+    store.create_user(user.clone()).await.unwrap();
+    assert_eq!(
+        store.get_user(&user.user_id).await.unwrap(),
+        Some(user.clone())
+    );
+
+    let duplicate = User {
+        y1: vec![7],
+        ..user.clone()
+    };
+    assert!(
+        matches!(
+            store.create_user(duplicate).await,
+            Err(StoreError::AlreadyExists(_))
+        ),
+        "creating an existing user must fail with AlreadyExists"
+    );
+    assert_eq!(
+        store.get_user(&user.user_id).await.unwrap(),
+        Some(user),
+        "a failed create must not overwrite the existing user"
+    );
+}
+
+/// Users can be listed, locked and deleted, and deleting a user deletes their sessions
+pub async fn manage_users(store: &dyn AuthStore) {
+    let user = test_user();
+    assert!(!store.set_user_locked(&user.user_id, true).await.unwrap());
+    assert!(!store.delete_user(&user.user_id).await.unwrap());
+
+    store.create_user(user.clone()).await.unwrap();
+    assert!(store.list_users().await.unwrap().iter().any(|u| u == &user));
+
+    assert!(store.set_user_locked(&user.user_id, true).await.unwrap());
+    assert!(store.get_user(&user.user_id).await.unwrap().unwrap().locked);
+    assert!(store.set_user_locked(&user.user_id, false).await.unwrap());
+    assert_eq!(
+        store.get_user(&user.user_id).await.unwrap(),
+        Some(user.clone())
+    );
+
+    let session = test_session(&user.user_id, 0, 3600);
+    store.create_session(session.clone()).await.unwrap();
+    assert!(store.delete_user(&user.user_id).await.unwrap());
+    assert_eq!(store.get_user(&user.user_id).await.unwrap(), None);
+    assert_eq!(store.get_session(&session.session_id).await.unwrap(), None);
+    assert!(!store
+        .list_users()
+        .await
+        .unwrap()
+        .iter()
+        .any(|u| u.user_id == user.user_id));
+
+    // The username can be registered again
+    store.create_user(user).await.unwrap();
+}
+
+/// Credentials are only replaced if they haven't changed since they were read, and every rotation is recorded
+pub async fn rotate_credentials(store: &dyn AuthStore) {
+    let user = test_user();
+    let new = User {
+        y1: vec![7, 8],
+        y2: vec![9, 10],
+        kdf: KdfParams::generate(),
+        ..user.clone()
+    };
+    assert!(
+        matches!(
+            store
+                .rotate_credentials(&user, new.clone(), whole_seconds_ago(0))
+                .await,
+            Err(StoreError::NotFound(_))
+        ),
+        "rotating a missing user's credentials must fail with NotFound"
+    );
+
+    store.create_user(user.clone()).await.unwrap();
+    assert!(store
+        .list_credential_rotations(&user.user_id)
+        .await
+        .unwrap()
+        .is_empty());
+
+    let rotated_at = whole_seconds_ago(0);
+    assert!(store
+        .rotate_credentials(&user, new.clone(), rotated_at)
+        .await
+        .unwrap());
+    assert_eq!(
+        store.get_user(&user.user_id).await.unwrap(),
+        Some(new.clone())
+    );
+
+    // The stored credentials no longer match the stale copy
+    let other = User {
+        y1: vec![11],
+        ..new.clone()
+    };
+    assert!(
+        !store
+            .rotate_credentials(&user, other, whole_seconds_ago(0))
+            .await
+            .unwrap(),
+        "rotating from stale credentials must not replace them"
+    );
+    assert_eq!(store.get_user(&user.user_id).await.unwrap(), Some(new));
+
+    assert_eq!(
+        store
+            .list_credential_rotations(&user.user_id)
+            .await
+            .unwrap(),
+        vec![CredentialRotation {
+            user_id: user.user_id.clone(),
+            rotated_at
+        }]
+    );
+}
+
+/// Lockout state round-trips through updates, concurrent updates aren't lost, and returning None deletes it
+pub async fn lockouts(store: &dyn AuthStore) {
+    let user_id = unique("user");
+    assert_eq!(store.get_lockout(&user_id).await.unwrap(), None);
+
+    let window_started_at = whole_seconds_ago(10);
+    let increment = |window_started_at: SystemTime| -> super::LockoutUpdate {
+        Box::new(move |current: Option<Lockout>| {
+            let mut lockout = current.unwrap_or(Lockout {
+                user_id: String::new(),
+                failures: 0,
+                window_started_at,
+                lockouts: 0,
+                locked_until: None,
+            });
+            lockout.failures += 1;
+            Some(lockout)
+        })
+    };
+    let (a, b, c, d) = join!(
+        store.update_lockout(&user_id, increment(window_started_at)),
+        store.update_lockout(&user_id, increment(window_started_at)),
+        store.update_lockout(&user_id, increment(window_started_at)),
+        store.update_lockout(&user_id, increment(window_started_at)),
+    );
+    for result in [a, b, c, d] {
+        result.unwrap();
+    }
+    let lockout = store.get_lockout(&user_id).await.unwrap().unwrap();
+    assert_eq!(
+        lockout.failures, 4,
+        "concurrent lockout updates must not be lost"
+    );
+    assert_eq!(lockout.window_started_at, window_started_at);
+
+    let locked_until = Some(whole_seconds_ago(0) + Duration::from_secs(60));
+    let updated = store
+        .update_lockout(
+            &user_id,
+            Box::new(move |current| {
+                Some(Lockout {
+                    lockouts: 3,
+                    locked_until,
+                    ..current.unwrap()
+                })
+            }),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(updated.locked_until, locked_until);
+    assert_eq!(store.get_lockout(&user_id).await.unwrap(), Some(updated));
+
+    assert_eq!(
+        store
+            .update_lockout(&user_id, Box::new(|_| None))
+            .await
+            .unwrap(),
+        None
+    );
+    assert_eq!(store.get_lockout(&user_id).await.unwrap(), None);
+
+    // Deleting a user deletes their lockout state
+    let user = test_user();
+    store.create_user(user.clone()).await.unwrap();
+    store
+        .update_lockout(&user.user_id, increment(window_started_at))
+        .await
+        .unwrap();
+    assert!(store.delete_user(&user.user_id).await.unwrap());
+    assert_eq!(store.get_lockout(&user.user_id).await.unwrap(), None);
+
+    // Only lockouts whose window started before the cutoff are deleted
+    let stale = unique("user");
+    store
+        .update_lockout(&stale, increment(whole_seconds_ago(3600)))
+        .await
+        .unwrap();
+    store
+        .update_lockout(&user_id, increment(window_started_at))
+        .await
+        .unwrap();
+    assert!(
+        store
+            .delete_lockouts_before(whole_seconds_ago(60))
+            .await
+            .unwrap()
+            >= 1
+    );
+    assert_eq!(store.get_lockout(&stale).await.unwrap(), None);
+    assert!(store.get_lockout(&user_id).await.unwrap().is_some());
+}
+
+/// Invites are single-use, unique, and deleted once expired
+pub async fn invites(store: &dyn AuthStore) {
+    let invite = Invite {
+        code_hash: uuid::Uuid::new_v4().as_bytes().to_vec(),
+        created_at: whole_seconds_ago(10),
+        expires_at: whole_seconds_ago(0) + Duration::from_secs(3600),
+    };
+    assert_eq!(store.take_invite(&invite.code_hash).await.unwrap(), None);
+
+    store.create_invite(invite.clone()).await.unwrap();
+    assert!(matches!(
+        store.create_invite(invite.clone()).await,
+        Err(StoreError::AlreadyExists(_))
+    ));
+    assert_eq!(
+        store.take_invite(&invite.code_hash).await.unwrap(),
+        Some(invite.clone())
+    );
+    assert_eq!(
+        store.take_invite(&invite.code_hash).await.unwrap(),
+        None,
+        "an invite must only be taken once"
+    );
+
+    let expired = Invite {
+        code_hash: uuid::Uuid::new_v4().as_bytes().to_vec(),
+        created_at: whole_seconds_ago(7200),
+        expires_at: whole_seconds_ago(3600),
+    };
+    store.create_invite(expired.clone()).await.unwrap();
+    store.create_invite(invite.clone()).await.unwrap();
+
+    let deleted = store
+        .delete_expired_invites(whole_seconds_ago(0))
+        .await
+        .unwrap();
+    assert!(deleted >= 1);
+    assert_eq!(store.take_invite(&expired.code_hash).await.unwrap(), None);
+    assert_eq!(
+        store.take_invite(&invite.code_hash).await.unwrap(),
+        Some(invite)
+    );
+}
+
+/// Rounds a time to whole seconds in the past, so backends storing coarser timestamps still compare equal
+fn whole_seconds_ago(secs_ago: u64) -> SystemTime {
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    SystemTime::UNIX_EPOCH + Duration::from_secs(now - secs_ago)
+}
+
+fn test_challenge(user_id: &str, secs_ago: u64) -> AuthChallenge {
+    AuthChallenge {
+        created_at: whole_seconds_ago(secs_ago),
+        ..AuthChallenge::new(user_id.to_string(), vec![1], vec![2], vec![3])
+    }
+}
+
+/// Challenges round-trip, including their creation time
+pub async fn challenges(store: &dyn AuthStore) {
+    let challenge = test_challenge(&unique("user"), 0);
+    assert_eq!(store.get_challenge(&challenge.auth_id).await.unwrap(), None);
+
+    store.create_challenge(challenge.clone()).await.unwrap();
+    assert_eq!(
+        store.get_challenge(&challenge.auth_id).await.unwrap(),
+        Some(challenge)
+    );
+}
+
+/// A challenge can be taken exactly once, even by concurrent callers
+pub async fn take_challenge(store: &dyn AuthStore) {
+    let challenge = test_challenge(&unique("user"), 0);
+    let auth_id = challenge.auth_id.clone();
+    assert_eq!(store.take_challenge(&auth_id).await.unwrap(), None);
+
+    store.create_challenge(challenge.clone()).await.unwrap();
+    assert_eq!(
+        store.take_challenge(&auth_id).await.unwrap(),
+        Some(challenge.clone())
+    );
+    assert_eq!(
+        store.take_challenge(&auth_id).await.unwrap(),
+        None,
+        "a challenge must only be taken once"
+    );
+    assert_eq!(store.get_challenge(&auth_id).await.unwrap(), None);
+
+    store.create_challenge(challenge).await.unwrap();
+    let (a, b, c, d) = join!(
+        store.take_challenge(&auth_id),
+        store.take_challenge(&auth_id),
+        store.take_challenge(&auth_id),
+        store.take_challenge(&auth_id)
+    );
+    let taken = [a, b, c, d]
+        .into_iter()
+        .filter(|t| t.as_ref().unwrap().is_some())
+        .count();
+    assert_eq!(taken, 1, "concurrent takes must return the challenge once");
+}
+
+/// Only challenges older than the cutoff are deleted
+pub async fn delete_challenges(store: &dyn AuthStore) {
+    let user_id = unique("user");
+    let old = test_challenge(&user_id, 3600);
+    let fresh = test_challenge(&user_id, 0);
+    store.create_challenge(old.clone()).await.unwrap();
+    store.create_challenge(fresh.clone()).await.unwrap();
+
+    let deleted = store
+        .delete_challenges_before(whole_seconds_ago(60))
+        .await
+        .unwrap();
+    assert!(deleted >= 1);
+    assert_eq!(store.get_challenge(&old.auth_id).await.unwrap(), None);
+    assert_eq!(
+        store.get_challenge(&fresh.auth_id).await.unwrap(),
+        Some(fresh)
+    );
+}
+
+/// A session created secs_ago, expiring expires_in seconds from now
+fn test_session(user_id: &str, secs_ago: u64, expires_in: u64) -> Session {
+    let created_at = whole_seconds_ago(secs_ago);
+    Session {
+        created_at,
+        expires_at: whole_seconds_ago(0) + Duration::from_secs(expires_in),
+        last_used_at: created_at,
+        ..Session::new(user_id.to_string(), Duration::ZERO)
+    }
+}
+
+/// Sessions round-trip, can be listed per user and deleted
+pub async fn sessions(store: &dyn AuthStore) {
+    let user_id = unique("user");
+    let mut created = Vec::new();
+    for _ in 0..3 {
+        let session = test_session(&user_id, 0, 3600);
+        store.create_session(session.clone()).await.unwrap();
+        assert_eq!(
+            store.get_session(&session.session_id).await.unwrap(),
+            Some(session.clone())
+        );
+        created.push(session);
+    }
+
+    assert!(
+        matches!(
+            store.create_session(created[0].clone()).await,
+            Err(StoreError::AlreadyExists(_))
+        ),
+        "creating an existing session must fail with AlreadyExists"
+    );
+
+    let mut created: Vec<String> = created.into_iter().map(|s| s.session_id).collect();
+    let mut listed: Vec<String> = store
+        .list_sessions(&user_id)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|s| s.session_id)
+        .collect();
+    listed.sort();
+    created.sort();
+    assert_eq!(listed, created);
+    assert!(store
+        .list_sessions(&unique("user"))
+        .await
+        .unwrap()
+        .is_empty());
+
+    assert!(store.delete_session(&created[0]).await.unwrap());
+    assert!(
+        !store.delete_session(&created[0]).await.unwrap(),
+        "deleting a missing session must return false"
+    );
+    assert_eq!(store.get_session(&created[0]).await.unwrap(), None);
+    assert_eq!(store.list_sessions(&user_id).await.unwrap().len(), 2);
+
+    let last_used_at = whole_seconds_ago(0) + Duration::from_secs(5);
+    assert!(store
+        .touch_session(&created[1], last_used_at)
+        .await
+        .unwrap());
+    assert_eq!(
+        store
+            .get_session(&created[1])
+            .await
+            .unwrap()
+            .unwrap()
+            .last_used_at,
+        last_used_at
+    );
+    assert!(
+        !store
+            .touch_session(&created[0], last_used_at)
+            .await
+            .unwrap(),
+        "touching a missing session must return false"
+    );
+
+    let other = test_session(&unique("user"), 0, 3600);
+    store.create_session(other.clone()).await.unwrap();
+    assert_eq!(store.delete_user_sessions(&user_id).await.unwrap(), 2);
+    assert!(store.list_sessions(&user_id).await.unwrap().is_empty());
+    assert_eq!(
+        store.get_session(&other.session_id).await.unwrap(),
+        Some(other),
+        "deleting a user's sessions must not touch other users' sessions"
+    );
+}
+
+/// Sessions past their expiry, or idle since before the cutoff, are deleted
+pub async fn session_expiry(store: &dyn AuthStore) {
+    let user_id = unique("user");
+    let live = test_session(&user_id, 60, 3600);
+    let expired = test_session(&user_id, 7200, 0);
+    let idle = test_session(&user_id, 3600, 3600);
+    for session in [&live, &expired, &idle] {
+        store.create_session(session.clone()).await.unwrap();
+    }
+
+    let deleted = store
+        .delete_expired_sessions(
+            whole_seconds_ago(0) + Duration::from_secs(1),
+            whole_seconds_ago(600),
+        )
+        .await
+        .unwrap();
+    assert!(deleted >= 2);
+    assert_eq!(store.get_session(&expired.session_id).await.unwrap(), None);
+    assert_eq!(store.get_session(&idle.session_id).await.unwrap(), None);
+    assert_eq!(
+        store.get_session(&live.session_id).await.unwrap(),
+        Some(live)
+    );
+}
+
+/// Nonces are returned with their issue time exactly once, and old ones can be deleted
+pub async fn nonces(store: &dyn AuthStore) {
+    let nonce = uuid::Uuid::new_v4().as_bytes().to_vec();
+    assert_eq!(store.take_nonce(&nonce).await.unwrap(), None);
+
+    let issued_at = whole_seconds_ago(0);
+    store.create_nonce(&nonce, issued_at).await.unwrap();
+    assert_eq!(store.take_nonce(&nonce).await.unwrap(), Some(issued_at));
+    assert_eq!(
+        store.take_nonce(&nonce).await.unwrap(),
+        None,
+        "a nonce must only be taken once"
+    );
+
+    let old = uuid::Uuid::new_v4().as_bytes().to_vec();
+    store
+        .create_nonce(&old, whole_seconds_ago(3600))
+        .await
+        .unwrap();
+    store.create_nonce(&nonce, issued_at).await.unwrap();
+
+    let deleted = store
+        .delete_nonces_before(whole_seconds_ago(60))
+        .await
+        .unwrap();
+    assert!(deleted >= 1);
+    assert_eq!(store.take_nonce(&old).await.unwrap(), None);
+    assert_eq!(store.take_nonce(&nonce).await.unwrap(), Some(issued_at));
+}
@@ -0,0 +1,274 @@
+use std::{collections::HashMap, time::SystemTime};
+
+use tokio::sync::RwLock;
+
+use super::{
+    AuthChallenge, AuthStore, CredentialRotation, Invite, Lockout, LockoutUpdate, Session,
+    StoreError, StoreResult, User,
+};
+
+/// A simple in-memory database for storing users, challenges and sessions.
+/// Everything is lost when the server restarts.
+#[derive(Debug, Default)]
+pub struct InMemoryDB {
+    users: RwLock<HashMap<String, User>>,
+    rotations: RwLock<Vec<CredentialRotation>>,
+    lockouts: RwLock<HashMap<String, Lockout>>,
+    challenges: RwLock<HashMap<String, AuthChallenge>>,
+    sessions: RwLock<HashMap<String, Session>>,
+    // Fiat-Shamir proof nonces, keyed by their hex encoding, with the time they were issued
+    nonces: RwLock<HashMap<String, SystemTime>>,
+    // Invites, keyed by the hex encoding of their code's hash
+    invites: RwLock<HashMap<String, Invite>>,
+}
+
+impl InMemoryDB {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[tonic::async_trait]
+impl AuthStore for InMemoryDB {
+    async fn create_user(&self, user: User) -> StoreResult<()> {
+        let mut users = self.users.write().await;
+        if users.contains_key(&user.user_id) {
+            return Err(StoreError::AlreadyExists(format!("user {}", user.user_id)));
+        }
+
+        users.insert(user.user_id.clone(), user);
+        Ok(())
+    }
+
+    async fn get_user(&self, user_id: &str) -> StoreResult<Option<User>> {
+        Ok(self.users.read().await.get(user_id).cloned())
+    }
+
+    async fn list_users(&self) -> StoreResult<Vec<User>> {
+        Ok(self.users.read().await.values().cloned().collect())
+    }
+
+    async fn delete_user(&self, user_id: &str) -> StoreResult<bool> {
+        // Every lock is held until the user is gone with all their state, so no session or lockout can be
+        // written in between. users is always locked first, as in rotate_credentials
+        let mut users = self.users.write().await;
+        let mut rotations = self.rotations.write().await;
+        let mut lockouts = self.lockouts.write().await;
+        let mut sessions = self.sessions.write().await;
+        if users.remove(user_id).is_none() {
+            return Ok(false);
+        }
+
+        sessions.retain(|_, s| s.user_id != user_id);
+        rotations.retain(|r| r.user_id != user_id);
+        lockouts.remove(user_id);
+        Ok(true)
+    }
+
+    async fn set_user_locked(&self, user_id: &str, locked: bool) -> StoreResult<bool> {
+        match self.users.write().await.get_mut(user_id) {
+            Some(user) => {
+                user.locked = locked;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    async fn rotate_credentials(
+        &self,
+        current: &User,
+        new: User,
+        rotated_at: SystemTime,
+    ) -> StoreResult<bool> {
+        let mut users = self.users.write().await;
+        let user = users
+            .get_mut(&current.user_id)
+            .ok_or_else(|| StoreError::NotFound(format!("user {}", current.user_id)))?;
+        if user.y1 != current.y1 || user.y2 != current.y2 {
+            return Ok(false);
+        }
+
+        user.y1 = new.y1;
+        user.y2 = new.y2;
+        user.kdf = new.kdf;
+        self.rotations.write().await.push(CredentialRotation {
+            user_id: current.user_id.clone(),
+            rotated_at,
+        });
+        Ok(true)
+    }
+
+    async fn list_credential_rotations(
+        &self,
+        user_id: &str,
+    ) -> StoreResult<Vec<CredentialRotation>> {
+        Ok(self
+            .rotations
+            .read()
+            .await
+            .iter()
+            .filter(|r| r.user_id == user_id)
+            .cloned()
+            .collect())
+    }
+
+    async fn get_lockout(&self, user_id: &str) -> StoreResult<Option<Lockout>> {
+        Ok(self.lockouts.read().await.get(user_id).cloned())
+    }
+
+    async fn update_lockout(
+        &self,
+        user_id: &str,
+        update: LockoutUpdate,
+    ) -> StoreResult<Option<Lockout>> {
+        let mut lockouts = self.lockouts.write().await;
+        let lockout = update(lockouts.remove(user_id));
+        if let Some(lockout) = &lockout {
+            lockouts.insert(user_id.to_string(), lockout.clone());
+        }
+        Ok(lockout)
+    }
+
+    async fn delete_lockouts_before(&self, cutoff: SystemTime) -> StoreResult<u64> {
+        let mut lockouts = self.lockouts.write().await;
+        let before = lockouts.len();
+        lockouts.retain(|_, lockout| lockout.window_started_at >= cutoff);
+        Ok((before - lockouts.len()) as u64)
+    }
+
+    async fn create_challenge(&self, challenge: AuthChallenge) -> StoreResult<()> {
+        self.challenges
+            .write()
+            .await
+            .insert(challenge.auth_id.clone(), challenge);
+        Ok(())
+    }
+
+    async fn get_challenge(&self, auth_id: &str) -> StoreResult<Option<AuthChallenge>> {
+        Ok(self.challenges.read().await.get(auth_id).cloned())
+    }
+
+    async fn take_challenge(&self, auth_id: &str) -> StoreResult<Option<AuthChallenge>> {
+        Ok(self.challenges.write().await.remove(auth_id))
+    }
+
+    async fn delete_challenges_before(&self, cutoff: SystemTime) -> StoreResult<u64> {
+        let mut challenges = self.challenges.write().await;
+        let before = challenges.len();
+        challenges.retain(|_, c| c.created_at >= cutoff);
+        Ok((before - challenges.len()) as u64)
+    }
+
+    async fn create_session(&self, session: Session) -> StoreResult<()> {
+        let mut sessions = self.sessions.write().await;
+        if sessions.contains_key(&session.session_id) {
+            return Err(StoreError::AlreadyExists(format!(
+                "session {}",
+                session.session_id
+            )));
+        }
+
+        sessions.insert(session.session_id.clone(), session);
+        Ok(())
+    }
+
+    async fn get_session(&self, session_id: &str) -> StoreResult<Option<Session>> {
+        Ok(self.sessions.read().await.get(session_id).cloned())
+    }
+
+    async fn list_sessions(&self, user_id: &str) -> StoreResult<Vec<Session>> {
+        Ok(self
+            .sessions
+            .read()
+            .await
+            .values()
+            .filter(|s| s.user_id == user_id)
+            .cloned()
+            .collect())
+    }
+
+    async fn touch_session(&self, session_id: &str, last_used_at: SystemTime) -> StoreResult<bool> {
+        match self.sessions.write().await.get_mut(session_id) {
+            Some(session) => {
+                session.last_used_at = last_used_at;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    async fn delete_session(&self, session_id: &str) -> StoreResult<bool> {
+        Ok(self.sessions.write().await.remove(session_id).is_some())
+    }
+
+    async fn delete_user_sessions(&self, user_id: &str) -> StoreResult<u64> {
+        let mut sessions = self.sessions.write().await;
+        let before = sessions.len();
+        sessions.retain(|_, s| s.user_id != user_id);
+        Ok((before - sessions.len()) as u64)
+    }
+
+    async fn delete_expired_sessions(
+        &self,
+        now: SystemTime,
+        idle_cutoff: SystemTime,
+    ) -> StoreResult<u64> {
+        let mut sessions = self.sessions.write().await;
+        let before = sessions.len();
+        sessions.retain(|_, s| s.expires_at >= now && s.last_used_at >= idle_cutoff);
+        Ok((before - sessions.len()) as u64)
+    }
+
+    async fn create_nonce(&self, nonce: &[u8], issued_at: SystemTime) -> StoreResult<()> {
+        self.nonces
+            .write()
+            .await
+            .insert(hex::encode(nonce), issued_at);
+        Ok(())
+    }
+
+    async fn take_nonce(&self, nonce: &[u8]) -> StoreResult<Option<SystemTime>> {
+        Ok(self.nonces.write().await.remove(&hex::encode(nonce)))
+    }
+
+    async fn delete_nonces_before(&self, cutoff: SystemTime) -> StoreResult<u64> {
+        let mut nonces = self.nonces.write().await;
+        let before = nonces.len();
+        nonces.retain(|_, issued_at| *issued_at >= cutoff);
+        Ok((before - nonces.len()) as u64)
+    }
+
+    async fn create_invite(&self, invite: Invite) -> StoreResult<()> {
+        let mut invites = self.invites.write().await;
+        let key = hex::encode(&invite.code_hash);
+        if invites.contains_key(&key) {
+            return Err(StoreError::AlreadyExists("invite".to_string()));
+        }
+
+        invites.insert(key, invite);
+        Ok(())
+    }
+
+    async fn take_invite(&self, code_hash: &[u8]) -> StoreResult<Option<Invite>> {
+        Ok(self.invites.write().await.remove(&hex::encode(code_hash)))
+    }
+
+    async fn delete_expired_invites(&self, now: SystemTime) -> StoreResult<u64> {
+        let mut invites = self.invites.write().await;
+        let before = invites.len();
+        invites.retain(|_, invite| invite.expires_at > now);
+        Ok((before - invites.len()) as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::conformance;
+
+    #[tokio::test]
+    async fn test_conformance() {
+        conformance::run_all(&InMemoryDB::new()).await;
+    }
+}
@@ -0,0 +1,767 @@
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{anyhow, Context, Result};
+use rusqlite::{params, Connection, ErrorCode, OptionalExtension, Row};
+
+use super::{
+    AuthChallenge, AuthStore, CredentialRotation, Invite, Lockout, LockoutUpdate, Session,
+    StoreError, StoreResult, User,
+};
+use crate::kdf::KdfParams;
+
+/// Schema migrations, applied in order. The database's `user_version` records how many have been applied,
+/// so a migration must never be edited once released; add a new one instead.
+const MIGRATIONS: &[&str] = &[
+    // 1 : initial schema
+    "CREATE TABLE users (
+        user_id TEXT PRIMARY KEY NOT NULL,
+        y1 BLOB NOT NULL,
+        y2 BLOB NOT NULL,
+        kdf_salt BLOB NOT NULL,
+        kdf_memory_kib INTEGER NOT NULL,
+        kdf_iterations INTEGER NOT NULL,
+        kdf_parallelism INTEGER NOT NULL
+    );
+    CREATE TABLE challenges (
+        auth_id TEXT PRIMARY KEY NOT NULL,
+        user_id TEXT NOT NULL,
+        r1 BLOB NOT NULL,
+        r2 BLOB NOT NULL,
+        c BLOB NOT NULL,
+        s BLOB,
+        session_id TEXT
+    );
+    CREATE INDEX challenges_user_id ON challenges (user_id);
+    CREATE TABLE sessions (
+        session_id TEXT PRIMARY KEY NOT NULL,
+        user_id TEXT NOT NULL,
+        created_at INTEGER NOT NULL
+    );
+    CREATE INDEX sessions_user_id ON sessions (user_id);
+    CREATE TABLE nonces (
+        nonce BLOB PRIMARY KEY NOT NULL,
+        issued_at INTEGER NOT NULL
+    );",
+    // 2 : challenges are single-use and expire. Outstanding challenges are short-lived, so are simply dropped
+    "DROP TABLE challenges;
+    CREATE TABLE challenges (
+        auth_id TEXT PRIMARY KEY NOT NULL,
+        user_id TEXT NOT NULL,
+        r1 BLOB NOT NULL,
+        r2 BLOB NOT NULL,
+        c BLOB NOT NULL,
+        created_at INTEGER NOT NULL
+    );
+    CREATE INDEX challenges_user_id ON challenges (user_id);
+    CREATE INDEX challenges_created_at ON challenges (created_at);
+    CREATE INDEX nonces_issued_at ON nonces (issued_at);",
+    // 3 : sessions expire. Existing sessions had no expiry, so are treated as already expired
+    "ALTER TABLE sessions ADD COLUMN expires_at INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE sessions ADD COLUMN last_used_at INTEGER NOT NULL DEFAULT 0;
+    CREATE INDEX sessions_expires_at ON sessions (expires_at);
+    CREATE INDEX sessions_last_used_at ON sessions (last_used_at);",
+    // 4 : credential rotations
+    "CREATE TABLE credential_rotations (
+        user_id TEXT NOT NULL,
+        rotated_at INTEGER NOT NULL
+    );
+    CREATE INDEX credential_rotations_user_id ON credential_rotations (user_id);",
+    // 5 : users can be locked by an admin
+    "ALTER TABLE users ADD COLUMN locked INTEGER NOT NULL DEFAULT 0;",
+    // 6 : brute-force lockouts
+    "CREATE TABLE lockouts (
+        user_id TEXT PRIMARY KEY NOT NULL,
+        failures INTEGER NOT NULL,
+        window_started_at INTEGER NOT NULL,
+        lockouts INTEGER NOT NULL,
+        locked_until INTEGER
+    );",
+    // 7 : invites to register
+    "CREATE TABLE invites (
+        code_hash BLOB PRIMARY KEY NOT NULL,
+        created_at INTEGER NOT NULL,
+        expires_at INTEGER NOT NULL
+    );
+    CREATE INDEX invites_expires_at ON invites (expires_at);",
+    // 8 : stale lockouts are deleted
+    "CREATE INDEX lockouts_window_started_at ON lockouts (window_started_at);",
+];
+
+/// Stores users, challenges and sessions in a SQLite database file, so they survive a server restart.
+/// Timestamps are stored as nanoseconds since the Unix epoch.
+#[derive(Debug, Clone)]
+pub struct SqliteDB {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl SqliteDB {
+    /// Opens (creating if needed) the database at path, and brings its schema up to date
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let conn = Connection::open(path)
+            .with_context(|| format!("failed to open SQLite database {}", path.display()))?;
+        Self::from_connection(conn)
+    }
+
+    /// Opens a private, in-memory SQLite database
+    pub fn open_in_memory() -> Result<Self> {
+        Self::from_connection(Connection::open_in_memory()?)
+    }
+
+    fn from_connection(mut conn: Connection) -> Result<Self> {
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        migrate(&mut conn)?;
+
+        Ok(SqliteDB {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    /// Runs f on the connection from the blocking thread pool, so queries don't stall the async runtime
+    async fn call<T, F>(&self, f: F) -> StoreResult<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> StoreResult<T> + Send + 'static,
+    {
+        let conn = self.conn.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut conn = conn
+                .lock()
+                .map_err(|_| anyhow!("SQLite connection mutex poisoned"))?;
+            f(&mut conn)
+        })
+        .await
+        .map_err(|e| anyhow!("SQLite task failed: {}", e))?
+    }
+}
+
+/// Applies every migration the database hasn't seen yet, each in its own transaction
+fn migrate(conn: &mut Connection) -> Result<()> {
+    let version: usize = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    if version > MIGRATIONS.len() {
+        return Err(anyhow!(
+            "database schema version {} is newer than this server supports ({})",
+            version,
+            MIGRATIONS.len()
+        ));
+    }
+
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration)
+            .with_context(|| format!("failed to apply migration {}", i + 1))?;
+        tx.pragma_update(None, "user_version", i + 1)?;
+        tx.commit()?;
+        log::info!("applied SQLite migration {}", i + 1);
+    }
+
+    Ok(())
+}
+
+impl From<rusqlite::Error> for StoreError {
+    fn from(e: rusqlite::Error) -> Self {
+        StoreError::Backend(e.into())
+    }
+}
+
+fn to_nanos(t: SystemTime) -> i64 {
+    t.duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos() as i64
+}
+
+fn from_nanos(nanos: i64) -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_nanos(nanos as u64)
+}
+
+fn is_constraint_violation(e: &rusqlite::Error) -> bool {
+    matches!(e, rusqlite::Error::SqliteFailure(f, _) if f.code == ErrorCode::ConstraintViolation)
+}
+
+fn user_from_row(row: &Row) -> rusqlite::Result<User> {
+    Ok(User {
+        user_id: row.get("user_id")?,
+        y1: row.get("y1")?,
+        y2: row.get("y2")?,
+        kdf: KdfParams {
+            salt: row.get("kdf_salt")?,
+            memory_kib: row.get("kdf_memory_kib")?,
+            iterations: row.get("kdf_iterations")?,
+            parallelism: row.get("kdf_parallelism")?,
+        },
+        locked: row.get("locked")?,
+    })
+}
+
+fn challenge_from_row(row: &Row) -> rusqlite::Result<AuthChallenge> {
+    Ok(AuthChallenge {
+        auth_id: row.get("auth_id")?,
+        user_id: row.get("user_id")?,
+        r1: row.get("r1")?,
+        r2: row.get("r2")?,
+        c: row.get("c")?,
+        created_at: from_nanos(row.get("created_at")?),
+    })
+}
+
+fn lockout_from_row(row: &Row) -> rusqlite::Result<Lockout> {
+    let locked_until: Option<i64> = row.get("locked_until")?;
+    Ok(Lockout {
+        user_id: row.get("user_id")?,
+        failures: row.get("failures")?,
+        window_started_at: from_nanos(row.get("window_started_at")?),
+        lockouts: row.get("lockouts")?,
+        locked_until: locked_until.map(from_nanos),
+    })
+}
+
+fn session_from_row(row: &Row) -> rusqlite::Result<Session> {
+    Ok(Session {
+        session_id: row.get("session_id")?,
+        user_id: row.get("user_id")?,
+        created_at: from_nanos(row.get("created_at")?),
+        expires_at: from_nanos(row.get("expires_at")?),
+        last_used_at: from_nanos(row.get("last_used_at")?),
+    })
+}
+
+#[tonic::async_trait]
+impl AuthStore for SqliteDB {
+    async fn create_user(&self, user: User) -> StoreResult<()> {
+        self.call(move |conn| {
+            conn.execute(
+                "INSERT INTO users (user_id, y1, y2, kdf_salt, kdf_memory_kib, kdf_iterations, kdf_parallelism, locked)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                params![
+                    user.user_id,
+                    user.y1,
+                    user.y2,
+                    user.kdf.salt,
+                    user.kdf.memory_kib,
+                    user.kdf.iterations,
+                    user.kdf.parallelism,
+                    user.locked
+                ],
+            )
+            .map_err(|e| match is_constraint_violation(&e) {
+                true => StoreError::AlreadyExists(format!("user {}", user.user_id)),
+                false => e.into(),
+            })?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_user(&self, user_id: &str) -> StoreResult<Option<User>> {
+        let user_id = user_id.to_string();
+        self.call(move |conn| {
+            Ok(conn
+                .query_row(
+                    "SELECT * FROM users WHERE user_id = ?1",
+                    [user_id],
+                    user_from_row,
+                )
+                .optional()?)
+        })
+        .await
+    }
+
+    async fn list_users(&self) -> StoreResult<Vec<User>> {
+        self.call(move |conn| {
+            let mut stmt = conn.prepare("SELECT * FROM users ORDER BY user_id")?;
+            let users = stmt
+                .query_map([], user_from_row)?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            Ok(users)
+        })
+        .await
+    }
+
+    async fn delete_user(&self, user_id: &str) -> StoreResult<bool> {
+        let user_id = user_id.to_string();
+        self.call(move |conn| {
+            let tx = conn.transaction()?;
+            let deleted = tx.execute("DELETE FROM users WHERE user_id = ?1", [&user_id])?;
+            tx.execute("DELETE FROM sessions WHERE user_id = ?1", [&user_id])?;
+            tx.execute(
+                "DELETE FROM credential_rotations WHERE user_id = ?1",
+                [&user_id],
+            )?;
+            tx.execute("DELETE FROM lockouts WHERE user_id = ?1", [&user_id])?;
+            tx.commit()?;
+            Ok(deleted > 0)
+        })
+        .await
+    }
+
+    async fn set_user_locked(&self, user_id: &str, locked: bool) -> StoreResult<bool> {
+        let user_id = user_id.to_string();
+        self.call(move |conn| {
+            let updated = conn.execute(
+                "UPDATE users SET locked = ?1 WHERE user_id = ?2",
+                params![locked, user_id],
+            )?;
+            Ok(updated > 0)
+        })
+        .await
+    }
+
+    async fn rotate_credentials(
+        &self,
+        current: &User,
+        new: User,
+        rotated_at: SystemTime,
+    ) -> StoreResult<bool> {
+        let current = current.clone();
+        self.call(move |conn| {
+            let tx = conn.transaction()?;
+            let updated = tx.execute(
+                "UPDATE users SET y1 = ?1, y2 = ?2, kdf_salt = ?3, kdf_memory_kib = ?4, kdf_iterations = ?5,
+                 kdf_parallelism = ?6 WHERE user_id = ?7 AND y1 = ?8 AND y2 = ?9",
+                params![
+                    new.y1,
+                    new.y2,
+                    new.kdf.salt,
+                    new.kdf.memory_kib,
+                    new.kdf.iterations,
+                    new.kdf.parallelism,
+                    current.user_id,
+                    current.y1,
+                    current.y2
+                ],
+            )?;
+            if updated == 0 {
+                let exists = tx
+                    .query_row(
+                        "SELECT 1 FROM users WHERE user_id = ?1",
+                        [&current.user_id],
+                        |_| Ok(()),
+                    )
+                    .optional()?
+                    .is_some();
+                return match exists {
+                    true => Ok(false),
+                    false => Err(StoreError::NotFound(format!("user {}", current.user_id))),
+                };
+            }
+
+            tx.execute(
+                "INSERT INTO credential_rotations (user_id, rotated_at) VALUES (?1, ?2)",
+                params![current.user_id, to_nanos(rotated_at)],
+            )?;
+            tx.commit()?;
+            Ok(true)
+        })
+        .await
+    }
+
+    async fn list_credential_rotations(
+        &self,
+        user_id: &str,
+    ) -> StoreResult<Vec<CredentialRotation>> {
+        let user_id = user_id.to_string();
+        self.call(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT user_id, rotated_at FROM credential_rotations WHERE user_id = ?1
+                 ORDER BY rotated_at, rowid",
+            )?;
+            let rotations = stmt
+                .query_map([user_id], |row| {
+                    Ok(CredentialRotation {
+                        user_id: row.get(0)?,
+                        rotated_at: from_nanos(row.get(1)?),
+                    })
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            Ok(rotations)
+        })
+        .await
+    }
+
+    async fn get_lockout(&self, user_id: &str) -> StoreResult<Option<Lockout>> {
+        let user_id = user_id.to_string();
+        self.call(move |conn| {
+            Ok(conn
+                .query_row(
+                    "SELECT * FROM lockouts WHERE user_id = ?1",
+                    [user_id],
+                    lockout_from_row,
+                )
+                .optional()?)
+        })
+        .await
+    }
+
+    async fn update_lockout(
+        &self,
+        user_id: &str,
+        update: LockoutUpdate,
+    ) -> StoreResult<Option<Lockout>> {
+        let user_id = user_id.to_string();
+        self.call(move |conn| {
+            // The connection is held exclusively for the whole transaction, so updates can't interleave
+            let tx = conn.transaction()?;
+            let current = tx
+                .query_row(
+                    "SELECT * FROM lockouts WHERE user_id = ?1",
+                    [&user_id],
+                    lockout_from_row,
+                )
+                .optional()?;
+
+            let lockout = update(current);
+            match &lockout {
+                Some(l) => tx.execute(
+                    "INSERT OR REPLACE INTO lockouts (user_id, failures, window_started_at, lockouts, locked_until)
+                     VALUES (?1, ?2, ?3, ?4, ?5)",
+                    params![
+                        user_id,
+                        l.failures,
+                        to_nanos(l.window_started_at),
+                        l.lockouts,
+                        l.locked_until.map(to_nanos)
+                    ],
+                )?,
+                None => tx.execute("DELETE FROM lockouts WHERE user_id = ?1", [&user_id])?,
+            };
+            tx.commit()?;
+            Ok(lockout)
+        })
+        .await
+    }
+
+    async fn delete_lockouts_before(&self, cutoff: SystemTime) -> StoreResult<u64> {
+        self.call(move |conn| {
+            let deleted = conn.execute(
+                "DELETE FROM lockouts WHERE window_started_at < ?1",
+                [to_nanos(cutoff)],
+            )?;
+            Ok(deleted as u64)
+        })
+        .await
+    }
+
+    async fn create_challenge(&self, challenge: AuthChallenge) -> StoreResult<()> {
+        self.call(move |conn| {
+            conn.execute(
+                "INSERT INTO challenges (auth_id, user_id, r1, r2, c, created_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    challenge.auth_id,
+                    challenge.user_id,
+                    challenge.r1,
+                    challenge.r2,
+                    challenge.c,
+                    to_nanos(challenge.created_at)
+                ],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_challenge(&self, auth_id: &str) -> StoreResult<Option<AuthChallenge>> {
+        let auth_id = auth_id.to_string();
+        self.call(move |conn| {
+            Ok(conn
+                .query_row(
+                    "SELECT * FROM challenges WHERE auth_id = ?1",
+                    [auth_id],
+                    challenge_from_row,
+                )
+                .optional()?)
+        })
+        .await
+    }
+
+    async fn take_challenge(&self, auth_id: &str) -> StoreResult<Option<AuthChallenge>> {
+        let auth_id = auth_id.to_string();
+        self.call(move |conn| {
+            Ok(conn
+                .query_row(
+                    "DELETE FROM challenges WHERE auth_id = ?1 RETURNING *",
+                    [auth_id],
+                    challenge_from_row,
+                )
+                .optional()?)
+        })
+        .await
+    }
+
+    async fn delete_challenges_before(&self, cutoff: SystemTime) -> StoreResult<u64> {
+        self.call(move |conn| {
+            let deleted = conn.execute(
+                "DELETE FROM challenges WHERE created_at < ?1",
+                [to_nanos(cutoff)],
+            )?;
+            Ok(deleted as u64)
+        })
+        .await
+    }
+
+    async fn create_session(&self, session: Session) -> StoreResult<()> {
+        self.call(move |conn| {
+            conn.execute(
+                "INSERT INTO sessions (session_id, user_id, created_at, expires_at, last_used_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    session.session_id,
+                    session.user_id,
+                    to_nanos(session.created_at),
+                    to_nanos(session.expires_at),
+                    to_nanos(session.last_used_at)
+                ],
+            )
+            .map_err(|e| match is_constraint_violation(&e) {
+                true => StoreError::AlreadyExists(format!("session {}", session.session_id)),
+                false => e.into(),
+            })?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_session(&self, session_id: &str) -> StoreResult<Option<Session>> {
+        let session_id = session_id.to_string();
+        self.call(move |conn| {
+            Ok(conn
+                .query_row(
+                    "SELECT * FROM sessions WHERE session_id = ?1",
+                    [session_id],
+                    session_from_row,
+                )
+                .optional()?)
+        })
+        .await
+    }
+
+    async fn list_sessions(&self, user_id: &str) -> StoreResult<Vec<Session>> {
+        let user_id = user_id.to_string();
+        self.call(move |conn| {
+            let mut stmt = conn.prepare("SELECT * FROM sessions WHERE user_id = ?1")?;
+            let sessions = stmt
+                .query_map([user_id], session_from_row)?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            Ok(sessions)
+        })
+        .await
+    }
+
+    async fn touch_session(&self, session_id: &str, last_used_at: SystemTime) -> StoreResult<bool> {
+        let session_id = session_id.to_string();
+        self.call(move |conn| {
+            let updated = conn.execute(
+                "UPDATE sessions SET last_used_at = ?1 WHERE session_id = ?2",
+                params![to_nanos(last_used_at), session_id],
+            )?;
+            Ok(updated > 0)
+        })
+        .await
+    }
+
+    async fn delete_session(&self, session_id: &str) -> StoreResult<bool> {
+        let session_id = session_id.to_string();
+        self.call(move |conn| {
+            let deleted =
+                conn.execute("DELETE FROM sessions WHERE session_id = ?1", [session_id])?;
+            Ok(deleted > 0)
+        })
+        .await
+    }
+
+    async fn delete_user_sessions(&self, user_id: &str) -> StoreResult<u64> {
+        let user_id = user_id.to_string();
+        self.call(move |conn| {
+            let deleted = conn.execute("DELETE FROM sessions WHERE user_id = ?1", [user_id])?;
+            Ok(deleted as u64)
+        })
+        .await
+    }
+
+    async fn delete_expired_sessions(
+        &self,
+        now: SystemTime,
+        idle_cutoff: SystemTime,
+    ) -> StoreResult<u64> {
+        self.call(move |conn| {
+            let deleted = conn.execute(
+                "DELETE FROM sessions WHERE expires_at < ?1 OR last_used_at < ?2",
+                [to_nanos(now), to_nanos(idle_cutoff)],
+            )?;
+            Ok(deleted as u64)
+        })
+        .await
+    }
+
+    async fn create_nonce(&self, nonce: &[u8], issued_at: SystemTime) -> StoreResult<()> {
+        let nonce = nonce.to_vec();
+        self.call(move |conn| {
+            conn.execute(
+                "INSERT OR REPLACE INTO nonces (nonce, issued_at) VALUES (?1, ?2)",
+                params![nonce, to_nanos(issued_at)],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn take_nonce(&self, nonce: &[u8]) -> StoreResult<Option<SystemTime>> {
+        let nonce = nonce.to_vec();
+        self.call(move |conn| {
+            let issued_at: Option<i64> = conn
+                .query_row(
+                    "DELETE FROM nonces WHERE nonce = ?1 RETURNING issued_at",
+                    [nonce],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            Ok(issued_at.map(from_nanos))
+        })
+        .await
+    }
+
+    async fn delete_nonces_before(&self, cutoff: SystemTime) -> StoreResult<u64> {
+        self.call(move |conn| {
+            let deleted = conn.execute(
+                "DELETE FROM nonces WHERE issued_at < ?1",
+                [to_nanos(cutoff)],
+            )?;
+            Ok(deleted as u64)
+        })
+        .await
+    }
+
+    async fn create_invite(&self, invite: Invite) -> StoreResult<()> {
+        self.call(move |conn| {
+            conn.execute(
+                "INSERT INTO invites (code_hash, created_at, expires_at) VALUES (?1, ?2, ?3)",
+                params![
+                    invite.code_hash,
+                    to_nanos(invite.created_at),
+                    to_nanos(invite.expires_at)
+                ],
+            )
+            .map_err(|e| match is_constraint_violation(&e) {
+                true => StoreError::AlreadyExists("invite".to_string()),
+                false => e.into(),
+            })?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn take_invite(&self, code_hash: &[u8]) -> StoreResult<Option<Invite>> {
+        let code_hash = code_hash.to_vec();
+        self.call(move |conn| {
+            Ok(conn
+                .query_row(
+                    "DELETE FROM invites WHERE code_hash = ?1 RETURNING *",
+                    [code_hash],
+                    |row| {
+                        Ok(Invite {
+                            code_hash: row.get("code_hash")?,
+                            created_at: from_nanos(row.get("created_at")?),
+                            expires_at: from_nanos(row.get("expires_at")?),
+                        })
+                    },
+                )
+                .optional()?)
+        })
+        .await
+    }
+
+    async fn delete_expired_invites(&self, now: SystemTime) -> StoreResult<u64> {
+        self.call(move |conn| {
+            let deleted = conn.execute(
+                "DELETE FROM invites WHERE expires_at <= ?1",
+                [to_nanos(now)],
+            )?;
+            Ok(deleted as u64)
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::conformance;
+
+    #[tokio::test]
+    async fn test_conformance() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = SqliteDB::open(dir.path().join("zkp-auth.db")).unwrap();
+        conformance::run_all(&db).await;
+
+        conformance::run_all(&SqliteDB::open_in_memory().unwrap()).await;
+    }
+
+    #[tokio::test]
+    async fn test_persists_across_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("zkp-auth.db");
+
+        let user = User::new(
+            "alice".to_string(),
+            vec![1; 33],
+            vec![2; 33],
+            KdfParams::generate(),
+        );
+        let session = Session::new("alice".to_string(), Duration::from_secs(3600));
+        {
+            let db = SqliteDB::open(&path).unwrap();
+            db.create_user(user.clone()).await.unwrap();
+
+            db.create_session(session.clone()).await.unwrap();
+        }
+
+        let db = SqliteDB::open(&path).unwrap();
+        assert_eq!(db.get_user("alice").await.unwrap(), Some(user));
+        assert_eq!(
+            db.get_session(&session.session_id).await.unwrap(),
+            Some(session)
+        );
+    }
+
+    #[test]
+    fn test_migrations() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("zkp-auth.db");
+        SqliteDB::open(&path).unwrap();
+
+        let conn = Connection::open(&path).unwrap();
+        let version: usize = conn
+            .pragma_query_value(None, "user_version", |row| row.get(0))
+            .unwrap();
+        assert_eq!(version, MIGRATIONS.len());
+
+        let indexes: Vec<String> = conn
+            .prepare(
+                "SELECT name FROM sqlite_master WHERE type = 'index' AND name NOT LIKE 'sqlite_%'",
+            )
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert!(indexes.contains(&"challenges_user_id".to_string()));
+        assert!(indexes.contains(&"challenges_created_at".to_string()));
+        assert!(indexes.contains(&"sessions_user_id".to_string()));
+        assert!(indexes.contains(&"sessions_expires_at".to_string()));
+        assert!(indexes.contains(&"credential_rotations_user_id".to_string()));
+        assert!(indexes.contains(&"invites_expires_at".to_string()));
+
+        // Reopening an up to date database is a no-op, while a database from a newer server is refused
+        drop(conn);
+        SqliteDB::open(&path).unwrap();
+        Connection::open(&path)
+            .unwrap()
+            .pragma_update(None, "user_version", MIGRATIONS.len() + 1)
+            .unwrap();
+        assert!(SqliteDB::open(&path).is_err());
+    }
+}
@@ -0,0 +1,250 @@
+use std::{collections::HashMap, time::Duration};
+
+use thiserror::Error;
+use tonic::{Code, Status};
+use tonic_types::{ErrorDetails, StatusExt};
+
+/// Domain of the `google.rpc.ErrorInfo` details attached to [`ZkpAuthError`] statuses
+pub const ERROR_DOMAIN: &str = "zkp-auth";
+
+/// Errors the Auth service reports to clients. Each is sent with a specific gRPC code, and a `google.rpc.ErrorInfo`
+/// detail whose reason names the variant, so clients can recover it with [`ZkpAuthError::from_status`] instead of
+/// parsing messages
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ZkpAuthError {
+    /// NotFound
+    #[error("user {user} does not exist. please register first")]
+    UserNotFound { user: String },
+    /// NotFound : the challenge was never issued, has been answered already, or was deleted after expiring
+    #[error("challenge {auth_id} does not exist or has already been used. please create an authentication challenge first")]
+    ChallengeNotFound { auth_id: String },
+    /// FailedPrecondition
+    #[error("challenge {auth_id} has expired. please create a new authentication challenge")]
+    ChallengeExpired { auth_id: String },
+    /// FailedPrecondition : the proof nonce was never issued, or has been used already
+    #[error("unknown or already used proof nonce. please request a new one")]
+    NonceNotFound,
+    /// FailedPrecondition
+    #[error("proof nonce has expired. please request a new one")]
+    NonceExpired,
+    /// Unauthenticated : the proof was well formed, but doesn't show knowledge of the user's secret
+    #[error("authentication failed for user {user}")]
+    ProofInvalid { user: String },
+    /// InvalidArgument : the registration proof doesn't show knowledge of the secret behind the credentials
+    #[error("registration proof for user {user} does not verify")]
+    RegistrationProofInvalid { user: String },
+    /// ResourceExhausted
+    #[error(
+        "too many authentication attempts for this {scope}. please retry in {} seconds",
+        .retry_after.as_secs_f64().ceil()
+    )]
+    RateLimited {
+        /// Which limit was exceeded : "user", "address" or "server"
+        scope: String,
+        retry_after: Duration,
+    },
+    /// PermissionDenied if an admin locked the user, or ResourceExhausted if failed attempts locked them out
+    /// for `retry_after`
+    #[error("{}", locked_message(.user, .retry_after))]
+    Locked {
+        user: String,
+        retry_after: Option<Duration>,
+    },
+    /// InvalidArgument : `what` doesn't decode, or isn't a valid group element or scalar
+    #[error("malformed {what}: {detail}")]
+    BadEncoding { what: String, detail: String },
+}
+
+fn locked_message(user: &str, retry_after: &Option<Duration>) -> String {
+    match retry_after {
+        None => format!("user {} is locked", user),
+        Some(d) => format!(
+            "user {} is locked out after too many failed attempts. please retry in {} seconds",
+            user,
+            d.as_secs_f64().ceil()
+        ),
+    }
+}
+
+impl ZkpAuthError {
+    pub fn bad_encoding(what: &str, e: impl std::fmt::Display) -> Self {
+        ZkpAuthError::BadEncoding {
+            what: what.to_string(),
+            detail: e.to_string(),
+        }
+    }
+
+    /// The gRPC code the error is sent with
+    pub fn code(&self) -> Code {
+        match self {
+            ZkpAuthError::UserNotFound { .. } => Code::NotFound,
+            ZkpAuthError::ChallengeNotFound { .. } => Code::NotFound,
+            ZkpAuthError::ChallengeExpired { .. } => Code::FailedPrecondition,
+            ZkpAuthError::NonceNotFound | ZkpAuthError::NonceExpired => Code::FailedPrecondition,
+            ZkpAuthError::ProofInvalid { .. } => Code::Unauthenticated,
+            ZkpAuthError::RegistrationProofInvalid { .. } => Code::InvalidArgument,
+            ZkpAuthError::RateLimited { .. } => Code::ResourceExhausted,
+            ZkpAuthError::Locked {
+                retry_after: None, ..
+            } => Code::PermissionDenied,
+            ZkpAuthError::Locked { .. } => Code::ResourceExhausted,
+            ZkpAuthError::BadEncoding { .. } => Code::InvalidArgument,
+        }
+    }
+
+    /// The `google.rpc.ErrorInfo` reason identifying the variant
+    pub fn reason(&self) -> &'static str {
+        match self {
+            ZkpAuthError::UserNotFound { .. } => "USER_NOT_FOUND",
+            ZkpAuthError::ChallengeNotFound { .. } => "CHALLENGE_NOT_FOUND",
+            ZkpAuthError::ChallengeExpired { .. } => "CHALLENGE_EXPIRED",
+            ZkpAuthError::NonceNotFound => "NONCE_NOT_FOUND",
+            ZkpAuthError::NonceExpired => "NONCE_EXPIRED",
+            ZkpAuthError::ProofInvalid { .. } => "PROOF_INVALID",
+            ZkpAuthError::RegistrationProofInvalid { .. } => "REGISTRATION_PROOF_INVALID",
+            ZkpAuthError::RateLimited { .. } => "RATE_LIMITED",
+            ZkpAuthError::Locked { .. } => "LOCKED",
+            ZkpAuthError::BadEncoding { .. } => "BAD_ENCODING",
+        }
+    }
+
+    fn metadata(&self) -> HashMap<String, String> {
+        let retry_after_secs = |d: &Duration| d.as_secs_f64().ceil().to_string();
+        let entries = match self {
+            ZkpAuthError::UserNotFound { user }
+            | ZkpAuthError::ProofInvalid { user }
+            | ZkpAuthError::RegistrationProofInvalid { user } => vec![("user", user.clone())],
+            ZkpAuthError::ChallengeNotFound { auth_id }
+            | ZkpAuthError::ChallengeExpired { auth_id } => vec![("auth_id", auth_id.clone())],
+            ZkpAuthError::NonceNotFound | ZkpAuthError::NonceExpired => vec![],
+            ZkpAuthError::RateLimited { scope, retry_after } => vec![
+                ("scope", scope.clone()),
+                ("retry_after_secs", retry_after_secs(retry_after)),
+            ],
+            ZkpAuthError::Locked { user, retry_after } => {
+                let mut entries = vec![("user", user.clone())];
+                if let Some(d) = retry_after {
+                    entries.push(("retry_after_secs", retry_after_secs(d)));
+                }
+                entries
+            }
+            ZkpAuthError::BadEncoding { what, detail } => {
+                vec![("what", what.clone()), ("detail", detail.clone())]
+            }
+        };
+        entries
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v))
+            .collect()
+    }
+
+    /// Recovers the error from a status sent by the server, or None if it carries no ZkpAuthError details
+    pub fn from_status(status: &Status) -> Option<Self> {
+        let info = status.get_details_error_info()?;
+        if info.domain != ERROR_DOMAIN {
+            return None;
+        }
+
+        let get = |key: &str| info.metadata.get(key).cloned().unwrap_or_default();
+        let retry_after = || {
+            info.metadata
+                .get("retry_after_secs")
+                .and_then(|s| s.parse().ok())
+                .map(Duration::from_secs)
+        };
+        let e = match info.reason.as_str() {
+            "USER_NOT_FOUND" => ZkpAuthError::UserNotFound { user: get("user") },
+            "CHALLENGE_NOT_FOUND" => ZkpAuthError::ChallengeNotFound {
+                auth_id: get("auth_id"),
+            },
+            "CHALLENGE_EXPIRED" => ZkpAuthError::ChallengeExpired {
+                auth_id: get("auth_id"),
+            },
+            "NONCE_NOT_FOUND" => ZkpAuthError::NonceNotFound,
+            "NONCE_EXPIRED" => ZkpAuthError::NonceExpired,
+            "PROOF_INVALID" => ZkpAuthError::ProofInvalid { user: get("user") },
+            "REGISTRATION_PROOF_INVALID" => {
+                ZkpAuthError::RegistrationProofInvalid { user: get("user") }
+            }
+            "RATE_LIMITED" => ZkpAuthError::RateLimited {
+                scope: get("scope"),
+                retry_after: retry_after().unwrap_or_default(),
+            },
+            "LOCKED" => ZkpAuthError::Locked {
+                user: get("user"),
+                retry_after: retry_after(),
+            },
+            "BAD_ENCODING" => ZkpAuthError::BadEncoding {
+                what: get("what"),
+                detail: get("detail"),
+            },
+            _ => return None,
+        };
+        Some(e)
+    }
+}
+
+impl From<ZkpAuthError> for Status {
+    fn from(e: ZkpAuthError) -> Self {
+        let details = ErrorDetails::with_error_info(e.reason(), ERROR_DOMAIN, e.metadata());
+        Status::with_error_details(e.code(), e.to_string(), details)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_round_trip() {
+        let errors = [
+            ZkpAuthError::UserNotFound {
+                user: "alice".to_string(),
+            },
+            ZkpAuthError::ChallengeNotFound {
+                auth_id: "1234".to_string(),
+            },
+            ZkpAuthError::ChallengeExpired {
+                auth_id: "1234".to_string(),
+            },
+            ZkpAuthError::NonceNotFound,
+            ZkpAuthError::NonceExpired,
+            ZkpAuthError::ProofInvalid {
+                user: "alice".to_string(),
+            },
+            ZkpAuthError::RegistrationProofInvalid {
+                user: "alice".to_string(),
+            },
+            ZkpAuthError::RateLimited {
+                scope: "address".to_string(),
+                retry_after: Duration::from_secs(30),
+            },
+            ZkpAuthError::Locked {
+                user: "alice".to_string(),
+                retry_after: None,
+            },
+            ZkpAuthError::Locked {
+                user: "alice".to_string(),
+                retry_after: Some(Duration::from_secs(60)),
+            },
+            ZkpAuthError::bad_encoding("proof", "scalar is out of range"),
+        ];
+        for e in errors {
+            let status = Status::from(e.clone());
+            assert_eq!(status.code(), e.code());
+            assert_eq!(status.message(), e.to_string());
+            assert_eq!(ZkpAuthError::from_status(&status), Some(e));
+        }
+
+        assert_eq!(
+            ZkpAuthError::from_status(&Status::not_found("no details")),
+            None
+        );
+        let other_domain = Status::with_error_details(
+            Code::NotFound,
+            "other service",
+            ErrorDetails::with_error_info("USER_NOT_FOUND", "example.com", HashMap::new()),
+        );
+        assert_eq!(ZkpAuthError::from_status(&other_domain), None);
+    }
+}
@@ -0,0 +1,573 @@
+use std::{fmt::Debug, str::FromStr};
+
+use anyhow::{anyhow, Result};
+use num::BigInt;
+use sha2::{Digest, Sha256, Sha512};
+
+mod modp;
+mod p256;
+mod ristretto;
+
+pub use self::p256::P256;
+pub use self::ristretto::Ristretto255;
+
+/// Domain separation tag used by the default [`Group::hash_to_scalar`]
+const HASH_TO_SCALAR_DOMAIN: &[u8] = b"zkp-auth/chaum-pedersen/hash-to-scalar/v1";
+
+/// Domain separation tag used by the default [`Group::fingerprint`]
+const FINGERPRINT_DOMAIN: &[u8] = b"zkp-auth/params-fingerprint/v1";
+
+/// A prime order group the Chaum-Pedersen protocol can be run over.
+/// The group is written multiplicatively : `exp` is exponentiation (scalar multiplication on a curve)
+/// and `mul` is the group operation (point addition on a curve).
+pub trait Group: Clone + Debug + Send + Sync + 'static {
+    type Element: Clone + Debug + PartialEq + Send + Sync;
+    type Scalar: Clone + Debug + PartialEq + Send + Sync;
+
+    /// Human readable name of the group
+    fn name(&self) -> String;
+
+    /// Canonical encoding of the group's public parameters, bound into every Fiat-Shamir challenge
+    fn encode_params(&self) -> Vec<u8>;
+
+    /// Order of the group, q
+    fn order(&self) -> BigInt;
+
+    /// First generator, G
+    fn g(&self) -> Self::Element;
+
+    /// Second generator, H. Nobody may know log_G(H)
+    fn h(&self) -> Self::Element;
+
+    /// Compute base^e
+    fn exp(&self, base: &Self::Element, e: &Self::Scalar) -> Self::Element;
+
+    /// Compute a * b
+    fn mul(&self, a: &Self::Element, b: &Self::Element) -> Self::Element;
+
+    /// Compute a * b mod q
+    fn scalar_mul(&self, a: &Self::Scalar, b: &Self::Scalar) -> Self::Scalar;
+
+    /// Compute a - b mod q
+    fn scalar_sub(&self, a: &Self::Scalar, b: &Self::Scalar) -> Self::Scalar;
+
+    /// Reduces an arbitrary integer mod q. The integer goes through BigInt arithmetic, so this is only for public
+    /// values such as challenges
+    fn scalar_from_bigint(&self, n: &BigInt) -> Self::Scalar;
+
+    /// Reduces unsigned big-endian bytes mod q, in constant time with respect to their value.
+    /// Secrets, such as KDF output, enter the group this way
+    fn scalar_from_bytes(&self, bytes: &[u8]) -> Self::Scalar;
+
+    /// Draws a uniformly random scalar in [0, q)
+    fn random_scalar(&self) -> Self::Scalar;
+
+    /// Canonical, fixed-length encoding of an element. Every element has exactly one encoding
+    fn encode_element(&self, e: &Self::Element) -> Vec<u8>;
+
+    /// Decodes an element received from a peer. Fails unless it is a valid element of the prime order group
+    /// other than the identity
+    fn decode_element(&self, bytes: &[u8]) -> Result<Self::Element>;
+
+    /// Canonical, fixed-length encoding of a scalar in [0, q). Every scalar has exactly one encoding
+    fn encode_scalar(&self, s: &Self::Scalar) -> Vec<u8>;
+
+    /// Decodes a scalar received from a peer. Fails unless it lies in [0, q)
+    fn decode_scalar(&self, bytes: &[u8]) -> Result<Self::Scalar>;
+
+    /// SHA-256 of the group's name and parameters. Two groups share a fingerprint only if they are the same group
+    fn fingerprint(&self) -> [u8; 32] {
+        let name = self.name();
+        let params = self.encode_params();
+
+        let mut hasher = Sha256::new();
+        hasher.update(FINGERPRINT_DOMAIN);
+        for part in [name.as_bytes(), &params] {
+            hasher.update((part.len() as u64).to_be_bytes());
+            hasher.update(part);
+        }
+        hasher.finalize().into()
+    }
+
+    /// Hashes the length-prefixed parts to a scalar.
+    /// SHA-512 is expanded to |q| + 128 bits before reducing, so the result is statistically close to uniform.
+    fn hash_to_scalar(&self, parts: &[&[u8]]) -> Self::Scalar {
+        let order = self.order();
+        let len = (order.bits() as usize + 128).div_ceil(8);
+
+        let mut prefix = Sha512::new();
+        prefix.update(HASH_TO_SCALAR_DOMAIN);
+        for part in parts {
+            prefix.update((part.len() as u64).to_be_bytes());
+            prefix.update(part);
+        }
+
+        let mut expanded = Vec::with_capacity(len + 64);
+        for block in 0u32.. {
+            if expanded.len() >= len {
+                break;
+            }
+            let mut hasher = prefix.clone();
+            hasher.update(block.to_be_bytes());
+            expanded.extend_from_slice(&hasher.finalize());
+        }
+
+        self.scalar_from_bytes(&expanded[..len])
+    }
+}
+
+/// How the prover obtains the challenge c
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProofMode {
+    /// The server picks c after receiving r1 and r2 (three round-trips including registration)
+    Interactive,
+    /// The client derives c by hashing, and sends (r1, r2, s) in a single Prove call
+    FiatShamir,
+    /// As FiatShamir, but the client sends the smaller (c, s) and the server recomputes r1 and r2
+    FiatShamirCompact,
+}
+
+impl ProofMode {
+    pub const ALL: [ProofMode; 3] = [
+        ProofMode::Interactive,
+        ProofMode::FiatShamir,
+        ProofMode::FiatShamirCompact,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ProofMode::Interactive => "interactive",
+            ProofMode::FiatShamir => "fiat-shamir",
+            ProofMode::FiatShamirCompact => "fiat-shamir-compact",
+        }
+    }
+}
+
+impl FromStr for ProofMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        ProofMode::ALL
+            .into_iter()
+            .find(|m| m.name().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| anyhow!("unknown proof mode '{}'", s))
+    }
+}
+
+/// Public inputs, besides y1 and y2, that a non-interactive proof is bound to
+#[derive(Debug, Clone, Copy)]
+pub struct ProofContext<'a> {
+    /// What the proof is for, i.e. b"login". Keeps proofs made for one purpose from being accepted for another
+    pub purpose: &'a [u8],
+    pub user: &'a str,
+    /// Single-use nonce issued by the server, preventing replay
+    pub nonce: &'a [u8],
+    /// Arbitrary application context chosen by the client
+    pub context: &'a [u8],
+}
+
+/// A non-interactive Chaum-Pedersen proof
+#[derive(Debug, Clone)]
+pub struct FiatShamirProof<G: Group> {
+    pub r1: G::Element,
+    pub r2: G::Element,
+    pub c: G::Scalar,
+    pub s: G::Scalar,
+}
+
+/// Chaum-Pedersen prover and verifier, over any [`Group`]
+#[derive(Debug, Clone)]
+pub struct ChaumPedersen<G: Group> {
+    group: G,
+}
+
+impl<G: Group> ChaumPedersen<G> {
+    pub fn new(group: G) -> Self {
+        ChaumPedersen { group }
+    }
+
+    pub fn group(&self) -> &G {
+        &self.group
+    }
+
+    /// Compute y1 and y2 : y1 = g^x, y2 = h^x
+    pub fn y1_y2(&self, x: &G::Scalar) -> (G::Element, G::Element) {
+        let g = &self.group;
+        (g.exp(&g.g(), x), g.exp(&g.h(), x))
+    }
+
+    /// Draws a fresh random nonce k
+    pub fn random_nonce(&self) -> G::Scalar {
+        self.group.random_scalar()
+    }
+
+    /// Compute r1 and r2 : r1 = g^k, r2 = h^k
+    pub fn r1_r2(&self, k: &G::Scalar) -> (G::Element, G::Element) {
+        self.y1_y2(k)
+    }
+
+    /// Compute s : s = k - c * x mod q
+    pub fn s(&self, k: &G::Scalar, c: &G::Scalar, x: &G::Scalar) -> G::Scalar {
+        let g = &self.group;
+        g.scalar_sub(k, &g.scalar_mul(c, x))
+    }
+
+    /// Checks the prover's response : r1 == g^s * y1^c and r2 == h^s * y2^c
+    pub fn verify(
+        &self,
+        y1: &G::Element,
+        y2: &G::Element,
+        r1: &G::Element,
+        r2: &G::Element,
+        c: &G::Scalar,
+        s: &G::Scalar,
+    ) -> bool {
+        let g = &self.group;
+        let r1_prime = g.mul(&g.exp(&g.g(), s), &g.exp(y1, c));
+        let r2_prime = g.mul(&g.exp(&g.h(), s), &g.exp(y2, c));
+
+        r1 == &r1_prime && r2 == &r2_prime
+    }
+
+    /// Compute the Fiat-Shamir challenge : c = H(params, purpose, user, y1, y2, r1, r2, nonce, context)
+    pub fn fiat_shamir_challenge(
+        &self,
+        ctx: &ProofContext,
+        y1: &G::Element,
+        y2: &G::Element,
+        r1: &G::Element,
+        r2: &G::Element,
+    ) -> G::Scalar {
+        let g = &self.group;
+        g.hash_to_scalar(&[
+            &g.encode_params(),
+            ctx.purpose,
+            ctx.user.as_bytes(),
+            &g.encode_element(y1),
+            &g.encode_element(y2),
+            &g.encode_element(r1),
+            &g.encode_element(r2),
+            ctx.nonce,
+            ctx.context,
+        ])
+    }
+
+    /// Produces a non-interactive proof of knowledge of x, bound to ctx
+    pub fn prove_non_interactive(&self, x: &G::Scalar, ctx: &ProofContext) -> FiatShamirProof<G> {
+        let (y1, y2) = self.y1_y2(x);
+        let k = self.random_nonce();
+        let (r1, r2) = self.r1_r2(&k);
+        let c = self.fiat_shamir_challenge(ctx, &y1, &y2, &r1, &r2);
+        let s = self.s(&k, &c, x);
+
+        FiatShamirProof { r1, r2, c, s }
+    }
+
+    /// Checks a non-interactive proof sent as (r1, r2, s), by recomputing c
+    pub fn verify_non_interactive(
+        &self,
+        ctx: &ProofContext,
+        y1: &G::Element,
+        y2: &G::Element,
+        r1: &G::Element,
+        r2: &G::Element,
+        s: &G::Scalar,
+    ) -> bool {
+        let c = self.fiat_shamir_challenge(ctx, y1, y2, r1, r2);
+        self.verify(y1, y2, r1, r2, &c, s)
+    }
+
+    /// Checks a non-interactive proof sent as (c, s), by recomputing r1 and r2 and checking they hash to c.
+    /// Returns the recomputed (r1, r2) if the proof is valid
+    pub fn verify_non_interactive_compact(
+        &self,
+        ctx: &ProofContext,
+        y1: &G::Element,
+        y2: &G::Element,
+        c: &G::Scalar,
+        s: &G::Scalar,
+    ) -> Option<(G::Element, G::Element)> {
+        let g = &self.group;
+        let r1 = g.mul(&g.exp(&g.g(), s), &g.exp(y1, c));
+        let r2 = g.mul(&g.exp(&g.h(), s), &g.exp(y2, c));
+
+        (&self.fiat_shamir_challenge(ctx, y1, y2, &r1, &r2) == c).then_some((r1, r2))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chaum_pedersen::{self, ChaumPedersenParams, NamedGroup};
+    use num::bigint::Sign;
+
+    fn check_protocol<G: Group>(group: G) {
+        let cp = ChaumPedersen::new(group.clone());
+        let x = group.scalar_from_bigint(&BigInt::from(64));
+        let (y1, y2) = cp.y1_y2(&x);
+
+        let k = cp.random_nonce();
+        let (r1, r2) = cp.r1_r2(&k);
+        let c = group.random_scalar();
+        let s = cp.s(&k, &c, &x);
+        assert!(
+            cp.verify(&y1, &y2, &r1, &r2, &c, &s),
+            "group {}",
+            group.name()
+        );
+
+        let wrong_x = group.scalar_from_bigint(&BigInt::from(65));
+        let s = cp.s(&k, &c, &wrong_x);
+        assert!(
+            !cp.verify(&y1, &y2, &r1, &r2, &c, &s),
+            "group {}",
+            group.name()
+        );
+    }
+
+    fn check_encoding_round_trip<G: Group>(group: G) {
+        let s = group.random_scalar();
+        assert_eq!(group.decode_scalar(&group.encode_scalar(&s)).unwrap(), s);
+
+        let e = group.exp(&group.g(), &s);
+        assert_eq!(group.decode_element(&group.encode_element(&e)).unwrap(), e);
+    }
+
+    #[test]
+    fn test_protocol_all_groups() {
+        check_protocol(NamedGroup::Rfc5114_2048_256.params());
+        check_protocol(Ristretto255);
+        check_protocol(P256);
+    }
+
+    #[test]
+    fn test_encoding_round_trip_all_groups() {
+        check_encoding_round_trip(NamedGroup::Rfc5114_2048_256.params());
+        check_encoding_round_trip(Ristretto255);
+        check_encoding_round_trip(P256);
+    }
+
+    fn check_rejects_identity<G: Group>(group: G) {
+        let identity = group.exp(&group.g(), &group.scalar_from_bigint(&BigInt::from(0)));
+        assert!(
+            group
+                .decode_element(&group.encode_element(&identity))
+                .is_err(),
+            "group {}",
+            group.name()
+        );
+        assert!(group.decode_element(&[]).is_err(), "group {}", group.name());
+    }
+
+    #[test]
+    fn test_rejects_identity_all_groups() {
+        check_rejects_identity(NamedGroup::Rfc5114_2048_256.params());
+        check_rejects_identity(Ristretto255);
+        check_rejects_identity(P256);
+    }
+
+    #[test]
+    fn test_modp_rejects_invalid_elements() {
+        for params in [
+            NamedGroup::Rfc5114_2048_256.params(),
+            NamedGroup::Ffdhe2048.params(),
+        ] {
+            let one = BigInt::from(1);
+            let p_minus_one = &params.p - &one;
+            // 2^q has order dividing (p-1)/q, so it lies in a small subgroup whenever it isn't 1
+            let small_order = BigInt::from(2).modpow(&params.q, &params.p);
+            let out_of_range = [
+                BigInt::from(0),
+                one.clone(),
+                params.p.clone(),
+                &params.p + 2,
+            ];
+            for v in out_of_range.iter().chain([&p_minus_one, &small_order]) {
+                let bytes = chaum_pedersen::encode_fixed(v, params.element_len());
+                assert!(params.decode_element(&bytes).is_err(), "{}", v);
+            }
+
+            let encode_scalar = |n: &BigInt| chaum_pedersen::encode_fixed(n, params.scalar_len());
+            assert!(params.decode_scalar(&encode_scalar(&params.q)).is_err());
+            let max = &params.q - &one;
+            assert_eq!(
+                params.decode_scalar(&encode_scalar(&max)).unwrap(),
+                params.scalar_from_bigint(&max)
+            );
+        }
+    }
+
+    /// Checks that the encodings of the scalar reduced from `n`, and of G raised to it, round trip, have the
+    /// group's fixed length, and stop decoding once padded or truncated
+    fn check_canonical_encoding<G: Group>(group: &G, n: &BigInt) {
+        let s = group.scalar_from_bigint(n);
+        let e = group.exp(&group.g(), &s);
+        let zero = group.scalar_from_bigint(&BigInt::from(0));
+
+        let encoded = group.encode_scalar(&s);
+        assert_eq!(encoded.len(), group.encode_scalar(&zero).len());
+        assert_eq!(group.decode_scalar(&encoded).unwrap(), s);
+        assert!(group.decode_scalar(&[&[0], &encoded[..]].concat()).is_err());
+        assert!(group.decode_scalar(&encoded[1..]).is_err());
+
+        if s == zero {
+            return;
+        }
+        let encoded = group.encode_element(&e);
+        assert_eq!(encoded.len(), group.encode_element(&group.g()).len());
+        assert_eq!(group.decode_element(&encoded).unwrap(), e);
+        assert!(group
+            .decode_element(&[&[0], &encoded[..]].concat())
+            .is_err());
+        assert!(group.decode_element(&encoded[1..]).is_err());
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn prop_encode_fixed_round_trip(
+            bytes in proptest::collection::vec(proptest::num::u8::ANY, 0..64),
+            pad in 0usize..8,
+        ) {
+            let n = BigInt::from_bytes_be(Sign::Plus, &bytes);
+            let len = bytes.len() + pad;
+            let encoded = chaum_pedersen::encode_fixed(&n, len);
+            assert_eq!(encoded.len(), len);
+            assert_eq!(chaum_pedersen::decode_fixed(&encoded, len).unwrap(), n);
+            assert!(chaum_pedersen::decode_fixed(&encoded, len + 1).is_err());
+        }
+
+        #[test]
+        fn prop_curve_encodings_are_canonical(
+            bytes in proptest::collection::vec(proptest::num::u8::ANY, 0..48),
+        ) {
+            let n = BigInt::from_bytes_be(Sign::Plus, &bytes);
+            check_canonical_encoding(&Ristretto255, &n);
+            check_canonical_encoding(&P256, &n);
+        }
+    }
+
+    proptest::proptest! {
+        // Exponentiation in a 2048-bit group is slow in debug builds
+        #![proptest_config(proptest::test_runner::Config::with_cases(16))]
+
+        #[test]
+        fn prop_modp_encodings_are_canonical(
+            bytes in proptest::collection::vec(proptest::num::u8::ANY, 0..48),
+        ) {
+            let n = BigInt::from_bytes_be(Sign::Plus, &bytes);
+            check_canonical_encoding(&NamedGroup::Rfc5114_2048_256.params(), &n);
+        }
+    }
+
+    #[test]
+    fn test_hash_to_scalar() {
+        let a = Ristretto255.hash_to_scalar(&[b"a", b"bc"]);
+        assert_eq!(a, Ristretto255.hash_to_scalar(&[b"a", b"bc"]));
+        // parts are length prefixed, so moving bytes between them changes the result
+        assert_ne!(a, Ristretto255.hash_to_scalar(&[b"ab", b"c"]));
+
+        let params = NamedGroup::Rfc5114_2048_256.params();
+        assert!(params.hash_to_scalar(&[b"a"]).to_bigint() < params.q);
+    }
+
+    #[test]
+    fn test_modp_names() {
+        assert_eq!(NamedGroup::Ffdhe2048.params().name(), "ffdhe2048");
+        assert_eq!(NamedGroup::Modp2048.params().name(), "modp2048");
+
+        // Other parameters never take a named group's name, whatever their size
+        let modp2048 = NamedGroup::Modp2048.params();
+        let custom = ChaumPedersenParams::new_with_derived_h(
+            modp2048.p,
+            modp2048.q,
+            modp2048.g,
+            b"custom".to_vec(),
+        );
+        assert_eq!(custom.name(), "modp-custom-2048");
+        assert_ne!(
+            custom.fingerprint(),
+            NamedGroup::Modp2048.params().fingerprint()
+        );
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let modp = NamedGroup::Rfc5114_2048_256.params();
+        assert_eq!(modp.fingerprint(), modp.clone().fingerprint());
+
+        let fingerprints = [
+            modp.fingerprint(),
+            NamedGroup::Ffdhe2048.params().fingerprint(),
+            Ristretto255.fingerprint(),
+            P256.fingerprint(),
+        ];
+        for (i, a) in fingerprints.iter().enumerate() {
+            for b in &fingerprints[i + 1..] {
+                assert_ne!(a, b);
+            }
+        }
+    }
+
+    fn check_non_interactive<G: Group>(group: G) {
+        let cp = ChaumPedersen::new(group.clone());
+        let x = group.scalar_from_bigint(&BigInt::from(64));
+        let (y1, y2) = cp.y1_y2(&x);
+        let ctx = ProofContext {
+            purpose: b"login",
+            user: "alice",
+            nonce: b"nonce",
+            context: b"",
+        };
+
+        let proof = cp.prove_non_interactive(&x, &ctx);
+        assert!(cp.verify_non_interactive(&ctx, &y1, &y2, &proof.r1, &proof.r2, &proof.s));
+        assert_eq!(
+            cp.verify_non_interactive_compact(&ctx, &y1, &y2, &proof.c, &proof.s),
+            Some((proof.r1.clone(), proof.r2.clone()))
+        );
+
+        // the proof is bound to every part of the context
+        for other in [
+            ProofContext {
+                purpose: b"register",
+                ..ctx
+            },
+            ProofContext { user: "bob", ..ctx },
+            ProofContext {
+                nonce: b"other",
+                ..ctx
+            },
+            ProofContext {
+                context: b"device",
+                ..ctx
+            },
+        ] {
+            assert!(!cp.verify_non_interactive(&other, &y1, &y2, &proof.r1, &proof.r2, &proof.s));
+            assert!(cp
+                .verify_non_interactive_compact(&other, &y1, &y2, &proof.c, &proof.s)
+                .is_none());
+        }
+
+        // and to the statement
+        let (y1_other, y2_other) = cp.y1_y2(&group.scalar_from_bigint(&BigInt::from(65)));
+        assert!(
+            !cp.verify_non_interactive(&ctx, &y1_other, &y2_other, &proof.r1, &proof.r2, &proof.s)
+        );
+    }
+
+    #[test]
+    fn test_non_interactive_all_groups() {
+        check_non_interactive(NamedGroup::Rfc5114_2048_256.params());
+        check_non_interactive(Ristretto255);
+        check_non_interactive(P256);
+    }
+
+    #[test]
+    fn test_proof_mode_from_str() {
+        assert_eq!(
+            ProofMode::from_str("fiat-shamir").unwrap(),
+            ProofMode::FiatShamir
+        );
+        assert!(ProofMode::from_str("other").is_err());
+    }
+}
@@ -0,0 +1,105 @@
+use anyhow::{anyhow, Result};
+use num::{BigInt, Integer, One, Signed};
+
+use super::Group;
+use crate::chaum_pedersen::{self, ct, ChaumPedersenParams, FixedUint, NamedGroup};
+
+/// The order-q subgroup of Z_p*. Scalars, which may be secret, are [`FixedUint`]s and only go through the
+/// constant-time arithmetic in `chaum_pedersen::ct`; multiplying public elements uses plain BigInt arithmetic.
+/// Elements and scalars are encoded as unsigned big-endian integers, left-padded to |p| and |q| bytes respectively
+/// (see [`chaum_pedersen::encode_fixed`]). Decoding rejects any other length, elements outside 1 < v < p or the
+/// order-q subgroup, and scalars outside [0, q).
+impl Group for ChaumPedersenParams {
+    type Element = BigInt;
+    type Scalar = FixedUint;
+
+    /// The named group's name, or `modp-custom-<bits of p>`, which no named group uses
+    fn name(&self) -> String {
+        NamedGroup::of(self).map_or_else(
+            || format!("modp-custom-{}", self.p.bits()),
+            |g| g.name().to_string(),
+        )
+    }
+
+    fn encode_params(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        for n in [&self.p, &self.q, &self.g, &self.h] {
+            let (_, bytes) = n.to_bytes_be();
+            out.extend_from_slice(&(bytes.len() as u64).to_be_bytes());
+            out.extend_from_slice(&bytes);
+        }
+        out
+    }
+
+    fn order(&self) -> BigInt {
+        self.q.clone()
+    }
+
+    fn g(&self) -> BigInt {
+        self.g.clone()
+    }
+
+    fn h(&self) -> BigInt {
+        self.h.clone()
+    }
+
+    fn exp(&self, base: &BigInt, e: &FixedUint) -> BigInt {
+        ct::modpow(base, e, &self.p)
+    }
+
+    fn mul(&self, a: &BigInt, b: &BigInt) -> BigInt {
+        (a * b).mod_floor(&self.p)
+    }
+
+    fn scalar_mul(&self, a: &FixedUint, b: &FixedUint) -> FixedUint {
+        ct::mul_mod(a, b, &self.q)
+    }
+
+    fn scalar_sub(&self, a: &FixedUint, b: &FixedUint) -> FixedUint {
+        ct::sub_mod(a, b, &self.q)
+    }
+
+    fn scalar_from_bigint(&self, n: &BigInt) -> FixedUint {
+        FixedUint::from_bigint(n, &self.q)
+    }
+
+    fn scalar_from_bytes(&self, bytes: &[u8]) -> FixedUint {
+        FixedUint::from_be_bytes(bytes, &self.q)
+    }
+
+    fn random_scalar(&self) -> FixedUint {
+        chaum_pedersen::random_nonce(&self.q)
+    }
+
+    fn encode_element(&self, e: &BigInt) -> Vec<u8> {
+        chaum_pedersen::encode_fixed(e, self.element_len())
+    }
+
+    fn decode_element(&self, bytes: &[u8]) -> Result<BigInt> {
+        let v = chaum_pedersen::decode_fixed(bytes, self.element_len())?;
+        if !v.is_positive() || v.is_one() || v >= self.p {
+            return Err(anyhow!(
+                "element is out of range, it must satisfy 1 < v < p"
+            ));
+        }
+        // Elements outside the subgroup would leak x mod the order of their own subgroup (small subgroup attack)
+        if !self.in_subgroup(&v) {
+            return Err(anyhow!("element is not in the order-q subgroup"));
+        }
+        Ok(v)
+    }
+
+    fn encode_scalar(&self, s: &FixedUint) -> Vec<u8> {
+        s.to_be_bytes(self.scalar_len())
+    }
+
+    fn decode_scalar(&self, bytes: &[u8]) -> Result<FixedUint> {
+        let s = chaum_pedersen::decode_fixed(bytes, self.scalar_len())?;
+        if s >= self.q {
+            return Err(anyhow!(
+                "scalar is out of range, it must satisfy 0 <= s < q"
+            ));
+        }
+        Ok(FixedUint::from_bigint(&s, &self.q))
+    }
+}
@@ -0,0 +1,164 @@
+use anyhow::{anyhow, Result};
+use num::{BigInt, Integer, Num};
+use p256::{
+    elliptic_curve::{
+        hash2curve::{ExpandMsgXmd, GroupDigest},
+        sec1::{FromEncodedPoint, ToEncodedPoint},
+        Field, PrimeField,
+    },
+    AffinePoint, EncodedPoint, FieldBytes, NistP256, ProjectivePoint, Scalar,
+};
+use rand_core::OsRng;
+use sha2::Sha256;
+
+use super::Group;
+use crate::chaum_pedersen::FixedUint;
+
+/// Label hashed onto the curve to obtain the second generator H
+const H_LABEL: &[u8] = b"zkp-auth/chaum-pedersen/generator/v1";
+
+/// Domain separation tag for hashing onto P-256 (RFC 9380)
+const HASH_DST: &[u8] = b"zkp-auth-P256_XMD:SHA-256_SSWU_RO_";
+
+/// Size in bytes of a SEC1 compressed point : a 0x02 or 0x03 tag, then the x coordinate
+const COMPRESSED_LEN: usize = 33;
+
+/// Order of the P-256 base point
+const ORDER: &str = "ffffffff00000000ffffffffffffffffbce6faada7179e84f3b9cac2fc632551";
+
+/// The NIST P-256 curve group.
+/// G is the standard base point, and H is H_LABEL hashed onto the curve, so nobody knows log_G(H).
+/// Elements are 33-byte SEC1 compressed points, and scalars are 32-byte big-endian integers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct P256;
+
+impl Group for P256 {
+    type Element = ProjectivePoint;
+    type Scalar = Scalar;
+
+    fn name(&self) -> String {
+        "p256".to_string()
+    }
+
+    fn encode_params(&self) -> Vec<u8> {
+        self.name().into_bytes()
+    }
+
+    fn order(&self) -> BigInt {
+        BigInt::from_str_radix(ORDER, 16).unwrap()
+    }
+
+    fn g(&self) -> ProjectivePoint {
+        ProjectivePoint::GENERATOR
+    }
+
+    fn h(&self) -> ProjectivePoint {
+        NistP256::hash_from_bytes::<ExpandMsgXmd<Sha256>>(&[H_LABEL], &[HASH_DST])
+            .expect("hash to curve with a fixed, valid DST cannot fail")
+    }
+
+    fn exp(&self, base: &ProjectivePoint, e: &Scalar) -> ProjectivePoint {
+        base * e
+    }
+
+    fn mul(&self, a: &ProjectivePoint, b: &ProjectivePoint) -> ProjectivePoint {
+        a + b
+    }
+
+    fn scalar_mul(&self, a: &Scalar, b: &Scalar) -> Scalar {
+        a * b
+    }
+
+    fn scalar_sub(&self, a: &Scalar, b: &Scalar) -> Scalar {
+        a - b
+    }
+
+    fn scalar_from_bigint(&self, n: &BigInt) -> Scalar {
+        let (_, bytes) = n.mod_floor(&self.order()).to_bytes_be();
+        let mut padded = vec![0u8; 32 - bytes.len()];
+        padded.extend_from_slice(&bytes);
+        Scalar::from_repr(FieldBytes::clone_from_slice(&padded)).unwrap()
+    }
+
+    fn scalar_from_bytes(&self, bytes: &[u8]) -> Scalar {
+        let reduced = FixedUint::from_be_bytes(bytes, &self.order()).to_be_bytes(32);
+        Scalar::from_repr(FieldBytes::clone_from_slice(&reduced)).unwrap()
+    }
+
+    fn random_scalar(&self) -> Scalar {
+        Scalar::random(&mut OsRng)
+    }
+
+    fn encode_element(&self, e: &ProjectivePoint) -> Vec<u8> {
+        e.to_affine().to_encoded_point(true).as_bytes().to_vec()
+    }
+
+    fn decode_element(&self, bytes: &[u8]) -> Result<ProjectivePoint> {
+        // SEC1 also allows uncompressed points, which would give each point a second encoding
+        if bytes.len() != COMPRESSED_LEN || !matches!(bytes[0], 0x02 | 0x03) {
+            return Err(anyhow!(
+                "p256 points must be {} byte SEC1 compressed encodings",
+                COMPRESSED_LEN
+            ));
+        }
+        let encoded = EncodedPoint::from_bytes(bytes)?;
+        let point = Option::<AffinePoint>::from(AffinePoint::from_encoded_point(&encoded))
+            .map(ProjectivePoint::from)
+            .ok_or_else(|| anyhow!("invalid p256 point"))?;
+        // P-256 has prime order, so every other point on the curve generates the whole group
+        if point == ProjectivePoint::IDENTITY {
+            return Err(anyhow!("p256 point is the identity"));
+        }
+        Ok(point)
+    }
+
+    fn encode_scalar(&self, s: &Scalar) -> Vec<u8> {
+        s.to_repr().to_vec()
+    }
+
+    fn decode_scalar(&self, bytes: &[u8]) -> Result<Scalar> {
+        if bytes.len() != 32 {
+            return Err(anyhow!("p256 scalars must be 32 bytes"));
+        }
+        Option::from(Scalar::from_repr(FieldBytes::clone_from_slice(bytes)))
+            .ok_or_else(|| anyhow!("non-canonical p256 scalar"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encoding_sizes() {
+        let e = P256.exp(&P256.g(), &P256.random_scalar());
+        assert_eq!(P256.encode_element(&e).len(), COMPRESSED_LEN);
+        assert_eq!(P256.encode_scalar(&Scalar::ONE).len(), 32);
+    }
+
+    #[test]
+    fn test_rejects_uncompressed_points() {
+        let e = P256.exp(&P256.g(), &P256.random_scalar());
+        let uncompressed = e.to_affine().to_encoded_point(false);
+        assert_eq!(uncompressed.len(), 65);
+        assert!(P256.decode_element(uncompressed.as_bytes()).is_err());
+
+        // Nor a compressed point with the uncompressed tag
+        let mut retagged = P256.encode_element(&e);
+        retagged[0] = 0x04;
+        assert!(P256.decode_element(&retagged).is_err());
+        assert_eq!(P256.decode_element(&P256.encode_element(&e)).unwrap(), e);
+    }
+
+    #[test]
+    fn test_scalar_from_bigint_reduces() {
+        let order = P256.order();
+        assert_eq!(P256.scalar_from_bigint(&order), Scalar::ZERO);
+        assert_eq!(P256.scalar_from_bigint(&(order + 5)), Scalar::from(5u64));
+    }
+
+    #[test]
+    fn test_generators_distinct() {
+        assert_ne!(P256.g(), P256.h());
+    }
+}
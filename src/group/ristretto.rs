@@ -0,0 +1,136 @@
+use anyhow::{anyhow, Result};
+use curve25519_dalek::{
+    constants::RISTRETTO_BASEPOINT_POINT,
+    ristretto::{CompressedRistretto, RistrettoPoint},
+    scalar::Scalar,
+    traits::Identity,
+};
+use num::{BigInt, Integer, Num};
+use rand_core::OsRng;
+use sha2::Sha512;
+
+use super::Group;
+use crate::chaum_pedersen::FixedUint;
+
+/// Label hashed onto the group to obtain the second generator H
+const H_LABEL: &[u8] = b"zkp-auth/chaum-pedersen/generator/v1";
+
+/// Order of the Ristretto255 group : 2^252 + 27742317777372353535851937790883648493
+const ORDER: &str = "1000000000000000000000000000000014def9dea2f79cd65812631a5cf5d3ed";
+
+/// The Ristretto255 prime order group.
+/// G is the standard base point, and H is H_LABEL hashed onto the group, so nobody knows log_G(H).
+/// Elements are 32-byte compressed points, and scalars are 32-byte little-endian integers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ristretto255;
+
+impl Group for Ristretto255 {
+    type Element = RistrettoPoint;
+    type Scalar = Scalar;
+
+    fn name(&self) -> String {
+        "ristretto255".to_string()
+    }
+
+    fn encode_params(&self) -> Vec<u8> {
+        self.name().into_bytes()
+    }
+
+    fn order(&self) -> BigInt {
+        BigInt::from_str_radix(ORDER, 16).unwrap()
+    }
+
+    fn g(&self) -> RistrettoPoint {
+        RISTRETTO_BASEPOINT_POINT
+    }
+
+    fn h(&self) -> RistrettoPoint {
+        RistrettoPoint::hash_from_bytes::<Sha512>(H_LABEL)
+    }
+
+    fn exp(&self, base: &RistrettoPoint, e: &Scalar) -> RistrettoPoint {
+        base * e
+    }
+
+    fn mul(&self, a: &RistrettoPoint, b: &RistrettoPoint) -> RistrettoPoint {
+        a + b
+    }
+
+    fn scalar_mul(&self, a: &Scalar, b: &Scalar) -> Scalar {
+        a * b
+    }
+
+    fn scalar_sub(&self, a: &Scalar, b: &Scalar) -> Scalar {
+        a - b
+    }
+
+    fn scalar_from_bigint(&self, n: &BigInt) -> Scalar {
+        let (_, mut bytes) = n.mod_floor(&self.order()).to_bytes_le();
+        bytes.resize(32, 0);
+        Scalar::from_bytes_mod_order(bytes.try_into().unwrap())
+    }
+
+    fn scalar_from_bytes(&self, bytes: &[u8]) -> Scalar {
+        let mut reduced = FixedUint::from_be_bytes(bytes, &self.order()).to_be_bytes(32);
+        reduced.reverse();
+        Scalar::from_canonical_bytes(reduced.try_into().unwrap()).unwrap()
+    }
+
+    fn random_scalar(&self) -> Scalar {
+        Scalar::random(&mut OsRng)
+    }
+
+    fn encode_element(&self, e: &RistrettoPoint) -> Vec<u8> {
+        e.compress().to_bytes().to_vec()
+    }
+
+    fn decode_element(&self, bytes: &[u8]) -> Result<RistrettoPoint> {
+        let point = CompressedRistretto::from_slice(bytes)?
+            .decompress()
+            .ok_or_else(|| anyhow!("invalid ristretto255 point"))?;
+        // Ristretto255 has prime order, so every other point generates the whole group
+        if point == RistrettoPoint::identity() {
+            return Err(anyhow!("ristretto255 point is the identity"));
+        }
+        Ok(point)
+    }
+
+    fn encode_scalar(&self, s: &Scalar) -> Vec<u8> {
+        s.to_bytes().to_vec()
+    }
+
+    fn decode_scalar(&self, bytes: &[u8]) -> Result<Scalar> {
+        let bytes: [u8; 32] = bytes
+            .try_into()
+            .map_err(|_| anyhow!("ristretto255 scalars must be 32 bytes"))?;
+        Option::from(Scalar::from_canonical_bytes(bytes))
+            .ok_or_else(|| anyhow!("non-canonical ristretto255 scalar"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encoding_sizes() {
+        let e = Ristretto255.exp(&Ristretto255.g(), &Ristretto255.random_scalar());
+        assert_eq!(Ristretto255.encode_element(&e).len(), 32);
+        assert_eq!(Ristretto255.encode_scalar(&Scalar::ONE).len(), 32);
+    }
+
+    #[test]
+    fn test_scalar_from_bigint_reduces() {
+        let order = Ristretto255.order();
+        assert_eq!(Ristretto255.scalar_from_bigint(&order), Scalar::ZERO);
+        assert_eq!(
+            Ristretto255.scalar_from_bigint(&(order + 5)),
+            Scalar::from(5u64)
+        );
+    }
+
+    #[test]
+    fn test_generators_distinct() {
+        assert_ne!(Ristretto255.g(), Ristretto255.h());
+    }
+}
@@ -0,0 +1,212 @@
+use anyhow::{anyhow, Result};
+use argon2::{Algorithm, Argon2, Params, Version};
+use rand_core::{OsRng, RngCore};
+
+use crate::{group::Group, server::zkp_auth::KdfParameters};
+
+/// Name of the only supported KDF, as sent over the wire
+pub const ARGON2ID: &str = "argon2id";
+
+/// Size in bytes of the per-user salt generated at registration
+pub const SALT_LEN: usize = 16;
+
+/// Argon2id parameters and salt used to turn a user's password into their secret x.
+/// These are public : the server stores them at registration and hands them back before login.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KdfParams {
+    pub salt: Vec<u8>,
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl KdfParams {
+    /// Minimum costs accepted by the server, following the OWASP recommendation for Argon2id (19 MiB, 2 passes)
+    pub const MIN_MEMORY_KIB: u32 = 19 * 1024;
+    pub const MIN_ITERATIONS: u32 = 2;
+
+    /// Maximum costs, so a hostile or misconfigured server can't make clients exhaust their memory or hang
+    pub const MAX_MEMORY_KIB: u32 = 1024 * 1024;
+    pub const MAX_ITERATIONS: u32 = 64;
+    pub const MAX_PARALLELISM: u32 = 16;
+    pub const MAX_SALT_LEN: usize = 64;
+
+    /// Creates parameters with the minimum costs and a fresh random salt
+    pub fn generate() -> Self {
+        let mut salt = vec![0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+
+        KdfParams {
+            salt,
+            memory_kib: Self::MIN_MEMORY_KIB,
+            iterations: Self::MIN_ITERATIONS,
+            parallelism: 1,
+        }
+    }
+
+    /// Checks the salt length, and that the costs are no weaker than the minimums and no larger than the maximums
+    pub fn validate(&self) -> Result<()> {
+        if self.salt.len() < SALT_LEN || self.salt.len() > Self::MAX_SALT_LEN {
+            return Err(anyhow!(
+                "salt must be between {} and {} bytes",
+                SALT_LEN,
+                Self::MAX_SALT_LEN
+            ));
+        }
+        if self.memory_kib < Self::MIN_MEMORY_KIB || self.memory_kib > Self::MAX_MEMORY_KIB {
+            return Err(anyhow!(
+                "memory cost must be between {} and {} KiB",
+                Self::MIN_MEMORY_KIB,
+                Self::MAX_MEMORY_KIB
+            ));
+        }
+        if self.iterations < Self::MIN_ITERATIONS || self.iterations > Self::MAX_ITERATIONS {
+            return Err(anyhow!(
+                "iteration count must be between {} and {}",
+                Self::MIN_ITERATIONS,
+                Self::MAX_ITERATIONS
+            ));
+        }
+        if self.parallelism == 0 || self.parallelism > Self::MAX_PARALLELISM {
+            return Err(anyhow!(
+                "parallelism must be between 1 and {}",
+                Self::MAX_PARALLELISM
+            ));
+        }
+        Ok(())
+    }
+
+    /// Derives the secret x from a UTF-8 password, as a scalar of `group`.
+    /// Argon2id output is stretched to |q| + 128 bits before reducing, so x is statistically close to uniform.
+    pub fn derive_secret<G: Group>(&self, password: &str, group: &G) -> Result<G::Scalar> {
+        self.validate()?;
+
+        let len = (group.order().bits() as usize + 128).div_ceil(8);
+        let params = Params::new(
+            self.memory_kib,
+            self.iterations,
+            self.parallelism,
+            Some(len),
+        )
+        .map_err(|e| anyhow!("invalid Argon2id parameters: {}", e))?;
+
+        let mut out = vec![0u8; len];
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(password.as_bytes(), &self.salt, &mut out)
+            .map_err(|e| anyhow!("failed to derive secret: {}", e))?;
+
+        let x = group.scalar_from_bytes(&out);
+        out.fill(0);
+
+        Ok(x)
+    }
+}
+
+impl From<&KdfParams> for KdfParameters {
+    fn from(kdf: &KdfParams) -> Self {
+        KdfParameters {
+            algorithm: ARGON2ID.to_string(),
+            salt: kdf.salt.clone(),
+            memory_kib: kdf.memory_kib,
+            iterations: kdf.iterations,
+            parallelism: kdf.parallelism,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{chaum_pedersen::ChaumPedersenParams, group::Ristretto255};
+
+    fn small_group() -> ChaumPedersenParams {
+        ChaumPedersenParams::new(10007.into(), 5003.into(), 2.into(), 3.into())
+    }
+
+    #[test]
+    fn test_derive_secret() {
+        let group = small_group();
+        let params = KdfParams::generate();
+
+        let x = params
+            .derive_secret("correct horse battery staple", &group)
+            .unwrap();
+        assert!(x.to_bigint() < group.q);
+        assert_eq!(
+            x,
+            params
+                .derive_secret("correct horse battery staple", &group)
+                .unwrap()
+        );
+
+        // A different salt gives an unrelated secret for the same password
+        let other = KdfParams::generate();
+        assert_ne!(
+            params.derive_secret("hunter2", &Ristretto255).unwrap(),
+            other.derive_secret("hunter2", &Ristretto255).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_validate_rejects_weak_params() {
+        assert!(KdfParams::generate().validate().is_ok());
+
+        let weak = [
+            KdfParams {
+                salt: vec![0; 8],
+                ..KdfParams::generate()
+            },
+            KdfParams {
+                memory_kib: 1024,
+                ..KdfParams::generate()
+            },
+            KdfParams {
+                iterations: 1,
+                ..KdfParams::generate()
+            },
+            KdfParams {
+                parallelism: 0,
+                ..KdfParams::generate()
+            },
+        ];
+        for params in weak {
+            assert!(params.validate().is_err());
+            assert!(params.derive_secret("hunter2", &small_group()).is_err());
+        }
+    }
+
+    #[test]
+    fn test_validate_rejects_oversized_params() {
+        let oversized = [
+            KdfParams {
+                salt: vec![0; KdfParams::MAX_SALT_LEN + 1],
+                ..KdfParams::generate()
+            },
+            KdfParams {
+                memory_kib: u32::MAX,
+                ..KdfParams::generate()
+            },
+            KdfParams {
+                iterations: u32::MAX,
+                ..KdfParams::generate()
+            },
+            KdfParams {
+                parallelism: KdfParams::MAX_PARALLELISM + 1,
+                ..KdfParams::generate()
+            },
+        ];
+        for params in oversized {
+            assert!(params.validate().is_err());
+            // Rejected before Argon2 allocates or runs anything
+            assert!(params.derive_secret("hunter2", &small_group()).is_err());
+        }
+
+        let largest = KdfParams {
+            salt: vec![0; KdfParams::MAX_SALT_LEN],
+            memory_kib: KdfParams::MAX_MEMORY_KIB,
+            iterations: KdfParams::MAX_ITERATIONS,
+            parallelism: KdfParams::MAX_PARALLELISM,
+        };
+        assert!(largest.validate().is_ok());
+    }
+}
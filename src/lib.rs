@@ -1,4 +1,10 @@
+pub mod backend;
 pub mod chaum_pedersen;
 pub mod client;
-mod db;
+pub mod db;
+pub mod error;
+pub mod group;
+pub mod kdf;
 pub mod server;
+pub mod tls;
+pub mod token;
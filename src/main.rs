@@ -1,10 +1,22 @@
-use std::{env, fs::File, io::stdin, str::FromStr};
+use std::{env, fs::File, io::stdin, sync::Arc, time::Duration};
 
-use clap::{command, Arg, Command};
+use clap::{command, Arg, ArgAction, ArgMatches, Command};
 use dotenv::dotenv;
-use num_bigint::BigInt;
 use rpassword::read_password;
-use zkp_auth::{chaum_pedersen, client, server};
+use tonic::transport::ClientTlsConfig;
+use zkp_auth::{
+    backend::Backend,
+    chaum_pedersen, client, db,
+    group::ProofMode,
+    server,
+    server::admin::ENV_ADMIN_TOKEN,
+    server::decoy::DecoyKey,
+    server::enrollment::ENV_ENROLLMENT_TOKEN,
+    server::lockout::LockoutPolicy,
+    server::rate_limit::{Quota, RateLimits},
+    tls,
+    token::TokenSigner,
+};
 
 #[tokio::main]
 async fn main() {
@@ -14,12 +26,69 @@ async fn main() {
     let matches = command!()
         .subcommand_required(true)
         .subcommand(
-            Command::new("server").about("run zkp auth server").arg(
+            Command::new("server").about("run zkp auth server").args([
                 Arg::new("addr")
                     .short('a')
                     .long("addr")
                     .default_value("127.0.0.1:8080"),
-            ),
+                Arg::new("db").long("db").default_value("memory").help(
+                    "where to store users and sessions : 'memory', or 'sqlite:///path/to/file.db'",
+                ),
+                Arg::new("challenge-bits")
+                    .long("challenge-bits")
+                    .value_parser(clap::value_parser!(u64))
+                    .help("size of interactive challenges in bits, at most |q| (default: |q|)"),
+                Arg::new("challenge-ttl")
+                    .long("challenge-ttl")
+                    .value_parser(clap::value_parser!(u64))
+                    .help("seconds an interactive challenge may be answered for (default: 60)"),
+                Arg::new("session-lifetime")
+                    .long("session-lifetime")
+                    .value_parser(clap::value_parser!(u64))
+                    .help("seconds a session lasts before the user must authenticate again (default: 86400)"),
+                Arg::new("session-idle-timeout")
+                    .long("session-idle-timeout")
+                    .value_parser(clap::value_parser!(u64))
+                    .help("seconds a session may go unused before it ends (default: 1800)"),
+                Arg::new("token-key").long("token-key").help(
+                    "issue signed session tokens with the Ed25519 key in this file, generating it if missing",
+                ),
+                Arg::new("decoy-key").long("decoy-key").help(
+                    "hide which users exist, answering for unknown users with decoys derived from the key in this file, generating it if missing",
+                ),
+                Arg::new("rate-limit-user")
+                    .long("rate-limit-user")
+                    .value_parser(clap::value_parser!(u32))
+                    .help("authentication attempts per minute per user, or 0 for no limit (default: 20)"),
+                Arg::new("rate-limit-peer")
+                    .long("rate-limit-peer")
+                    .value_parser(clap::value_parser!(u32))
+                    .help("authentication attempts per minute per client address, or 0 for no limit (default: 60)"),
+                Arg::new("rate-limit-global")
+                    .long("rate-limit-global")
+                    .value_parser(clap::value_parser!(u32))
+                    .help("authentication attempts per minute for the whole server, or 0 for no limit (default: 6000)"),
+                Arg::new("lockout-after")
+                    .long("lockout-after")
+                    .value_parser(clap::value_parser!(u32))
+                    .help("failed attempts within 15 minutes that lock a user out, or 0 to never lock users out (default: 5)"),
+                Arg::new("invites")
+                    .long("invites")
+                    .action(ArgAction::SetTrue)
+                    .help("only let users with an invite from `admin create-invite`, or the ZKP_ENROLLMENT_TOKEN, register"),
+                Arg::new("tls-cert")
+                    .long("tls-cert")
+                    .requires("tls-key")
+                    .help("serve over TLS with the PEM encoded certificate chain in this file"),
+                Arg::new("tls-key")
+                    .long("tls-key")
+                    .requires("tls-cert")
+                    .help("PEM encoded private key of --tls-cert"),
+                Arg::new("client-ca")
+                    .long("client-ca")
+                    .requires("tls-cert")
+                    .help("require client certificates signed by the PEM encoded CA certificate in this file"),
+            ]),
         )
         .subcommand(
             Command::new("client").about("run zkp auth client").args([
@@ -29,82 +98,340 @@ async fn main() {
                     .default_value("127.0.0.1:8080"),
                 Arg::new("user").short('u').long("user"),
                 Arg::new("password").short('p').long("password"),
-            ]),
+                Arg::new("mode")
+                    .short('m')
+                    .long("mode")
+                    .value_parser(["interactive", "fiat-shamir", "fiat-shamir-compact"])
+                    .default_value("interactive")
+                    .help("how to prove knowledge of the password"),
+                Arg::new("enrollment-code").long("enrollment-code").help(
+                    "invite code or enrollment token to register with (default: ZKP_ENROLLMENT_TOKEN)",
+                ),
+                pin_arg(),
+            ])
+            .args(client_tls_args()),
+        )
+        .subcommand(
+            Command::new("rotate")
+                .about("change a user's password, ending all of their sessions")
+                .args([
+                    Arg::new("server")
+                        .short('s')
+                        .long("server")
+                        .default_value("127.0.0.1:8080"),
+                    Arg::new("user").short('u').long("user"),
+                    Arg::new("password").short('p').long("password"),
+                    Arg::new("new-password").long("new-password"),
+                    pin_arg(),
+                ])
+                .args(client_tls_args()),
+        )
+        .subcommand(
+            Command::new("info")
+                .about("show the server's protocol version, group and fingerprint")
+                .arg(
+                    Arg::new("server")
+                        .short('s')
+                        .long("server")
+                        .default_value("127.0.0.1:8080"),
+                )
+                .args(client_tls_args()),
+        )
+        .subcommand(
+            Command::new("admin")
+                .about("manage users. requires the server's admin token, from --token or ZKP_ADMIN_TOKEN")
+                .subcommand_required(true)
+                .args([
+                    Arg::new("server")
+                        .short('s')
+                        .long("server")
+                        .global(true)
+                        .default_value("127.0.0.1:8080"),
+                    Arg::new("token").long("token").global(true),
+                ])
+                .args(client_tls_args().map(|arg| arg.global(true)))
+                .subcommand(Command::new("list-users").about("list all users"))
+                .subcommand(
+                    Command::new("get-user")
+                        .about("show a user")
+                        .arg(Arg::new("user").required(true)),
+                )
+                .subcommand(
+                    Command::new("create-invite")
+                        .about("issue a single-use code that lets someone register")
+                        .arg(
+                            Arg::new("ttl")
+                                .long("ttl")
+                                .value_parser(clap::value_parser!(u64))
+                                .help("seconds the code may be used for (default: 7 days)"),
+                        ),
+                )
+                .subcommand(
+                    Command::new("delete-user")
+                        .about("delete a user and their sessions")
+                        .arg(Arg::new("user").required(true)),
+                )
+                .subcommand(
+                    Command::new("lock-user")
+                        .about("stop a user authenticating, and end their sessions")
+                        .arg(Arg::new("user").required(true)),
+                )
+                .subcommand(
+                    Command::new("unlock-user")
+                        .about("let a locked or locked out user authenticate again")
+                        .arg(Arg::new("user").required(true)),
+                )
+                .subcommand(
+                    Command::new("revoke-sessions")
+                        .about("end all of a user's sessions")
+                        .arg(Arg::new("user").required(true)),
+                ),
         )
         .subcommand(
             Command::new("generate")
                 .about("generate a fresh set of Chaum-Pederson params")
-                .arg(
+                .args([
                     Arg::new("out")
                         .short('o')
                         .long("out")
                         .required(false)
                         .help("output .env file directory"),
-                ),
+                    Arg::new("bits")
+                        .short('b')
+                        .long("bits")
+                        .value_parser(["2048", "3072", "4096"])
+                        .default_value("2048")
+                        .help("size of the safe prime P, in bits"),
+                ]),
         )
         .get_matches();
 
     match matches.subcommand() {
         Some(("server", sub_matches)) => {
-            let params = chaum_pedersen::ChaumPedersenParams::new_from_env();
+            let backend = Backend::new_from_env();
             let addr = sub_matches
                 .get_one::<String>("addr")
                 .expect("server listen address is required");
 
-            server::run_server(addr, params).await;
+            let store = db::open_store(
+                sub_matches
+                    .get_one::<String>("db")
+                    .expect("db has a default value"),
+            )
+            .unwrap();
+
+            let token_signer = sub_matches
+                .get_one::<String>("token-key")
+                .map(|path| Arc::new(TokenSigner::load_or_generate(path).unwrap()));
+            let decoy_key = sub_matches
+                .get_one::<String>("decoy-key")
+                .map(|path| Arc::new(DecoyKey::load_or_generate(path).unwrap()));
+
+            let mut rate_limits = RateLimits::default();
+            for (arg, limit) in [
+                ("rate-limit-user", &mut rate_limits.per_user),
+                ("rate-limit-peer", &mut rate_limits.per_peer),
+                ("rate-limit-global", &mut rate_limits.global),
+            ] {
+                if let Some(attempts) = sub_matches.get_one::<u32>(arg) {
+                    *limit = Some(*attempts).filter(|a| *a > 0).map(Quota::per_minute);
+                }
+            }
+            let lockout_policy = sub_matches
+                .get_one::<u32>("lockout-after")
+                .map(|max_failures| LockoutPolicy {
+                    max_failures: *max_failures,
+                    ..Default::default()
+                });
+
+            let tls = sub_matches.get_one::<String>("tls-cert").map(|cert| {
+                let key = sub_matches
+                    .get_one::<String>("tls-key")
+                    .expect("tls-key is required with tls-cert");
+                tls::server_tls_config(cert, key, sub_matches.get_one::<String>("client-ca"))
+                    .unwrap()
+            });
+
+            // Registration is restricted when an enrollment token is set, or invites are enabled
+            let registration = server::enrollment::RegistrationPolicy {
+                enrollment_token: env::var(ENV_ENROLLMENT_TOKEN)
+                    .ok()
+                    .filter(|t| !t.is_empty()),
+                invites: sub_matches.get_flag("invites"),
+            };
+
+            let options = server::ServerOptions {
+                challenge_bits: sub_matches.get_one::<u64>("challenge-bits").copied(),
+                store: Some(store),
+                challenge_ttl: sub_matches
+                    .get_one::<u64>("challenge-ttl")
+                    .map(|secs| Duration::from_secs(*secs)),
+                session_lifetime: sub_matches
+                    .get_one::<u64>("session-lifetime")
+                    .map(|secs| Duration::from_secs(*secs)),
+                session_idle_timeout: sub_matches
+                    .get_one::<u64>("session-idle-timeout")
+                    .map(|secs| Duration::from_secs(*secs)),
+                token_signer,
+                // The admin service is only served when a token is configured
+                admin_token: env::var(ENV_ADMIN_TOKEN).ok().filter(|t| !t.is_empty()),
+                rate_limits: Some(rate_limits),
+                lockout_policy,
+                registration: Some(registration),
+                tls,
+                decoy_key,
+                group_name: Some(backend.group_name()),
+            };
+
+            server::run_server_with_options(addr, backend, options)
+                .await
+                .unwrap();
         }
         Some(("client", sub_matches)) => {
-            let params = chaum_pedersen::ChaumPedersenParams::new_from_env();
+            let backend = Backend::new_from_env();
             let addr = sub_matches
                 .get_one::<String>("server")
                 .expect("server address is required");
 
-            let username = sub_matches.get_one::<String>("user");
-            let username = match username {
-                Some(u) => Some(u.to_owned()), // If username is already provided via args, use it.
-                None => {
-                    let mut buffer = String::new();
-                    println!("Enter username: ");
-                    stdin()
-                        .read_line(&mut buffer)
-                        .expect("Failed to read username");
+            let username = username_arg(sub_matches);
+            let password = password_arg(sub_matches, "password", "Enter password: ");
 
-                    Some(buffer)
-                }
+            let mode = sub_matches
+                .get_one::<String>("mode")
+                .expect("mode has a default value")
+                .parse::<ProofMode>()
+                .expect("mode is restricted to valid proof modes");
+
+            let options = client::ClientOptions {
+                tls: client_tls_arg(sub_matches),
+                enrollment_code: sub_matches
+                    .get_one::<String>("enrollment-code")
+                    .cloned()
+                    .or_else(|| env::var(ENV_ENROLLMENT_TOKEN).ok())
+                    .filter(|c| !c.is_empty()),
+                pinned_fingerprint: pinned_fingerprint_arg(sub_matches),
             };
 
-            let maybe_password_str = sub_matches.get_one::<String>("password");
-            let password = match maybe_password_str {
-                Some(p) => match BigInt::from_str(p) {
-                    Ok(parsed_password) => Some(parsed_password),
-                    Err(_) => {
-                        eprintln!("Error: Password must be a number.");
-                        None
-                    }
-                },
-                None => {
-                    println!("Enter password: ");
-                    match read_password()
-                        .expect("Failed to read password")
-                        .parse::<BigInt>()
-                    {
-                        Ok(parsed_password) => Some(parsed_password),
-                        Err(_) => {
-                            eprintln!("Error: Password must be a number.");
-                            None
-                        }
-                    }
-                }
+            client::run_client_auth_check_with_options(
+                addr, &username, &password, backend, mode, options,
+            )
+            .await
+            .unwrap();
+        }
+        Some(("rotate", sub_matches)) => {
+            let backend = Backend::new_from_env();
+            let addr = sub_matches
+                .get_one::<String>("server")
+                .expect("server address is required");
+
+            let username = username_arg(sub_matches);
+            let password = password_arg(sub_matches, "password", "Enter current password: ");
+            let new_password = password_arg(sub_matches, "new-password", "Enter new password: ");
+
+            let options = client::ClientOptions {
+                tls: client_tls_arg(sub_matches),
+                pinned_fingerprint: pinned_fingerprint_arg(sub_matches),
+                ..Default::default()
             };
 
-            client::run_client_auth_check(addr, &username.unwrap(), &password.unwrap(), params)
+            client::run_client_rotate_with_options(
+                addr,
+                &username,
+                &password,
+                &new_password,
+                backend,
+                options,
+            )
+            .await
+            .unwrap();
+        }
+        Some(("info", sub_matches)) => {
+            let addr = sub_matches
+                .get_one::<String>("server")
+                .expect("server has a default value");
+
+            let mut client =
+                client::Client::connect(addr, String::new(), client_tls_arg(sub_matches))
+                    .await
+                    .unwrap();
+            let info = client.get_server_info().await.unwrap();
+            println!("Protocol version {}", info.protocol_version);
+            println!("Group {}", info.group);
+            println!("Fingerprint {}", hex::encode(&info.fingerprint));
+            println!("Proof modes {}", info.proof_modes.join(", "));
+        }
+        Some(("admin", sub_matches)) => {
+            let addr = sub_matches
+                .get_one::<String>("server")
+                .expect("server has a default value");
+            let token = sub_matches
+                .get_one::<String>("token")
+                .cloned()
+                .or_else(|| env::var(ENV_ADMIN_TOKEN).ok())
+                .expect("an admin token is required, from --token or ZKP_ADMIN_TOKEN");
+
+            let mut admin = client::AdminClient::connect(addr, &token, client_tls_arg(sub_matches))
                 .await
                 .unwrap();
+            let (command, args) = sub_matches
+                .subcommand()
+                .expect("admin requires a subcommand");
+            let user = args.get_one::<String>("user");
+            let user = || user.expect("user is required").as_str();
+
+            match command {
+                "list-users" => {
+                    for user in admin.list_users().await.unwrap() {
+                        println!("{:?}", user);
+                    }
+                }
+                "get-user" => println!("{:?}", admin.get_user(user()).await.unwrap()),
+                "create-invite" => {
+                    let ttl = args.get_one::<u64>("ttl").copied().unwrap_or(0);
+                    let invite = admin.create_invite(ttl).await.unwrap();
+                    println!(
+                        "Invite code {}, valid for {} seconds",
+                        invite.code, invite.expires_in_secs
+                    );
+                }
+                "delete-user" => {
+                    let revoked = admin.delete_user(user()).await.unwrap();
+                    println!("Deleted user {}. {} sessions ended", user(), revoked);
+                }
+                "lock-user" => {
+                    let revoked = admin.lock_user(user()).await.unwrap();
+                    println!("Locked user {}. {} sessions ended", user(), revoked);
+                }
+                "unlock-user" => {
+                    admin.unlock_user(user()).await.unwrap();
+                    println!("Unlocked user {}", user());
+                }
+                "revoke-sessions" => {
+                    let revoked = admin.revoke_all_sessions(user()).await.unwrap();
+                    println!("{} sessions of user {} ended", revoked, user());
+                }
+                _ => unreachable!("Exhausted list of admin subcommands"),
+            }
         }
         Some(("generate", sub_matches)) => {
             let out = sub_matches.get_one::<String>("out");
+            let bits = sub_matches
+                .get_one::<String>("bits")
+                .expect("bits has a default value")
+                .parse::<usize>()
+                .expect("bits is restricted to valid sizes");
 
-            let p = chaum_pedersen::generate_params().unwrap();
+            eprintln!(
+                "Generating {}-bit safe prime, this may take a while...",
+                bits
+            );
+            let p = chaum_pedersen::generate_params(bits, |tested| {
+                if tested % 100 == 0 {
+                    eprint!("\rTested {} candidates", tested);
+                }
+            })
+            .unwrap();
+            eprintln!();
 
             match out {
                 Some(out) => {
@@ -123,3 +450,72 @@ async fn main() {
         _ => unreachable!("Exhausted list of subcommands and subcommand_required prevents `None`"),
     }
 }
+
+/// Arguments for connecting to a server over TLS
+fn client_tls_args() -> [Arg; 3] {
+    [
+        Arg::new("ca")
+            .long("ca")
+            .help("connect over TLS, trusting the PEM encoded CA certificate in this file to sign the server's"),
+        Arg::new("cert")
+            .long("cert")
+            .requires_all(["ca", "key"])
+            .help("PEM encoded client certificate chain, for servers that require mutual TLS"),
+        Arg::new("key")
+            .long("key")
+            .requires("cert")
+            .help("PEM encoded private key of --cert"),
+    ]
+}
+
+/// Reads the TLS settings from the arguments of [`client_tls_args`]. The connection is plaintext without --ca
+fn client_tls_arg(matches: &ArgMatches) -> Option<ClientTlsConfig> {
+    let ca = matches.get_one::<String>("ca")?;
+    let identity = matches
+        .get_one::<String>("cert")
+        .zip(matches.get_one::<String>("key"));
+    Some(tls::client_tls_config(ca, identity).unwrap())
+}
+
+fn pin_arg() -> Arg {
+    Arg::new("pin").long("pin").help(
+        "refuse to authenticate unless the group's fingerprint is this hex string, as shown by the info command",
+    )
+}
+
+/// Reads the fingerprint of [`pin_arg`]
+fn pinned_fingerprint_arg(matches: &ArgMatches) -> Option<[u8; 32]> {
+    let pin = matches.get_one::<String>("pin")?;
+    let fingerprint = hex::decode(pin.trim())
+        .ok()
+        .and_then(|f| f.try_into().ok())
+        .expect("--pin must be a 32-byte hex encoded fingerprint");
+    Some(fingerprint)
+}
+
+/// Reads the username from the arguments, or prompts for it
+fn username_arg(matches: &ArgMatches) -> String {
+    match matches.get_one::<String>("user") {
+        Some(u) => u.to_owned(), // If username is already provided via args, use it.
+        None => {
+            let mut buffer = String::new();
+            println!("Enter username: ");
+            stdin()
+                .read_line(&mut buffer)
+                .expect("Failed to read username");
+
+            buffer.trim().to_string()
+        }
+    }
+}
+
+/// Reads a password from the arguments, or prompts for it without echoing
+fn password_arg(matches: &ArgMatches, id: &str, prompt: &str) -> String {
+    match matches.get_one::<String>(id) {
+        Some(p) => p.to_owned(),
+        None => {
+            println!("{}", prompt);
+            read_password().expect("Failed to read password")
+        }
+    }
+}
@@ -1,98 +1,607 @@
-use std::sync::Arc;
+use std::{
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
 
-use num::{bigint::Sign, BigInt, One};
-use num_bigint::ToBigInt;
+use anyhow::{anyhow, Context, Result};
 use rand_core::{OsRng, RngCore};
-use tokio::sync::RwLock;
-use tonic::{transport::Server, Request, Response, Status};
+use tonic::{
+    transport::{Server, ServerTlsConfig},
+    Request, Response, Status,
+};
 
 use crate::{
-    chaum_pedersen::ChaumPedersenParams,
-    db::{AuthChallenge, InMemoryDB},
+    backend::Backend,
+    chaum_pedersen,
+    db::{AuthChallenge, AuthStore, InMemoryDB, Session, StoreError, User},
+    error::ZkpAuthError,
+    group::{ChaumPedersen, Group, ProofContext, ProofMode, Ristretto255, P256},
+    kdf::{KdfParams, ARGON2ID},
+    token::{TokenClaims, TokenSigner, ED25519, ISSUER},
 };
 
+use self::admin::{AdminAuth, AdminService};
+use self::decoy::DecoyKey;
+use self::enrollment::{Enrollment, RegistrationPolicy};
+use self::lockout::{locked_out_for, LockoutPolicy};
+use self::rate_limit::{RateLimiter, RateLimits};
 use self::zkp_auth::{
-    auth_server, AuthenticationAnswerRequest, AuthenticationAnswerResponse,
-    AuthenticationChallengeRequest, AuthenticationChallengeResponse, RegisterRequest,
-    RegisterResponse,
+    admin_server, auth_server, AuthenticationAnswerRequest, AuthenticationAnswerResponse,
+    AuthenticationChallengeRequest, AuthenticationChallengeResponse, GetPublicKeysRequest,
+    GetPublicKeysResponse, KdfParameters, KdfParametersRequest, KdfParametersResponse,
+    ListSessionsRequest, ListSessionsResponse, LogoutRequest, LogoutResponse, ProofNonceRequest,
+    ProofNonceResponse, ProveRequest, ProveResponse, PublicKey, RefreshSessionRequest,
+    RefreshSessionResponse, RegisterRequest, RegisterResponse, RotateCredentialsRequest,
+    RotateCredentialsResponse, ServerInfoRequest, ServerInfoResponse, SessionInfo,
+    ValidateSessionRequest, ValidateSessionResponse,
 };
 
+/// Version of the authentication protocol, reported by GetServerInfo.
+/// Bumped whenever a change would make clients and servers of different versions unable to authenticate
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Purpose bound into Fiat-Shamir proofs submitted to Register
+pub const REGISTER_PURPOSE: &[u8] = b"register";
+
+/// Purpose bound into Fiat-Shamir proofs submitted to Prove
+pub const LOGIN_PURPOSE: &[u8] = b"login";
+
+/// Purpose bound into Fiat-Shamir proofs submitted to RotateCredentials
+pub const ROTATE_PURPOSE: &[u8] = b"rotate";
+
+/// Size in bytes of the nonces issued for Fiat-Shamir proofs
+const PROOF_NONCE_LEN: usize = 32;
+
+/// How long an issued proof nonce may be used for. Clients can fetch one ahead of time and prove offline within this window
+const PROOF_NONCE_TTL: Duration = Duration::from_secs(300);
+
+/// How long an interactive challenge may be answered for, unless overridden with [`ServerOptions::challenge_ttl`]
+pub const DEFAULT_CHALLENGE_TTL: Duration = Duration::from_secs(60);
+
+/// How long a session lasts, unless overridden with [`ServerOptions::session_lifetime`]
+pub const DEFAULT_SESSION_LIFETIME: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// How long a session may go unused before it ends, unless overridden with [`ServerOptions::session_idle_timeout`]
+pub const DEFAULT_SESSION_IDLE_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+/// How often expired challenges, nonces and sessions are deleted from the store
+const GC_INTERVAL: Duration = Duration::from_secs(30);
+
+pub mod admin;
+pub mod decoy;
+pub mod enrollment;
+pub mod lockout;
+pub mod rate_limit;
+
 pub mod zkp_auth {
     tonic::include_proto!("zkp_auth");
 }
 
+/// Optional server settings. The defaults are the most secure choice
+#[derive(Debug, Clone, Default)]
+pub struct ServerOptions {
+    /// Size in bits of the challenges drawn for interactive authentication, at most |q|. Defaults to |q|
+    pub challenge_bits: Option<u64>,
+    /// Where users, challenges and sessions are kept. Defaults to a fresh [`InMemoryDB`]
+    pub store: Option<Arc<dyn AuthStore>>,
+    /// How long an interactive challenge may be answered for. Defaults to [`DEFAULT_CHALLENGE_TTL`]
+    pub challenge_ttl: Option<Duration>,
+    /// How long a session lasts before the user must authenticate again. Defaults to [`DEFAULT_SESSION_LIFETIME`]
+    pub session_lifetime: Option<Duration>,
+    /// How long a session may go unused before it ends. Defaults to [`DEFAULT_SESSION_IDLE_TIMEOUT`]
+    pub session_idle_timeout: Option<Duration>,
+    /// Signs a token for every new session, which other services can verify offline with [`crate::token::verify_token`].
+    /// No tokens are issued if unset
+    pub token_signer: Option<Arc<TokenSigner>>,
+    /// Bearer token for the Admin service, at least [`admin::MIN_ADMIN_TOKEN_LEN`] characters.
+    /// The Admin service isn't served if unset
+    pub admin_token: Option<String>,
+    /// How many authentication attempts are accepted per user, per client address and in total.
+    /// Defaults to [`RateLimits::default`]
+    pub rate_limits: Option<RateLimits>,
+    /// When failed authentication attempts lock a user out. Defaults to [`LockoutPolicy::default`]
+    pub lockout_policy: Option<LockoutPolicy>,
+    /// Who may register. Defaults to anyone
+    pub registration: Option<RegistrationPolicy>,
+    /// Serves over TLS, see [`crate::tls::server_tls_config`]. The transport is plaintext if unset
+    pub tls: Option<ServerTlsConfig>,
+    /// Hides which users are registered : unknown users are answered with decoy credentials derived from this key,
+    /// so authenticating as them fails exactly as a wrong password does. Unknown users get NotFound if unset
+    pub decoy_key: Option<Arc<DecoyKey>>,
+    /// Name of the group reported by GetServerInfo. [`run_server_with_options`] sets it from the backend, see
+    /// [`Backend::group_name`]. Defaults to [`Group::name`]
+    pub group_name: Option<String>,
+}
+
 #[derive(Debug)]
-pub struct ZkpAuthService {
-    params: ChaumPedersenParams,
-    db: Arc<RwLock<InMemoryDB>>,
+pub struct ZkpAuthService<G: Group> {
+    cp: ChaumPedersen<G>,
+    store: Arc<dyn AuthStore>,
+    challenge_bits: u64,
+    challenge_ttl: Duration,
+    session_lifetime: Duration,
+    session_idle_timeout: Duration,
+    token_signer: Option<Arc<TokenSigner>>,
+    rate_limiter: RateLimiter,
+    lockout_policy: LockoutPolicy,
+    enrollment: Enrollment,
+    decoy_key: Option<Arc<DecoyKey>>,
+    group_name: String,
+    // Hex encoded fingerprint of the group, included in tokens
+    fingerprint: String,
 }
 
-impl ZkpAuthService {
-    pub fn new(params: ChaumPedersenParams) -> Self {
-        ZkpAuthService {
-            params,
-            db: Arc::new(RwLock::new(InMemoryDB::new())),
+impl<G: Group> ZkpAuthService<G> {
+    pub fn new(group: G, store: Arc<dyn AuthStore>) -> Self {
+        let options = ServerOptions {
+            store: Some(store),
+            ..Default::default()
+        };
+        Self::with_options(group, options).expect("default server options are always valid")
+    }
+
+    /// Creates the service with the given options, returning an error if they are invalid for the group
+    pub fn with_options(group: G, options: ServerOptions) -> Result<Self> {
+        let q = group.order();
+        let challenge_bits = options.challenge_bits.unwrap_or(q.bits());
+        chaum_pedersen::check_challenge_bits(&q, challenge_bits)?;
+        let rate_limits = options.rate_limits.unwrap_or_default();
+        rate_limits.validate()?;
+        let enrollment = Enrollment::new(options.registration.unwrap_or_default())?;
+        if options.decoy_key.is_some() && enrollment.is_open() {
+            // Anyone could register a name to learn whether it is taken
+            return Err(anyhow!(
+                "hiding users with a decoy key requires restricted registration : set an enrollment token or accept invites"
+            ));
+        }
+
+        Ok(ZkpAuthService {
+            group_name: options.group_name.unwrap_or_else(|| group.name()),
+            fingerprint: hex::encode(group.fingerprint()),
+            cp: ChaumPedersen::new(group),
+            store: options.store.unwrap_or_else(|| Arc::new(InMemoryDB::new())),
+            challenge_bits,
+            challenge_ttl: options.challenge_ttl.unwrap_or(DEFAULT_CHALLENGE_TTL),
+            session_lifetime: options.session_lifetime.unwrap_or(DEFAULT_SESSION_LIFETIME),
+            session_idle_timeout: options
+                .session_idle_timeout
+                .unwrap_or(DEFAULT_SESSION_IDLE_TIMEOUT),
+            token_signer: options.token_signer,
+            rate_limiter: RateLimiter::new(rate_limits),
+            lockout_policy: options.lockout_policy.unwrap_or_default(),
+            enrollment,
+            decoy_key: options.decoy_key,
+        })
+    }
+
+    /// Deletes expired challenges, proof nonces, sessions and invites and stale lockouts from the store, and
+    /// forgets idle rate limits
+    pub async fn collect_garbage(&self) -> Result<(), StoreError> {
+        self.rate_limiter.prune();
+
+        let now = SystemTime::now();
+        let challenges = self
+            .store
+            .delete_challenges_before(now - self.challenge_ttl)
+            .await?;
+        let nonces = self
+            .store
+            .delete_nonces_before(now - PROOF_NONCE_TTL)
+            .await?;
+        let sessions = self
+            .store
+            .delete_expired_sessions(now, now - self.session_idle_timeout)
+            .await?;
+        let invites = self.store.delete_expired_invites(now).await?;
+        // Failed attempts at unknown users are stored too, so the lockouts would otherwise grow with every name tried
+        let lockouts = self
+            .store
+            .delete_lockouts_before(self.lockout_policy.stale_before(now))
+            .await?;
+        log::debug!(
+            "deleted {} expired challenges, {} expired proof nonces, {} expired sessions, {} expired invites and {} stale lockouts",
+            challenges,
+            nonces,
+            sessions,
+            invites,
+            lockouts
+        );
+        Ok(())
+    }
+
+    /// Starts and stores a new session for an authenticated user
+    async fn start_session(&self, user_id: String) -> Result<Session, Status> {
+        let session = Session::new(user_id, self.session_lifetime);
+        self.store.create_session(session.clone()).await?;
+        Ok(session)
+    }
+
+    /// Signs a token for the session, returning an empty string if the server doesn't issue tokens
+    fn issue_token(&self, session: &Session, auth_id: Option<String>) -> Result<String> {
+        let signer = match &self.token_signer {
+            Some(signer) => signer,
+            None => return Ok(String::new()),
+        };
+
+        let claims = TokenClaims {
+            iss: ISSUER.to_string(),
+            sub: session.user_id.clone(),
+            iat: unix_secs(session.created_at),
+            exp: unix_secs(session.expires_at),
+            sid: session.session_id.clone(),
+            auth_id,
+            fpr: self.fingerprint.clone(),
+        };
+        signer.sign(&claims)
+    }
+
+    /// Looks up a session and records that it was used, failing with Unauthenticated if it doesn't exist or has ended
+    async fn use_session(&self, session_id: &str) -> Result<Session, Status> {
+        let mut session = self
+            .store
+            .get_session(session_id)
+            .await?
+            .ok_or_else(|| Status::unauthenticated("unknown session. please authenticate"))?;
+
+        if session.is_expired(self.session_idle_timeout) {
+            self.store.delete_session(session_id).await?;
+            return Err(Status::unauthenticated(
+                "session has expired. please authenticate again",
+            ));
+        }
+
+        session.last_used_at = SystemTime::now();
+        if !self
+            .store
+            .touch_session(session_id, session.last_used_at)
+            .await?
+        {
+            // Logged out concurrently
+            return Err(Status::unauthenticated(
+                "unknown session. please authenticate",
+            ));
+        }
+
+        Ok(session)
+    }
+
+    /// Looks up a user, failing with NotFound if they haven't registered, or returning their decoy with a decoy key
+    async fn get_user(&self, user_id: &str) -> Result<User, Status> {
+        let user = self.store.get_user(user_id).await?;
+        if let Some(decoy_key) = &self.decoy_key {
+            // Derived for registered users too, so the response time doesn't tell them apart
+            let decoy = decoy_key.user(&self.cp, user_id);
+            return Ok(user.unwrap_or(decoy));
+        }
+
+        user.ok_or_else(|| {
+            ZkpAuthError::UserNotFound {
+                user: user_id.to_string(),
+            }
+            .into()
+        })
+    }
+
+    /// Looks up a user, as [`Self::get_user`], failing as [`Self::check_unlocked`] if they may not authenticate
+    async fn get_unlocked_user(&self, user_id: &str) -> Result<User, Status> {
+        let user = self.get_user(user_id).await?;
+        self.check_unlocked(&user).await?;
+        Ok(user)
+    }
+
+    /// Fails with PermissionDenied if the user is locked, or ResourceExhausted if failed attempts locked them out.
+    /// With decoys, locked users aren't refused here, as decoys never are : see [`Self::accepts`]
+    async fn check_unlocked(&self, user: &User) -> Result<(), Status> {
+        let retry_after = if user.locked && self.decoy_key.is_none() {
+            None
+        } else {
+            let lockout = self.store.get_lockout(&user.user_id).await?;
+            match lockout.and_then(|l| locked_out_for(&l, SystemTime::now())) {
+                Some(remaining) => Some(remaining),
+                None => return Ok(()),
+            }
+        };
+        Err(ZkpAuthError::Locked {
+            user: user.user_id.clone(),
+            retry_after,
+        }
+        .into())
+    }
+
+    /// Whether a verified proof lets the user in. A locked user's proofs never do : with decoys, they are checked
+    /// and then fail as a wrong password would, so locking a user doesn't give away that they exist
+    fn accepts(&self, user: &User, verified: bool) -> bool {
+        verified && !user.locked
+    }
+
+    /// Counts a failed authentication attempt towards locking the user out.
+    /// Decoys' failures are stored just as registered users' are, so both take the same time and last as long
+    async fn record_failure(&self, user: &User) -> Result<(), Status> {
+        if !self.lockout_policy.enabled() {
+            return Ok(());
+        }
+
+        let policy = self.lockout_policy;
+        let now = SystemTime::now();
+        let user_id = &user.user_id;
+        let id = user_id.clone();
+        let lockout = self
+            .store
+            .update_lockout(
+                user_id,
+                Box::new(move |current| Some(policy.record_failure(current, &id, now))),
+            )
+            .await?;
+
+        // Failures only reset to 0 when the user is locked out
+        if let Some(lockout) = lockout.filter(|l| l.failures == 0) {
+            log::warn!(
+                "user {} locked out for {:?} after too many failed attempts",
+                user_id,
+                policy.delay(lockout.lockouts)
+            );
+        }
+        Ok(())
+    }
+
+    /// Forgets the user's failed attempts once they authenticate
+    async fn clear_failures(&self, user_id: &str) -> Result<(), Status> {
+        self.store
+            .update_lockout(user_id, Box::new(|_| None))
+            .await?;
+        Ok(())
+    }
+
+    /// Consumes a proof nonce, failing if it was never issued, has already been used or has expired
+    async fn take_proof_nonce(&self, nonce: &[u8]) -> Result<(), Status> {
+        let issued_at = self
+            .store
+            .take_nonce(nonce)
+            .await?
+            .ok_or(ZkpAuthError::NonceNotFound)?;
+        if issued_at.elapsed().unwrap_or_default() > PROOF_NONCE_TTL {
+            return Err(ZkpAuthError::NonceExpired.into());
+        }
+        Ok(())
+    }
+
+    /// Checks that each of the byte strings encodes a valid group element, other than the identity
+    fn check_elements(&self, elements: &[&[u8]]) -> Result<()> {
+        let group = self.cp.group();
+        for e in elements {
+            group.decode_element(e)?;
+        }
+        Ok(())
+    }
+
+    /// Decodes the stored commitments and the prover's response, and checks the proof
+    fn verify_proof(
+        &self,
+        y1: &[u8],
+        y2: &[u8],
+        r1: &[u8],
+        r2: &[u8],
+        c: &[u8],
+        s: &[u8],
+    ) -> Result<bool> {
+        let group = self.cp.group();
+        Ok(self.cp.verify(
+            &group.decode_element(y1)?,
+            &group.decode_element(y2)?,
+            &group.decode_element(r1)?,
+            &group.decode_element(r2)?,
+            &group.decode_scalar(c)?,
+            &group.decode_scalar(s)?,
+        ))
+    }
+
+    /// Checks a Fiat-Shamir proof sent as either (r1, r2, s) or (c, s)
+    #[allow(clippy::too_many_arguments)]
+    fn verify_fiat_shamir_proof(
+        &self,
+        ctx: &ProofContext,
+        y1: &[u8],
+        y2: &[u8],
+        r1: &[u8],
+        r2: &[u8],
+        c: &[u8],
+        s: &[u8],
+    ) -> Result<bool> {
+        let group = self.cp.group();
+        let y1 = group.decode_element(y1)?;
+        let y2 = group.decode_element(y2)?;
+        let s = group.decode_scalar(s)?;
+
+        if !c.is_empty() {
+            let c = group.decode_scalar(c)?;
+            return Ok(self
+                .cp
+                .verify_non_interactive_compact(ctx, &y1, &y2, &c, &s)
+                .is_some());
+        }
+
+        let r1 = group.decode_element(r1)?;
+        let r2 = group.decode_element(r2)?;
+        let c = self.cp.fiat_shamir_challenge(ctx, &y1, &y2, &r1, &r2);
+
+        Ok(self.cp.verify(&y1, &y2, &r1, &r2, &c, &s))
+    }
+}
+
+impl From<StoreError> for Status {
+    fn from(e: StoreError) -> Self {
+        match e {
+            StoreError::AlreadyExists(what) => {
+                Status::already_exists(format!("{} already exists", what))
+            }
+            StoreError::NotFound(what) => Status::not_found(format!("{} does not exist", what)),
+            StoreError::Backend(e) => {
+                log::error!("storage error: {:#}", e);
+                Status::internal("storage error")
+            }
         }
     }
 }
 
+/// Checks the KDF parameters a client registers with, rejecting unknown algorithms and weak costs
+fn kdf_from_proto(kdf: Option<KdfParameters>) -> Result<KdfParams> {
+    let kdf = kdf.ok_or_else(|| anyhow!("KDF parameters are required"))?;
+    if kdf.algorithm != ARGON2ID {
+        return Err(anyhow!(
+            "unsupported KDF '{}', only {} is supported",
+            kdf.algorithm,
+            ARGON2ID
+        ));
+    }
+
+    let params = KdfParams {
+        salt: kdf.salt,
+        memory_kib: kdf.memory_kib,
+        iterations: kdf.iterations,
+        parallelism: kdf.parallelism,
+    };
+    params.validate().context("invalid KDF parameters")?;
+
+    Ok(params)
+}
+
+/// Context bound into Register and RotateCredentials proofs, so they can't be replayed with other credentials
+pub fn credentials_context(y1: &[u8], y2: &[u8], kdf: &KdfParams) -> Vec<u8> {
+    let mut out = Vec::new();
+    for part in [y1, y2, &kdf.salt] {
+        out.extend_from_slice(&(part.len() as u64).to_be_bytes());
+        out.extend_from_slice(part);
+    }
+    for cost in [kdf.memory_kib, kdf.iterations, kdf.parallelism] {
+        out.extend_from_slice(&cost.to_be_bytes());
+    }
+    out
+}
+
 #[tonic::async_trait]
-impl auth_server::Auth for ZkpAuthService {
-    async fn register(
+impl<G: Group> auth_server::Auth for ZkpAuthService<G> {
+    async fn get_server_info(
         &self,
-        request: Request<RegisterRequest>,
-    ) -> Result<Response<RegisterResponse>, Status> {
+        request: Request<ServerInfoRequest>,
+    ) -> Result<Response<ServerInfoResponse>, Status> {
         log::trace!("request: {:?}", request);
 
-        let r = request.into_inner();
+        let version = request.into_inner().protocol_version;
+        if version != 0 && version != PROTOCOL_VERSION {
+            return Err(Status::failed_precondition(format!(
+                "unsupported protocol version {}. this server speaks version {}",
+                version, PROTOCOL_VERSION
+            )));
+        }
 
-        let y1 = BigInt::from_bytes_be(Sign::Plus, &r.y1);
-        let y2 = BigInt::from_bytes_be(Sign::Plus, &r.y2);
-        let user_id = r.user;
+        let group = self.cp.group();
+        let resp = ServerInfoResponse {
+            protocol_version: PROTOCOL_VERSION,
+            group: self.group_name.clone(),
+            parameters: group.encode_params(),
+            fingerprint: group.fingerprint().to_vec(),
+            proof_modes: ProofMode::ALL
+                .iter()
+                .map(|m| m.name().to_string())
+                .collect(),
+        };
+        Ok(Response::new(resp))
+    }
 
-        let mut db = self.db.write().await;
-        if db.get_user(&user_id).await.is_some() {
-            return Err(Status::already_exists(format!(
-                "user {} already exists",
-                user_id
-            )));
+    async fn register(
+        &self,
+        request: Request<RegisterRequest>,
+    ) -> Result<Response<RegisterResponse>, Status> {
+        let r = request.into_inner();
+        // The request itself isn't logged, as it may carry the enrollment token
+        log::trace!("register request for user {}", r.user);
+
+        let kdf =
+            kdf_from_proto(r.kdf).map_err(|e| Status::invalid_argument(format!("{:#}", e)))?;
+        self.check_elements(&[&r.y1, &r.y2])
+            .map_err(|e| ZkpAuthError::bad_encoding("credentials", e))?;
+        self.take_proof_nonce(&r.nonce).await?;
+
+        // Registrants must know the secret, so nobody can register credentials they can't use
+        let context = credentials_context(&r.y1, &r.y2, &kdf);
+        let ctx = ProofContext {
+            purpose: REGISTER_PURPOSE,
+            user: &r.user,
+            nonce: &r.nonce,
+            context: &context,
         };
+        let verified = self
+            .verify_fiat_shamir_proof(&ctx, &r.y1, &r.y2, &r.r1, &r.r2, &r.c, &r.s)
+            .map_err(|e| ZkpAuthError::bad_encoding("proof", e))?;
+        if !verified {
+            return Err(ZkpAuthError::RegistrationProofInvalid { user: r.user }.into());
+        }
 
-        db.create_user(user_id, y1, y2);
+        // Checked before the enrollment code, unless users are hidden with decoys : then only registrants with a
+        // valid code learn a name is taken
+        if self.decoy_key.is_none() && self.store.get_user(&r.user).await?.is_some() {
+            return Err(StoreError::AlreadyExists(format!("user {}", r.user)).into());
+        }
+        let store = self.store.as_ref();
+        let invite = self.enrollment.check(store, &r.enrollment_code).await?;
+
+        // An invite isn't used up by a registration that fails
+        let user = User::new(r.user.clone(), r.y1, r.y2, kdf);
+        if let Err(e) = store.create_user(user).await {
+            self.enrollment.restore(store, invite).await?;
+            return Err(e.into());
+        }
 
         let resp = RegisterResponse {};
         Ok(Response::new(resp))
     }
 
+    async fn get_kdf_parameters(
+        &self,
+        request: Request<KdfParametersRequest>,
+    ) -> Result<Response<KdfParametersResponse>, Status> {
+        log::trace!("request: {:?}", request);
+
+        let user = self.get_user(&request.into_inner().user).await?;
+
+        let resp = KdfParametersResponse {
+            kdf: Some((&user.kdf).into()),
+        };
+        Ok(Response::new(resp))
+    }
+
     async fn create_authentication_challenge(
         &self,
         request: Request<AuthenticationChallengeRequest>,
     ) -> Result<Response<AuthenticationChallengeResponse>, Status> {
         log::trace!("request: {:?}", request);
 
+        let peer = request.remote_addr().map(|a| a.ip());
         let r = request.into_inner();
         let user_id = r.user;
 
-        if self.db.read().await.get_user(&user_id).await.is_none() {
-            return Err(Status::not_found(format!(
-                "user {} does not exist. please register first",
-                user_id
-            )));
-        };
-
-        let r1 = BigInt::from_bytes_be(Sign::Plus, &r.r1);
-        let r2 = BigInt::from_bytes_be(Sign::Plus, &r.r2);
+        self.rate_limiter.check(&user_id, peer)?;
+        self.get_unlocked_user(&user_id).await?;
+        self.check_elements(&[&r.r1, &r.r2])
+            .map_err(|e| ZkpAuthError::bad_encoding("commitment", e))?;
 
-        let c = OsRng.next_u64().to_bigint().unwrap();
+        let group = self.cp.group();
+        let c =
+            chaum_pedersen::random_challenge(&group.order(), self.challenge_bits).map_err(|e| {
+                log::error!("failed to draw a challenge: {:#}", e);
+                Status::internal("failed to draw a challenge")
+            })?;
+        let c = group.encode_scalar(&group.scalar_from_bigint(&c));
 
-        let new_challenge = AuthChallenge::new(user_id, r1, r2, c.to_owned());
+        let new_challenge = AuthChallenge::new(user_id, r.r1, r.r2, c.to_owned());
         let new_challenge_id = new_challenge.auth_id.clone();
 
-        self.db.write().await.create_challenge(new_challenge);
+        self.store.create_challenge(new_challenge).await?;
 
         let resp = AuthenticationChallengeResponse {
             auth_id: new_challenge_id,
-            c: c.to_bytes_be().1,
+            c,
         };
         Ok(Response::new(resp))
     }
@@ -102,72 +611,373 @@ impl auth_server::Auth for ZkpAuthService {
         request: Request<AuthenticationAnswerRequest>,
     ) -> Result<Response<AuthenticationAnswerResponse>, Status> {
         log::debug!("Got a request: {:?}", request);
+        let peer = request.remote_addr().map(|a| a.ip());
         let r = request.into_inner();
 
-        let s = BigInt::from_bytes_be(Sign::Plus, &r.s);
+        let s = r.s;
         let auth_id = r.auth_id;
 
-        let db = self.db.read().await;
-
-        let challenge = match db.get_challenge(&auth_id).await {
+        // Challenges are consumed by the first attempt, successful or not, so an answer can never be replayed
+        let challenge = match self.store.take_challenge(&auth_id).await? {
             Some(c) => c,
-            None => {
-                return Err(Status::not_found(format!(
-                    "challenge {} does not exist. please create an authentication challenge first",
-                    auth_id
-                )))
-            }
+            None => return Err(ZkpAuthError::ChallengeNotFound { auth_id }.into()),
         };
+        if challenge.is_expired(self.challenge_ttl) {
+            return Err(ZkpAuthError::ChallengeExpired { auth_id }.into());
+        }
+        self.rate_limiter.check(&challenge.user_id, peer)?;
+
+        let user = self.get_unlocked_user(&challenge.user_id).await?;
+
+        let success = self
+            .verify_proof(
+                &user.y1,
+                &user.y2,
+                &challenge.r1,
+                &challenge.r2,
+                &challenge.c,
+                &s,
+            )
+            .map_err(|e| ZkpAuthError::bad_encoding("proof", e))?;
+
+        if self.accepts(&user, success) {
+            self.clear_failures(&user.user_id).await?;
+            let session = self.start_session(user.user_id).await?;
+            let token = self
+                .issue_token(&session, Some(challenge.auth_id))
+                .map_err(token_error)?;
 
-        let user = match db.get_user(&challenge.lock().await.user_id).await {
-            Some(u) => u,
-            None => {
-                return Err(Status::not_found(format!(
-                    "user {} does not exist for challenge {}",
-                    challenge.lock().await.user_id,
-                    auth_id
-                )))
-            }
+            let resp = AuthenticationAnswerResponse {
+                token,
+                expires_in_secs: expires_in_secs(&session),
+                session_id: session.session_id,
+            };
+            Ok(Response::new(resp))
+        } else {
+            self.record_failure(&user).await?;
+            Err(ZkpAuthError::ProofInvalid { user: user.user_id }.into())
+        }
+    }
+
+    async fn get_proof_nonce(
+        &self,
+        request: Request<ProofNonceRequest>,
+    ) -> Result<Response<ProofNonceResponse>, Status> {
+        log::trace!("request: {:?}", request);
+
+        let mut nonce = vec![0u8; PROOF_NONCE_LEN];
+        OsRng.fill_bytes(&mut nonce);
+
+        self.store.create_nonce(&nonce, SystemTime::now()).await?;
+
+        let resp = ProofNonceResponse {
+            nonce,
+            expires_in_secs: PROOF_NONCE_TTL.as_secs(),
         };
+        Ok(Response::new(resp))
+    }
 
-        let mut challenge = challenge.lock().await;
-        let user = user.lock().await;
-        let params = &self.params;
+    async fn prove(
+        &self,
+        request: Request<ProveRequest>,
+    ) -> Result<Response<ProveResponse>, Status> {
+        log::debug!("Got a request: {:?}", request);
+        let peer = request.remote_addr().map(|a| a.ip());
+        let r = request.into_inner();
 
-        let r1_prime = (params.g.modpow(&s, &params.p) * &user.y1.modpow(&challenge.c, &params.p))
-            .modpow(&BigInt::one(), &params.p);
+        self.rate_limiter.check(&r.user, peer)?;
+        self.take_proof_nonce(&r.nonce).await?;
+        let user = self.get_unlocked_user(&r.user).await?;
 
-        let r2_prime = (params.h.modpow(&s, &params.p) * &user.y2.modpow(&challenge.c, &params.p))
-            .modpow(&BigInt::one(), &params.p);
+        let ctx = ProofContext {
+            purpose: LOGIN_PURPOSE,
+            user: &r.user,
+            nonce: &r.nonce,
+            context: &r.context,
+        };
+        let verified = self
+            .verify_fiat_shamir_proof(&ctx, &user.y1, &user.y2, &r.r1, &r.r2, &r.c, &r.s)
+            .map_err(|e| ZkpAuthError::bad_encoding("proof", e))?;
 
-        let success = challenge.r1 == r1_prime && challenge.r2 == r2_prime;
+        if !self.accepts(&user, verified) {
+            self.record_failure(&user).await?;
+            return Err(ZkpAuthError::ProofInvalid { user: r.user }.into());
+        }
+        self.clear_failures(&r.user).await?;
 
-        if success {
-            challenge.finalize_challenge(s.clone());
-            let resp = AuthenticationAnswerResponse {
-                session_id: challenge.session_id.clone().unwrap(),
-            };
-            Ok(Response::new(resp))
+        log::debug!("user {} authenticated with non-interactive proof", r.user);
+
+        let session = self.start_session(r.user).await?;
+        let token = self
+            .issue_token(&session, Some(hex::encode(&r.nonce)))
+            .map_err(token_error)?;
+
+        let resp = ProveResponse {
+            token,
+            expires_in_secs: expires_in_secs(&session),
+            session_id: session.session_id,
+        };
+        Ok(Response::new(resp))
+    }
+
+    async fn validate_session(
+        &self,
+        request: Request<ValidateSessionRequest>,
+    ) -> Result<Response<ValidateSessionResponse>, Status> {
+        let session = self.use_session(&request.into_inner().session_id).await?;
+
+        let resp = ValidateSessionResponse {
+            expires_in_secs: expires_in_secs(&session),
+            user: session.user_id,
+        };
+        Ok(Response::new(resp))
+    }
+
+    async fn refresh_session(
+        &self,
+        request: Request<RefreshSessionRequest>,
+    ) -> Result<Response<RefreshSessionResponse>, Status> {
+        let old = self.use_session(&request.into_inner().session_id).await?;
+
+        // Only one of several concurrent refreshes of the same session wins
+        if !self.store.delete_session(&old.session_id).await? {
+            return Err(Status::unauthenticated(
+                "unknown session. please authenticate",
+            ));
+        }
+        let session = self.start_session(old.user_id).await?;
+        log::debug!(
+            "session {} of user {} refreshed as {}",
+            old.session_id,
+            session.user_id,
+            session.session_id
+        );
+
+        let resp = RefreshSessionResponse {
+            token: self.issue_token(&session, None).map_err(token_error)?,
+            expires_in_secs: expires_in_secs(&session),
+            session_id: session.session_id,
+        };
+        Ok(Response::new(resp))
+    }
+
+    async fn logout(
+        &self,
+        request: Request<LogoutRequest>,
+    ) -> Result<Response<LogoutResponse>, Status> {
+        let r = request.into_inner();
+
+        let session = self.use_session(&r.session_id).await?;
+        let revoked = if r.all_sessions {
+            self.store.delete_user_sessions(&session.user_id).await?
         } else {
-            Err(Status::failed_precondition(format!(
-                "authentication failed for challenge {}",
-                challenge.auth_id
-            )))
+            self.store.delete_session(&session.session_id).await? as u64
+        };
+        log::debug!("revoked {} sessions of user {}", revoked, session.user_id);
+
+        let resp = LogoutResponse { revoked };
+        Ok(Response::new(resp))
+    }
+
+    async fn list_sessions(
+        &self,
+        request: Request<ListSessionsRequest>,
+    ) -> Result<Response<ListSessionsResponse>, Status> {
+        let current = self.use_session(&request.into_inner().session_id).await?;
+
+        let sessions = self
+            .store
+            .list_sessions(&current.user_id)
+            .await?
+            .into_iter()
+            .filter(|s| !s.is_expired(self.session_idle_timeout))
+            .map(|s| SessionInfo {
+                current: s.session_id == current.session_id,
+                created_at: unix_secs(s.created_at),
+                expires_at: unix_secs(s.expires_at),
+                last_used_at: unix_secs(s.last_used_at),
+                session_id: s.session_id,
+            })
+            .collect();
+
+        let resp = ListSessionsResponse { sessions };
+        Ok(Response::new(resp))
+    }
+
+    async fn rotate_credentials(
+        &self,
+        request: Request<RotateCredentialsRequest>,
+    ) -> Result<Response<RotateCredentialsResponse>, Status> {
+        log::debug!("Got a request: {:?}", request);
+        let peer = request.remote_addr().map(|a| a.ip());
+        let r = request.into_inner();
+
+        self.rate_limiter.check(&r.user, peer)?;
+        self.take_proof_nonce(&r.nonce).await?;
+        let user = self.get_unlocked_user(&r.user).await?;
+
+        let new_kdf =
+            kdf_from_proto(r.new_kdf).map_err(|e| Status::invalid_argument(format!("{:#}", e)))?;
+        self.check_elements(&[&r.new_y1, &r.new_y2])
+            .map_err(|e| ZkpAuthError::bad_encoding("new credentials", e))?;
+
+        let context = credentials_context(&r.new_y1, &r.new_y2, &new_kdf);
+        let ctx = ProofContext {
+            purpose: ROTATE_PURPOSE,
+            user: &r.user,
+            nonce: &r.nonce,
+            context: &context,
+        };
+        let verified = self
+            .verify_fiat_shamir_proof(&ctx, &user.y1, &user.y2, &r.r1, &r.r2, &r.c, &r.s)
+            .map_err(|e| ZkpAuthError::bad_encoding("proof", e))?;
+        if !self.accepts(&user, verified) {
+            self.record_failure(&user).await?;
+            return Err(ZkpAuthError::ProofInvalid { user: r.user }.into());
         }
+        self.clear_failures(&r.user).await?;
+
+        let new = User::new(r.user.clone(), r.new_y1, r.new_y2, new_kdf);
+        if !self
+            .store
+            .rotate_credentials(&user, new, SystemTime::now())
+            .await?
+        {
+            return Err(Status::aborted(format!(
+                "credentials of user {} were changed concurrently. please try again",
+                r.user
+            )));
+        }
+
+        // Sessions proven with the old secret must not outlive it
+        let revoked_sessions = self.store.delete_user_sessions(&r.user).await?;
+        log::info!(
+            "user {} rotated their credentials, revoking {} sessions",
+            r.user,
+            revoked_sessions
+        );
+
+        let resp = RotateCredentialsResponse { revoked_sessions };
+        Ok(Response::new(resp))
     }
+
+    async fn get_public_keys(
+        &self,
+        _request: Request<GetPublicKeysRequest>,
+    ) -> Result<Response<GetPublicKeysResponse>, Status> {
+        let keys = self
+            .token_signer
+            .iter()
+            .map(|signer| {
+                let key = signer.public_key();
+                PublicKey {
+                    kid: key.kid.clone(),
+                    algorithm: ED25519.to_string(),
+                    public_key: key.key.to_bytes().to_vec(),
+                }
+            })
+            .collect();
+
+        let resp = GetPublicKeysResponse { keys };
+        Ok(Response::new(resp))
+    }
+}
+
+fn token_error(e: anyhow::Error) -> Status {
+    log::error!("failed to sign session token: {:#}", e);
+    Status::internal("failed to sign session token")
+}
+
+fn unix_secs(t: SystemTime) -> u64 {
+    t.duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Seconds until the session reaches its expiry, ignoring the idle timeout
+fn expires_in_secs(session: &Session) -> u64 {
+    session
+        .expires_at
+        .duration_since(SystemTime::now())
+        .unwrap_or_default()
+        .as_secs()
 }
 
 /// Runs the Chaum-Pedersen ZKP authentication server
-/// This function will block until the server is shutdown
-pub async fn run_server(addr: &str, params: ChaumPedersenParams) {
-    log::info!("starting server on {}", addr);
-    let addr = addr.parse().unwrap();
-
-    let service = ZkpAuthService::new(params);
-
-    Server::builder()
-        .add_service(auth_server::AuthServer::new(service))
-        .serve(addr)
-        .await
-        .unwrap();
+/// This function will block until the server is shutdown, and returns an error if the parameters fail validation
+pub async fn run_server(addr: &str, backend: impl Into<Backend>) -> Result<()> {
+    run_server_with_options(addr, backend, ServerOptions::default()).await
+}
+
+/// Runs the Chaum-Pedersen ZKP authentication server, as [`run_server`], with the given options
+pub async fn run_server_with_options(
+    addr: &str,
+    backend: impl Into<Backend>,
+    mut options: ServerOptions,
+) -> Result<()> {
+    let backend = backend.into();
+    backend
+        .validate()
+        .context("refusing to start server with invalid Chaum-Pedersen parameters")?;
+
+    log::info!("starting server on {} with backend {}", addr, backend);
+    let addr = addr.parse()?;
+    options
+        .group_name
+        .get_or_insert_with(|| backend.group_name());
+
+    match backend {
+        Backend::ModP(params) => serve(addr, params, options).await,
+        Backend::Ristretto255 => serve(addr, Ristretto255, options).await,
+        Backend::P256 => serve(addr, P256, options).await,
+    }
+}
+
+async fn serve<G: Group>(addr: SocketAddr, group: G, options: ServerOptions) -> Result<()> {
+    let admin_auth = options
+        .admin_token
+        .as_deref()
+        .map(AdminAuth::new)
+        .transpose()
+        .context("refusing to start server with an invalid admin token")?;
+    let mut server = Server::builder();
+    if let Some(tls) = options.tls.clone() {
+        server = server
+            .tls_config(tls)
+            .context("refusing to start server with invalid TLS settings")?;
+    } else {
+        log::warn!("serving without TLS : proofs and session IDs are sent in plaintext");
+    }
+    let service = Arc::new(
+        ZkpAuthService::with_options(group, options)
+            .context("refusing to start server with invalid options")?,
+    );
+    let admin = admin_auth.map(|auth| {
+        let admin = AdminService::new(service.store.clone(), service.session_idle_timeout);
+        admin_server::AdminServer::with_interceptor(admin, auth)
+    });
+
+    let gc = {
+        let service = service.clone();
+        async move {
+            let mut interval = tokio::time::interval(GC_INTERVAL);
+            loop {
+                interval.tick().await;
+                if let Err(e) = service.collect_garbage().await {
+                    log::warn!("failed to delete expired state: {}", e);
+                }
+            }
+        }
+    };
+
+    // The garbage collector runs for as long as the server does
+    tokio::select! {
+        res = server
+            .add_service(auth_server::AuthServer::from_arc(service))
+            .add_optional_service(admin)
+            .serve(addr) => res?,
+        _ = gc => unreachable!("garbage collection loop never returns"),
+    }
+
+    Ok(())
 }
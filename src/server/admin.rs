@@ -0,0 +1,291 @@
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{anyhow, Result};
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+use tonic::{service::Interceptor, Request, Response, Status};
+
+use super::{
+    enrollment::{new_invite, DEFAULT_INVITE_TTL, MAX_INVITE_TTL},
+    unix_secs,
+    zkp_auth::{
+        admin_server, AdminUser, CreateInviteRequest, CreateInviteResponse, DeleteUserRequest,
+        DeleteUserResponse, GetUserRequest, GetUserResponse, ListUsersRequest, ListUsersResponse,
+        LockUserRequest, LockUserResponse, RevokeAllSessionsRequest, RevokeAllSessionsResponse,
+        UnlockUserRequest, UnlockUserResponse,
+    },
+};
+use crate::{
+    db::{AuthStore, User},
+    error::ZkpAuthError,
+};
+
+/// Environment variable holding the admin token, for both the server and the `admin` CLI
+pub const ENV_ADMIN_TOKEN: &str = "ZKP_ADMIN_TOKEN";
+
+/// Shortest admin token the server accepts
+pub const MIN_ADMIN_TOKEN_LEN: usize = 16;
+
+/// Rejects admin calls that don't carry the admin token as a bearer token
+#[derive(Clone)]
+pub struct AdminAuth {
+    // Only a hash of the token is kept, and compared in constant time
+    token_hash: [u8; 32],
+}
+
+impl AdminAuth {
+    pub fn new(token: &str) -> Result<Self> {
+        if token.len() < MIN_ADMIN_TOKEN_LEN {
+            return Err(anyhow!(
+                "admin token must be at least {} characters",
+                MIN_ADMIN_TOKEN_LEN
+            ));
+        }
+
+        Ok(AdminAuth {
+            token_hash: Sha256::digest(token).into(),
+        })
+    }
+}
+
+impl Interceptor for AdminAuth {
+    fn call(&mut self, request: Request<()>) -> Result<Request<()>, Status> {
+        let token = request
+            .metadata()
+            .get("authorization")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "));
+
+        match token {
+            Some(token) if bool::from(Sha256::digest(token).ct_eq(&self.token_hash)) => Ok(request),
+            _ => Err(Status::unauthenticated("missing or invalid admin token")),
+        }
+    }
+}
+
+/// User management for operators, served as a separate gRPC service behind [`AdminAuth`]
+#[derive(Debug)]
+pub struct AdminService {
+    store: Arc<dyn AuthStore>,
+    session_idle_timeout: Duration,
+}
+
+impl AdminService {
+    pub fn new(store: Arc<dyn AuthStore>, session_idle_timeout: Duration) -> Self {
+        AdminService {
+            store,
+            session_idle_timeout,
+        }
+    }
+
+    async fn get_user(&self, user_id: &str) -> Result<User, Status> {
+        self.store
+            .get_user(user_id)
+            .await?
+            .ok_or_else(|| user_not_found(user_id))
+    }
+
+    async fn describe(&self, user: User) -> Result<AdminUser, Status> {
+        let active_sessions = self
+            .store
+            .list_sessions(&user.user_id)
+            .await?
+            .iter()
+            .filter(|s| !s.is_expired(self.session_idle_timeout))
+            .count();
+        let rotations = self.store.list_credential_rotations(&user.user_id).await?;
+        let last_rotated_at = rotations
+            .iter()
+            .map(|r| r.rotated_at)
+            .max()
+            .map(unix_secs)
+            .unwrap_or(0);
+        let now = SystemTime::now();
+        let locked_out_until = self
+            .store
+            .get_lockout(&user.user_id)
+            .await?
+            .and_then(|l| l.locked_until)
+            .filter(|until| *until > now)
+            .map(unix_secs)
+            .unwrap_or(0);
+
+        Ok(AdminUser {
+            user: user.user_id,
+            locked: user.locked,
+            active_sessions: active_sessions as u64,
+            credential_rotations: rotations.len() as u64,
+            last_rotated_at,
+            locked_out_until,
+        })
+    }
+}
+
+#[tonic::async_trait]
+impl admin_server::Admin for AdminService {
+    async fn list_users(
+        &self,
+        _request: Request<ListUsersRequest>,
+    ) -> Result<Response<ListUsersResponse>, Status> {
+        let mut users = Vec::new();
+        for user in self.store.list_users().await? {
+            users.push(self.describe(user).await?);
+        }
+        users.sort_by(|a, b| a.user.cmp(&b.user));
+
+        let resp = ListUsersResponse { users };
+        Ok(Response::new(resp))
+    }
+
+    async fn get_user(
+        &self,
+        request: Request<GetUserRequest>,
+    ) -> Result<Response<GetUserResponse>, Status> {
+        let user = self.get_user(&request.into_inner().user).await?;
+
+        let resp = GetUserResponse {
+            user: Some(self.describe(user).await?),
+        };
+        Ok(Response::new(resp))
+    }
+
+    async fn delete_user(
+        &self,
+        request: Request<DeleteUserRequest>,
+    ) -> Result<Response<DeleteUserResponse>, Status> {
+        let user_id = request.into_inner().user;
+
+        // Sessions are only counted for users that exist; delete_user removes any started in the meantime
+        self.get_user(&user_id).await?;
+        let revoked_sessions = self.store.delete_user_sessions(&user_id).await?;
+        if !self.store.delete_user(&user_id).await? {
+            return Err(user_not_found(&user_id));
+        }
+        log::info!("admin deleted user {}", user_id);
+
+        let resp = DeleteUserResponse { revoked_sessions };
+        Ok(Response::new(resp))
+    }
+
+    async fn create_invite(
+        &self,
+        request: Request<CreateInviteRequest>,
+    ) -> Result<Response<CreateInviteResponse>, Status> {
+        let ttl = match request.into_inner().ttl_secs {
+            0 => DEFAULT_INVITE_TTL,
+            secs => Duration::from_secs(secs),
+        };
+        if ttl > MAX_INVITE_TTL {
+            return Err(Status::invalid_argument(format!(
+                "invites may be valid for at most {} seconds",
+                MAX_INVITE_TTL.as_secs()
+            )));
+        }
+
+        let (code, invite) = new_invite(ttl);
+        self.store.create_invite(invite).await?;
+        log::info!("admin created an invite, valid for {:?}", ttl);
+
+        let resp = CreateInviteResponse {
+            code,
+            expires_in_secs: ttl.as_secs(),
+        };
+        Ok(Response::new(resp))
+    }
+
+    async fn lock_user(
+        &self,
+        request: Request<LockUserRequest>,
+    ) -> Result<Response<LockUserResponse>, Status> {
+        let user_id = request.into_inner().user;
+
+        if !self.store.set_user_locked(&user_id, true).await? {
+            return Err(user_not_found(&user_id));
+        }
+        let revoked_sessions = self.store.delete_user_sessions(&user_id).await?;
+        log::info!(
+            "admin locked user {}, revoking {} sessions",
+            user_id,
+            revoked_sessions
+        );
+
+        let resp = LockUserResponse { revoked_sessions };
+        Ok(Response::new(resp))
+    }
+
+    async fn unlock_user(
+        &self,
+        request: Request<UnlockUserRequest>,
+    ) -> Result<Response<UnlockUserResponse>, Status> {
+        let user_id = request.into_inner().user;
+
+        if !self.store.set_user_locked(&user_id, false).await? {
+            return Err(user_not_found(&user_id));
+        }
+        // Also lift any lockout after failed attempts
+        self.store
+            .update_lockout(&user_id, Box::new(|_| None))
+            .await?;
+        log::info!("admin unlocked user {}", user_id);
+
+        let resp = UnlockUserResponse {};
+        Ok(Response::new(resp))
+    }
+
+    async fn revoke_all_sessions(
+        &self,
+        request: Request<RevokeAllSessionsRequest>,
+    ) -> Result<Response<RevokeAllSessionsResponse>, Status> {
+        let user = self.get_user(&request.into_inner().user).await?;
+
+        let revoked_sessions = self.store.delete_user_sessions(&user.user_id).await?;
+        log::info!(
+            "admin revoked {} sessions of user {}",
+            revoked_sessions,
+            user.user_id
+        );
+
+        let resp = RevokeAllSessionsResponse { revoked_sessions };
+        Ok(Response::new(resp))
+    }
+}
+
+fn user_not_found(user_id: &str) -> Status {
+    ZkpAuthError::UserNotFound {
+        user: user_id.to_string(),
+    }
+    .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_authorization(value: &str) -> Request<()> {
+        let mut request = Request::new(());
+        request
+            .metadata_mut()
+            .insert("authorization", value.parse().unwrap());
+        request
+    }
+
+    #[test]
+    fn test_admin_auth() {
+        assert!(AdminAuth::new("too short").is_err());
+
+        let mut auth = AdminAuth::new("correct horse battery staple").unwrap();
+        assert!(auth
+            .call(with_authorization("Bearer correct horse battery staple"))
+            .is_ok());
+        assert!(auth
+            .call(with_authorization("Bearer correct horse battery stapler"))
+            .is_err());
+        assert!(auth
+            .call(with_authorization("correct horse battery staple"))
+            .is_err());
+        assert!(auth.call(Request::new(())).is_err());
+    }
+}
@@ -0,0 +1,131 @@
+use std::{fs, path::Path};
+
+use anyhow::{anyhow, Context, Result};
+use hmac::{Hmac, Mac};
+use rand_core::{OsRng, RngCore};
+use sha2::Sha256;
+
+use crate::{
+    db::User,
+    group::{ChaumPedersen, Group},
+    kdf::{KdfParams, SALT_LEN},
+    token::write_private,
+};
+
+/// Size in bytes of decoy keys
+pub const DECOY_KEY_LEN: usize = 32;
+
+/// Derives credentials for users that don't exist, so the server can answer for them exactly as it would for a
+/// registered user given the wrong password. The same key and name always give the same decoy, so repeated
+/// requests can't tell decoys from real users either
+pub struct DecoyKey {
+    key: [u8; DECOY_KEY_LEN],
+}
+
+impl std::fmt::Debug for DecoyKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DecoyKey").finish_non_exhaustive()
+    }
+}
+
+impl DecoyKey {
+    pub fn new(key: [u8; DECOY_KEY_LEN]) -> Self {
+        DecoyKey { key }
+    }
+
+    /// Creates a fresh random key
+    pub fn generate() -> Self {
+        let mut key = [0u8; DECOY_KEY_LEN];
+        OsRng.fill_bytes(&mut key);
+        Self::new(key)
+    }
+
+    /// Loads the hex encoded key at path, creating it with a fresh key if the file doesn't exist.
+    /// The key must outlive restarts : decoys that change would give unknown users away
+    pub fn load_or_generate(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        if !path.exists() {
+            let key = Self::generate();
+            write_private(path, &hex::encode(key.key))
+                .with_context(|| format!("failed to write decoy key {}", path.display()))?;
+            log::info!("generated decoy key {}", path.display());
+            return Ok(key);
+        }
+
+        let key = fs::read_to_string(path)
+            .with_context(|| format!("failed to read decoy key {}", path.display()))?;
+        let key: [u8; DECOY_KEY_LEN] = hex::decode(key.trim())
+            .ok()
+            .and_then(|k| k.try_into().ok())
+            .ok_or_else(|| {
+                anyhow!(
+                    "decoy key {} is not {} hex encoded bytes",
+                    path.display(),
+                    DECOY_KEY_LEN
+                )
+            })?;
+        Ok(Self::new(key))
+    }
+
+    fn mac(&self, label: &[u8], user_id: &str) -> [u8; 32] {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.key).expect("HMAC accepts any key length");
+        for part in [label, user_id.as_bytes()] {
+            mac.update(&(part.len() as u64).to_be_bytes());
+            mac.update(part);
+        }
+        mac.finalize().into_bytes().into()
+    }
+
+    /// The decoy for user_id : credentials for a secret nobody knows, with KDF parameters shaped like
+    /// [`KdfParams::generate`]'s
+    pub fn user<G: Group>(&self, cp: &ChaumPedersen<G>, user_id: &str) -> User {
+        let group = cp.group();
+        let x = group.hash_to_scalar(&[&self.mac(b"secret", user_id)]);
+        let (y1, y2) = cp.y1_y2(&x);
+
+        let kdf = KdfParams {
+            salt: self.mac(b"salt", user_id)[..SALT_LEN].to_vec(),
+            ..KdfParams::generate()
+        };
+        User::new(
+            user_id.to_string(),
+            group.encode_element(&y1),
+            group.encode_element(&y2),
+            kdf,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::group::Ristretto255;
+
+    #[test]
+    fn test_decoys() {
+        let cp = ChaumPedersen::new(Ristretto255);
+        let key = DecoyKey::generate();
+
+        let alice = key.user(&cp, "alice");
+        assert_eq!(alice, key.user(&cp, "alice"));
+        assert_eq!(alice.kdf.salt.len(), SALT_LEN);
+        assert!(alice.kdf.validate().is_ok());
+        assert_ne!(alice.y1, key.user(&cp, "bob").y1);
+        assert_ne!(alice.y1, DecoyKey::generate().user(&cp, "alice").y1);
+    }
+
+    #[test]
+    fn test_load_or_generate() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("decoy.key");
+        let cp = ChaumPedersen::new(Ristretto255);
+
+        let generated = DecoyKey::load_or_generate(&path).unwrap();
+        let loaded = DecoyKey::load_or_generate(&path).unwrap();
+        assert_eq!(generated.user(&cp, "alice"), loaded.user(&cp, "alice"));
+
+        fs::write(&path, "not a key").unwrap();
+        assert!(DecoyKey::load_or_generate(&path).is_err());
+    }
+}
@@ -0,0 +1,168 @@
+use std::time::{Duration, SystemTime};
+
+use anyhow::{anyhow, Result};
+use rand_core::{OsRng, RngCore};
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+use tonic::Status;
+
+use crate::db::{AuthStore, Invite};
+
+/// Environment variable holding the enrollment token, for both the server and the `client` CLI
+pub const ENV_ENROLLMENT_TOKEN: &str = "ZKP_ENROLLMENT_TOKEN";
+
+/// Shortest enrollment token the server accepts
+pub const MIN_ENROLLMENT_TOKEN_LEN: usize = 16;
+
+/// How long an invite may be used for, unless the admin asks for another lifetime
+pub const DEFAULT_INVITE_TTL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Longest an invite may be used for
+pub const MAX_INVITE_TTL: Duration = Duration::from_secs(365 * 24 * 60 * 60);
+
+/// Size in bytes of invite codes, before hex encoding
+const INVITE_CODE_LEN: usize = 16;
+
+/// Who may register. Anyone may, unless an enrollment token is set or invites are accepted, in which case
+/// registrations must present one of them
+#[derive(Debug, Clone, Default)]
+pub struct RegistrationPolicy {
+    /// Pre-shared token, at least [`MIN_ENROLLMENT_TOKEN_LEN`] characters
+    pub enrollment_token: Option<String>,
+    /// Accept single-use invite codes, issued by the Admin service's CreateInvite
+    pub invites: bool,
+}
+
+/// Generates a fresh invite code, returning it with the invite to store
+pub fn new_invite(ttl: Duration) -> (String, Invite) {
+    let mut code = [0u8; INVITE_CODE_LEN];
+    OsRng.fill_bytes(&mut code);
+    let code = hex::encode(code);
+
+    let created_at = SystemTime::now();
+    let invite = Invite {
+        code_hash: Sha256::digest(&code).to_vec(),
+        created_at,
+        expires_at: created_at + ttl,
+    };
+    (code, invite)
+}
+
+/// Checks registrations against a [`RegistrationPolicy`]
+#[derive(Debug)]
+pub struct Enrollment {
+    // Only a hash of the token is kept, and compared in constant time
+    token_hash: Option<[u8; 32]>,
+    invites: bool,
+}
+
+impl Enrollment {
+    pub fn new(policy: RegistrationPolicy) -> Result<Self> {
+        if let Some(token) = &policy.enrollment_token {
+            if token.len() < MIN_ENROLLMENT_TOKEN_LEN {
+                return Err(anyhow!(
+                    "enrollment token must be at least {} characters",
+                    MIN_ENROLLMENT_TOKEN_LEN
+                ));
+            }
+        }
+
+        Ok(Enrollment {
+            token_hash: policy
+                .enrollment_token
+                .map(|token| Sha256::digest(token).into()),
+            invites: policy.invites,
+        })
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.token_hash.is_none() && !self.invites
+    }
+
+    /// Fails with PermissionDenied unless registration is open or code is the enrollment token or an unused
+    /// invite code. A matching invite is consumed and returned, so it can be given back with [`Self::restore`]
+    /// if the registration fails
+    pub async fn check(&self, store: &dyn AuthStore, code: &str) -> Result<Option<Invite>, Status> {
+        if self.is_open() {
+            return Ok(None);
+        }
+
+        let code_hash: [u8; 32] = Sha256::digest(code).into();
+        if let Some(token_hash) = &self.token_hash {
+            if bool::from(code_hash.ct_eq(token_hash)) {
+                return Ok(None);
+            }
+        }
+
+        if self.invites && !code.is_empty() {
+            if let Some(invite) = store.take_invite(&code_hash).await? {
+                if invite.expires_at > SystemTime::now() {
+                    return Ok(Some(invite));
+                }
+            }
+        }
+
+        Err(Status::permission_denied(
+            "registration requires a valid enrollment token or invite code",
+        ))
+    }
+
+    /// Gives back an invite consumed by [`Self::check`], so it can still be used
+    pub async fn restore(
+        &self,
+        store: &dyn AuthStore,
+        invite: Option<Invite>,
+    ) -> Result<(), Status> {
+        if let Some(invite) = invite {
+            store.create_invite(invite).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::InMemoryDB;
+
+    #[tokio::test]
+    async fn test_enrollment() {
+        let store = InMemoryDB::new();
+        let open = Enrollment::new(RegistrationPolicy::default()).unwrap();
+        assert!(open.check(&store, "").await.is_ok());
+
+        assert!(Enrollment::new(RegistrationPolicy {
+            enrollment_token: Some("too short".to_string()),
+            invites: false,
+        })
+        .is_err());
+
+        let enrollment = Enrollment::new(RegistrationPolicy {
+            enrollment_token: Some("correct horse battery staple".to_string()),
+            invites: true,
+        })
+        .unwrap();
+        assert!(enrollment.check(&store, "").await.is_err());
+        assert!(enrollment.check(&store, "wrong token").await.is_err());
+        assert!(enrollment
+            .check(&store, "correct horse battery staple")
+            .await
+            .is_ok());
+
+        // Invites work once, and only until they expire
+        let (code, invite) = new_invite(DEFAULT_INVITE_TTL);
+        store.create_invite(invite).await.unwrap();
+        let invite = enrollment.check(&store, &code).await.unwrap();
+        assert!(invite.is_some());
+        assert!(enrollment.check(&store, &code).await.is_err());
+
+        // A restored invite can be used again, once
+        enrollment.restore(&store, invite).await.unwrap();
+        assert!(enrollment.check(&store, &code).await.is_ok());
+        assert!(enrollment.check(&store, &code).await.is_err());
+
+        let (code, invite) = new_invite(Duration::ZERO);
+        store.create_invite(invite).await.unwrap();
+        assert!(enrollment.check(&store, &code).await.is_err());
+    }
+}
@@ -0,0 +1,168 @@
+use std::time::{Duration, SystemTime};
+
+use crate::db::Lockout;
+
+/// Failed attempts that lock a user out, unless overridden with [`LockoutPolicy::max_failures`]
+pub const DEFAULT_MAX_FAILURES: u32 = 5;
+
+/// Window the failures must happen within, unless overridden with [`LockoutPolicy::window`]
+pub const DEFAULT_FAILURE_WINDOW: Duration = Duration::from_secs(15 * 60);
+
+/// How long the first lockout lasts, unless overridden with [`LockoutPolicy::base_delay`]
+pub const DEFAULT_LOCKOUT_DELAY: Duration = Duration::from_secs(60);
+
+/// Longest a lockout lasts, unless overridden with [`LockoutPolicy::max_delay`]
+pub const DEFAULT_MAX_LOCKOUT_DELAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// When failed authentication attempts lock a user out.
+/// Each lockout without a successful authentication in between lasts twice as long as the last, up to `max_delay`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LockoutPolicy {
+    /// Failed attempts within `window` that lock the user out. 0 never locks users out
+    pub max_failures: u32,
+    pub window: Duration,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for LockoutPolicy {
+    fn default() -> Self {
+        LockoutPolicy {
+            max_failures: DEFAULT_MAX_FAILURES,
+            window: DEFAULT_FAILURE_WINDOW,
+            base_delay: DEFAULT_LOCKOUT_DELAY,
+            max_delay: DEFAULT_MAX_LOCKOUT_DELAY,
+        }
+    }
+}
+
+impl LockoutPolicy {
+    pub fn enabled(&self) -> bool {
+        self.max_failures > 0
+    }
+
+    /// How long the nth lockout in a row lasts, counting from 1
+    pub fn delay(&self, lockouts: u32) -> Duration {
+        let doublings = lockouts.saturating_sub(1).min(31);
+        self.base_delay
+            .saturating_mul(1 << doublings)
+            .min(self.max_delay)
+    }
+
+    /// Lockout state whose window started before this is stale at `now` : the window and any lockout in it ended
+    /// at least `max_delay` ago. Forgetting it only means the next lockout's delay starts over from `base_delay`
+    pub fn stale_before(&self, now: SystemTime) -> SystemTime {
+        // A lockout starts a new window, and lasts at most max_delay from its start
+        now.checked_sub(self.window.max(self.max_delay) + self.max_delay)
+            .unwrap_or(SystemTime::UNIX_EPOCH)
+    }
+
+    /// Returns the user's lockout state after a failed attempt at `now`
+    pub fn record_failure(
+        &self,
+        current: Option<Lockout>,
+        user_id: &str,
+        now: SystemTime,
+    ) -> Lockout {
+        let mut lockout = current.unwrap_or_else(|| Lockout {
+            user_id: user_id.to_string(),
+            failures: 0,
+            window_started_at: now,
+            lockouts: 0,
+            locked_until: None,
+        });
+
+        let window_elapsed = now
+            .duration_since(lockout.window_started_at)
+            .unwrap_or_default();
+        if window_elapsed > self.window {
+            lockout.failures = 0;
+            lockout.window_started_at = now;
+        }
+
+        lockout.failures += 1;
+        if lockout.failures >= self.max_failures {
+            lockout.lockouts += 1;
+            lockout.locked_until = Some(now + self.delay(lockout.lockouts));
+            lockout.failures = 0;
+            lockout.window_started_at = now;
+        }
+        lockout
+    }
+}
+
+/// How long until the user may authenticate again, or None if they aren't locked out
+pub fn locked_out_for(lockout: &Lockout, now: SystemTime) -> Option<Duration> {
+    lockout
+        .locked_until
+        .and_then(|until| until.duration_since(now).ok())
+        .filter(|d| !d.is_zero())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_failure() {
+        let policy = LockoutPolicy {
+            max_failures: 3,
+            window: Duration::from_secs(60),
+            base_delay: Duration::from_secs(10),
+            max_delay: Duration::from_secs(25),
+        };
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let at = |secs| start + Duration::from_secs(secs);
+
+        // Failures spread wider than the window never lock the user out
+        let mut lockout = None;
+        for secs in [0, 40, 80, 120] {
+            let l = policy.record_failure(lockout, "alice", at(secs));
+            assert_eq!(l.locked_until, None);
+            lockout = Some(l);
+        }
+
+        // The delay doubles with each lockout, up to the maximum
+        for (n, delay) in [(1, 10), (2, 20), (3, 25)] {
+            let base = 200 * n as u64;
+            let mut l = lockout.take();
+            for secs in base..base + 3 {
+                l = Some(policy.record_failure(l, "alice", at(secs)));
+            }
+            let l = l.unwrap();
+            assert_eq!(l.lockouts, n);
+            assert_eq!(l.locked_until, Some(at(base + 2 + delay)));
+            assert_eq!(
+                locked_out_for(&l, at(base + 2)),
+                Some(Duration::from_secs(delay))
+            );
+            assert_eq!(locked_out_for(&l, at(base + 2 + delay)), None);
+            lockout = Some(l);
+        }
+    }
+
+    #[test]
+    fn test_stale_before() {
+        let policy = LockoutPolicy {
+            max_failures: 2,
+            window: Duration::from_secs(60),
+            base_delay: Duration::from_secs(10),
+            max_delay: Duration::from_secs(100),
+        };
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let at = |secs| start + Duration::from_secs(secs);
+
+        // Locked out for as long as possible, the state is kept until max_delay after the lockout ends
+        let current = Lockout {
+            user_id: "alice".to_string(),
+            failures: 1,
+            window_started_at: at(0),
+            lockouts: 9,
+            locked_until: None,
+        };
+        let l = policy.record_failure(Some(current), "alice", at(2));
+        assert_eq!(l.locked_until, Some(at(102)));
+        assert!(l.window_started_at >= policy.stale_before(at(202)));
+        assert!(l.window_started_at < policy.stale_before(at(203)));
+    }
+}
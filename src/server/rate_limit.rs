@@ -0,0 +1,290 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use tonic::Status;
+
+use crate::error::ZkpAuthError;
+
+/// Authentication attempts a user may make per minute, unless overridden with [`RateLimits::per_user`]
+pub const DEFAULT_USER_ATTEMPTS_PER_MINUTE: u32 = 20;
+
+/// Authentication attempts an address may make per minute, unless overridden with [`RateLimits::per_peer`]
+pub const DEFAULT_PEER_ATTEMPTS_PER_MINUTE: u32 = 60;
+
+/// Authentication attempts the whole server accepts per minute, unless overridden with [`RateLimits::global`]
+pub const DEFAULT_GLOBAL_ATTEMPTS_PER_MINUTE: u32 = 6000;
+
+/// A token bucket : up to `burst` attempts in a row, refilled at `burst` attempts per `period`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quota {
+    pub burst: u32,
+    pub period: Duration,
+}
+
+impl Quota {
+    pub fn per_minute(attempts: u32) -> Self {
+        Quota {
+            burst: attempts,
+            period: Duration::from_secs(60),
+        }
+    }
+
+    fn refill_per_sec(&self) -> f64 {
+        self.burst as f64 / self.period.as_secs_f64()
+    }
+}
+
+/// How many authentication attempts are accepted. A limit of None is unlimited
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimits {
+    pub per_user: Option<Quota>,
+    /// Per IP address of the client
+    pub per_peer: Option<Quota>,
+    pub global: Option<Quota>,
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        RateLimits {
+            per_user: Some(Quota::per_minute(DEFAULT_USER_ATTEMPTS_PER_MINUTE)),
+            per_peer: Some(Quota::per_minute(DEFAULT_PEER_ATTEMPTS_PER_MINUTE)),
+            global: Some(Quota::per_minute(DEFAULT_GLOBAL_ATTEMPTS_PER_MINUTE)),
+        }
+    }
+}
+
+impl RateLimits {
+    pub fn unlimited() -> Self {
+        RateLimits {
+            per_user: None,
+            per_peer: None,
+            global: None,
+        }
+    }
+
+    /// Checks that every quota allows at least one attempt per period
+    pub fn validate(&self) -> Result<()> {
+        for quota in [self.per_user, self.per_peer, self.global].iter().flatten() {
+            if quota.burst == 0 || quota.period.is_zero() {
+                return Err(anyhow!(
+                    "rate limits must allow at least one attempt per period. use no limit instead"
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Key {
+    User(String),
+    Peer(IpAddr),
+    Global,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl Bucket {
+    /// Tokens in the bucket at `now`
+    fn refilled(&self, quota: &Quota, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(self.updated_at).as_secs_f64();
+        (self.tokens + elapsed * quota.refill_per_sec()).min(quota.burst as f64)
+    }
+}
+
+/// An attempt was refused because it exceeded a limit
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateLimited {
+    /// Which limit was exceeded : "user", "address" or "server"
+    pub scope: &'static str,
+    pub retry_after: Duration,
+}
+
+impl From<RateLimited> for ZkpAuthError {
+    fn from(e: RateLimited) -> Self {
+        ZkpAuthError::RateLimited {
+            scope: e.scope.to_string(),
+            retry_after: e.retry_after,
+        }
+    }
+}
+
+impl From<RateLimited> for Status {
+    fn from(e: RateLimited) -> Self {
+        ZkpAuthError::from(e).into()
+    }
+}
+
+/// Throttles authentication attempts with a token bucket per user, per client address and for the whole server
+#[derive(Debug)]
+pub struct RateLimiter {
+    limits: RateLimits,
+    buckets: Mutex<HashMap<Key, Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(limits: RateLimits) -> Self {
+        RateLimiter {
+            limits,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Takes a token from each bucket the attempt counts against. Nothing is taken if any of them is empty
+    pub fn check(&self, user_id: &str, peer: Option<IpAddr>) -> Result<(), RateLimited> {
+        self.check_at(user_id, peer, Instant::now())
+    }
+
+    fn check_at(
+        &self,
+        user_id: &str,
+        peer: Option<IpAddr>,
+        now: Instant,
+    ) -> Result<(), RateLimited> {
+        let mut limits = Vec::with_capacity(3);
+        if let Some(quota) = self.limits.global {
+            limits.push((Key::Global, "server", quota));
+        }
+        if let (Some(quota), Some(peer)) = (self.limits.per_peer, peer) {
+            limits.push((Key::Peer(peer), "address", quota));
+        }
+        if let Some(quota) = self.limits.per_user {
+            limits.push((Key::User(user_id.to_string()), "user", quota));
+        }
+
+        let mut buckets = self.buckets.lock().unwrap();
+        let mut refilled = Vec::with_capacity(limits.len());
+        for (key, scope, quota) in &limits {
+            let tokens = buckets
+                .get(key)
+                .map_or(quota.burst as f64, |b| b.refilled(quota, now));
+            if tokens < 1.0 {
+                return Err(RateLimited {
+                    scope,
+                    retry_after: Duration::from_secs_f64((1.0 - tokens) / quota.refill_per_sec()),
+                });
+            }
+            refilled.push(tokens);
+        }
+
+        for ((key, _, _), tokens) in limits.into_iter().zip(refilled) {
+            buckets.insert(
+                key,
+                Bucket {
+                    tokens: tokens - 1.0,
+                    updated_at: now,
+                },
+            );
+        }
+        Ok(())
+    }
+
+    /// Forgets buckets that have refilled, which behave the same as missing ones
+    pub fn prune(&self) {
+        self.prune_at(Instant::now())
+    }
+
+    fn prune_at(&self, now: Instant) {
+        let limits = self.limits;
+        self.buckets.lock().unwrap().retain(|key, bucket| {
+            let quota = match key {
+                Key::User(_) => limits.per_user,
+                Key::Peer(_) => limits.per_peer,
+                Key::Global => limits.global,
+            };
+            quota.is_some_and(|q| bucket.refilled(&q, now) < q.burst as f64)
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(per_user: u32, per_peer: u32, global: u32) -> RateLimiter {
+        RateLimiter::new(RateLimits {
+            per_user: Some(Quota::per_minute(per_user)),
+            per_peer: Some(Quota::per_minute(per_peer)),
+            global: Some(Quota::per_minute(global)),
+        })
+    }
+
+    #[test]
+    fn test_rate_limits() {
+        let limiter = limiter(2, 3, 5);
+        let peer = Some("10.0.0.1".parse().unwrap());
+        let other_peer = Some("10.0.0.2".parse().unwrap());
+        let now = Instant::now();
+
+        // A user may burst up to their quota, then must wait for a refill
+        assert!(limiter.check_at("alice", peer, now).is_ok());
+        assert!(limiter.check_at("alice", peer, now).is_ok());
+        let limited = limiter.check_at("alice", peer, now).unwrap_err();
+        assert_eq!(limited.scope, "user");
+        assert_eq!(limited.retry_after, Duration::from_secs(30));
+
+        // So may an address, whichever users it tries
+        assert!(limiter.check_at("bob", peer, now).is_ok());
+        assert_eq!(
+            limiter.check_at("carol", peer, now).unwrap_err().scope,
+            "address"
+        );
+        assert!(limiter.check_at("carol", other_peer, now).is_ok());
+
+        // And the server as a whole
+        assert!(limiter.check_at("dave", other_peer, now).is_ok());
+        assert_eq!(
+            limiter.check_at("erin", None, now).unwrap_err().scope,
+            "server"
+        );
+
+        assert!(limiter
+            .check_at("alice", peer, now + Duration::from_secs(30))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_refused_attempts_take_nothing() {
+        let limiter = limiter(1, 10, 10);
+        let peer = Some("10.0.0.1".parse().unwrap());
+        let now = Instant::now();
+
+        assert!(limiter.check_at("alice", peer, now).is_ok());
+        for _ in 0..20 {
+            assert_eq!(
+                limiter.check_at("alice", peer, now).unwrap_err().scope,
+                "user"
+            );
+        }
+        // Only alice's successful attempt counted against the address and the server
+        for user in ["bob", "carol", "dave"] {
+            assert!(limiter.check_at(user, peer, now).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_prune() {
+        let limiter = limiter(2, 2, 100);
+        let now = Instant::now();
+        limiter.check_at("alice", None, now).unwrap();
+
+        // The server's bucket refills within a second, alice's takes 30
+        limiter.prune_at(now + Duration::from_secs(10));
+        assert_eq!(limiter.buckets.lock().unwrap().len(), 1);
+        limiter.prune_at(now + Duration::from_secs(60));
+        assert!(limiter.buckets.lock().unwrap().is_empty());
+
+        assert!(RateLimiter::new(RateLimits::unlimited())
+            .check_at("alice", None, now)
+            .is_ok());
+    }
+}
@@ -0,0 +1,53 @@
+use std::{fs, path::Path};
+
+use anyhow::{anyhow, Context, Result};
+use tonic::transport::{Certificate, ClientTlsConfig, Identity, ServerTlsConfig};
+
+fn read_pem(path: &Path, what: &str) -> Result<Vec<u8>> {
+    let pem =
+        fs::read(path).with_context(|| format!("failed to read {} {}", what, path.display()))?;
+    if !String::from_utf8_lossy(&pem).contains("-----BEGIN ") {
+        return Err(anyhow!("{} {} is not PEM encoded", what, path.display()));
+    }
+    Ok(pem)
+}
+
+/// Server TLS settings from the PEM encoded certificate chain and private key at `cert` and `key`.
+/// With `client_ca`, clients must also present a certificate signed by that CA (mutual TLS)
+pub fn server_tls_config(
+    cert: impl AsRef<Path>,
+    key: impl AsRef<Path>,
+    client_ca: Option<impl AsRef<Path>>,
+) -> Result<ServerTlsConfig> {
+    let identity = Identity::from_pem(
+        read_pem(cert.as_ref(), "certificate")?,
+        read_pem(key.as_ref(), "private key")?,
+    );
+    let mut config = ServerTlsConfig::new().identity(identity);
+    if let Some(client_ca) = client_ca {
+        config = config.client_ca_root(Certificate::from_pem(read_pem(
+            client_ca.as_ref(),
+            "client CA certificate",
+        )?));
+    }
+    Ok(config)
+}
+
+/// Client TLS settings trusting the PEM encoded CA certificate at `ca` to sign the server's certificate.
+/// `identity` is a client certificate chain and private key, for servers that require mutual TLS
+pub fn client_tls_config(
+    ca: impl AsRef<Path>,
+    identity: Option<(impl AsRef<Path>, impl AsRef<Path>)>,
+) -> Result<ClientTlsConfig> {
+    let mut config = ClientTlsConfig::new().ca_certificate(Certificate::from_pem(read_pem(
+        ca.as_ref(),
+        "CA certificate",
+    )?));
+    if let Some((cert, key)) = identity {
+        config = config.identity(Identity::from_pem(
+            read_pem(cert.as_ref(), "client certificate")?,
+            read_pem(key.as_ref(), "client private key")?,
+        ));
+    }
+    Ok(config)
+}
@@ -0,0 +1,285 @@
+use std::{
+    fs,
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{anyhow, Context, Result};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use rand_core::OsRng;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// JWT `alg` of the tokens issued by the server
+pub const EDDSA: &str = "EdDSA";
+
+/// Name of the signature algorithm of published keys, as sent over the wire
+pub const ED25519: &str = "Ed25519";
+
+/// Issuer of every token
+pub const ISSUER: &str = "zkp-auth";
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Header {
+    alg: String,
+    typ: String,
+    kid: String,
+}
+
+/// Claims of a session token
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenClaims {
+    pub iss: String,
+    /// The authenticated user
+    pub sub: String,
+    /// Issued at, in seconds since the Unix epoch
+    pub iat: u64,
+    /// Expiry, in seconds since the Unix epoch. The same as the session's
+    pub exp: u64,
+    /// The session the token was issued for
+    pub sid: String,
+    /// The challenge that was answered, or the hex encoded nonce of a Fiat-Shamir proof.
+    /// Missing from tokens issued by RefreshSession
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth_id: Option<String>,
+    /// Hex encoded [`crate::group::Group::fingerprint`] of the group the user proved knowledge of their secret in
+    pub fpr: String,
+}
+
+impl TokenClaims {
+    pub fn expires_at(&self) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(self.exp)
+    }
+}
+
+/// A public key tokens can be verified with, identified by its key ID
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenPublicKey {
+    pub kid: String,
+    pub key: VerifyingKey,
+}
+
+impl TokenPublicKey {
+    pub fn new(key: VerifyingKey) -> Self {
+        // The key ID is derived from the key, so it never needs to be configured separately
+        let kid = URL_SAFE_NO_PAD.encode(&Sha256::digest(key.as_bytes())[..12]);
+        TokenPublicKey { kid, key }
+    }
+
+    /// Parses a key published by GetPublicKeys, checking that the key ID matches
+    pub fn from_bytes(kid: &str, algorithm: &str, bytes: &[u8]) -> Result<Self> {
+        if algorithm != ED25519 {
+            return Err(anyhow!("unsupported token key algorithm '{}'", algorithm));
+        }
+        let bytes: &[u8; 32] = bytes
+            .try_into()
+            .map_err(|_| anyhow!("Ed25519 public keys are 32 bytes"))?;
+        let key = Self::new(VerifyingKey::from_bytes(bytes)?);
+        if key.kid != kid {
+            return Err(anyhow!("key ID {} does not match the key", kid));
+        }
+        Ok(key)
+    }
+}
+
+/// Signs session tokens with an Ed25519 key, as EdDSA JWTs
+pub struct TokenSigner {
+    key: SigningKey,
+    public_key: TokenPublicKey,
+}
+
+impl std::fmt::Debug for TokenSigner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TokenSigner")
+            .field("kid", &self.public_key.kid)
+            .finish_non_exhaustive()
+    }
+}
+
+impl TokenSigner {
+    pub fn new(key: SigningKey) -> Self {
+        let public_key = TokenPublicKey::new(key.verifying_key());
+        TokenSigner { key, public_key }
+    }
+
+    /// Creates a signer with a fresh random key
+    pub fn generate() -> Self {
+        Self::new(SigningKey::generate(&mut OsRng))
+    }
+
+    /// Loads the hex encoded 32-byte key seed at path, creating it with a fresh key if the file doesn't exist
+    pub fn load_or_generate(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        if !path.exists() {
+            let signer = Self::generate();
+            write_private(path, &hex::encode(signer.key.to_bytes()))
+                .with_context(|| format!("failed to write token key {}", path.display()))?;
+            log::info!("generated token signing key {}", path.display());
+            return Ok(signer);
+        }
+
+        let seed = fs::read_to_string(path)
+            .with_context(|| format!("failed to read token key {}", path.display()))?;
+        let seed: [u8; 32] = hex::decode(seed.trim())
+            .ok()
+            .and_then(|s| s.try_into().ok())
+            .ok_or_else(|| anyhow!("token key {} is not 32 hex encoded bytes", path.display()))?;
+        Ok(Self::new(SigningKey::from_bytes(&seed)))
+    }
+
+    pub fn public_key(&self) -> &TokenPublicKey {
+        &self.public_key
+    }
+
+    /// Signs the claims, returning a compact JWT
+    pub fn sign(&self, claims: &TokenClaims) -> Result<String> {
+        let header = Header {
+            alg: EDDSA.to_string(),
+            typ: "JWT".to_string(),
+            kid: self.public_key.kid.clone(),
+        };
+        let signing_input = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(serde_json::to_vec(&header)?),
+            URL_SAFE_NO_PAD.encode(serde_json::to_vec(claims)?)
+        );
+        let signature = self.key.sign(signing_input.as_bytes());
+
+        Ok(format!(
+            "{}.{}",
+            signing_input,
+            URL_SAFE_NO_PAD.encode(signature.to_bytes())
+        ))
+    }
+}
+
+#[cfg(unix)]
+pub(crate) fn write_private(path: &Path, contents: &str) -> std::io::Result<()> {
+    use std::{io::Write, os::unix::fs::OpenOptionsExt};
+
+    fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)?
+        .write_all(contents.as_bytes())
+}
+
+#[cfg(not(unix))]
+pub(crate) fn write_private(path: &Path, contents: &str) -> std::io::Result<()> {
+    fs::write(path, contents)
+}
+
+/// Verifies a session token offline, against the keys published by the server's GetPublicKeys RPC.
+/// Checks the signature, issuer and expiry, and returns the claims. Callers should also check `fpr` if they
+/// only trust proofs in a particular group. The token does not reflect a later logout : call ValidateSession for that
+pub fn verify_token(token: &str, keys: &[TokenPublicKey]) -> Result<TokenClaims> {
+    let mut parts = token.split('.');
+    let (header, claims, signature) = match (parts.next(), parts.next(), parts.next(), parts.next())
+    {
+        (Some(h), Some(c), Some(s), None) => (h, c, s),
+        _ => return Err(anyhow!("malformed token")),
+    };
+
+    let decoded_header: Header = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(header)?)
+        .context("malformed token header")?;
+    if decoded_header.alg != EDDSA {
+        return Err(anyhow!(
+            "unsupported token algorithm '{}'",
+            decoded_header.alg
+        ));
+    }
+    let key = keys
+        .iter()
+        .find(|k| k.kid == decoded_header.kid)
+        .ok_or_else(|| anyhow!("token signed by unknown key {}", decoded_header.kid))?;
+
+    let signature = Signature::from_slice(&URL_SAFE_NO_PAD.decode(signature)?)
+        .context("malformed token signature")?;
+    key.key
+        .verify_strict(format!("{}.{}", header, claims).as_bytes(), &signature)
+        .map_err(|_| anyhow!("invalid token signature"))?;
+
+    let claims: TokenClaims = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(claims)?)
+        .context("malformed token claims")?;
+    if claims.iss != ISSUER {
+        return Err(anyhow!("token issued by '{}'", claims.iss));
+    }
+    if SystemTime::now() >= claims.expires_at() {
+        return Err(anyhow!("token has expired"));
+    }
+
+    Ok(claims)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_claims(expires_in: u64) -> TokenClaims {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        TokenClaims {
+            iss: ISSUER.to_string(),
+            sub: "alice".to_string(),
+            iat: now,
+            exp: now + expires_in,
+            sid: "session".to_string(),
+            auth_id: Some("challenge".to_string()),
+            fpr: "00".repeat(32),
+        }
+    }
+
+    #[test]
+    fn test_sign_and_verify() {
+        let signer = TokenSigner::generate();
+        let other = TokenSigner::generate();
+        let keys = [other.public_key().clone(), signer.public_key().clone()];
+
+        let claims = test_claims(60);
+        let token = signer.sign(&claims).unwrap();
+        assert_eq!(verify_token(&token, &keys).unwrap(), claims);
+
+        // Unknown key, expired token, tampered claims and truncated tokens are all rejected
+        assert!(verify_token(&token, &keys[..1]).is_err());
+        assert!(verify_token(&signer.sign(&test_claims(0)).unwrap(), &keys).is_err());
+
+        let mut parts: Vec<&str> = token.split('.').collect();
+        let other_claims = URL_SAFE_NO_PAD.encode(
+            serde_json::to_vec(&TokenClaims {
+                sub: "mallory".to_string(),
+                ..claims
+            })
+            .unwrap(),
+        );
+        parts[1] = &other_claims;
+        assert!(verify_token(&parts.join("."), &keys).is_err());
+        assert!(verify_token(token.rsplit_once('.').unwrap().0, &keys).is_err());
+    }
+
+    #[test]
+    fn test_public_key_round_trip() {
+        let key = TokenSigner::generate().public_key().clone();
+        let parsed = TokenPublicKey::from_bytes(&key.kid, ED25519, key.key.as_bytes()).unwrap();
+        assert_eq!(parsed, key);
+
+        assert!(TokenPublicKey::from_bytes("wrong", ED25519, key.key.as_bytes()).is_err());
+        assert!(TokenPublicKey::from_bytes(&key.kid, "RS256", key.key.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_load_or_generate() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("token.key");
+
+        let generated = TokenSigner::load_or_generate(&path).unwrap();
+        let loaded = TokenSigner::load_or_generate(&path).unwrap();
+        assert_eq!(generated.public_key(), loaded.public_key());
+
+        fs::write(&path, "not a key").unwrap();
+        assert!(TokenSigner::load_or_generate(&path).is_err());
+    }
+}
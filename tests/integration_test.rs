@@ -2,17 +2,21 @@
 mod integration_tests {
     use num_bigint::ToBigInt;
     use std::time::Duration;
+    use zkp_auth::backend::Backend;
     use zkp_auth::chaum_pedersen::ChaumPedersenParams;
     use zkp_auth::client::run_client_auth_check;
-    use zkp_auth::server::run_server;
+    use zkp_auth::group::ProofMode;
+    use zkp_auth::server::{run_server, run_server_with_options, ServerOptions};
 
     static SERVER_ADDR: &str = "127.0.0.1:8181";
     static TEST_USER: &str = "test_user";
+    static TEST_PASSWORD: &str = "correct horse battery staple";
 
     fn create_test_params() -> ChaumPedersenParams {
         // Example parameters (usually these should be large prime numbers)
-        let p = 10009.to_bigint().unwrap();
-        let q = 5004.to_bigint().unwrap();
+        // 10007 is a safe prime, so q = (10007-1)/2 = 5003 is also prime
+        let p = 10007.to_bigint().unwrap();
+        let q = 5003.to_bigint().unwrap();
         let g = 2.to_bigint().unwrap();
         let h = 3.to_bigint().unwrap();
 
@@ -22,15 +26,21 @@ mod integration_tests {
     #[tokio::test]
     async fn test_end_to_end_functionality() {
         let test_params = create_test_params();
-        let test_password = &64.to_bigint().unwrap();
+        let test_password = TEST_PASSWORD;
         // Spin up the server
         let server_thread = tokio::spawn(run_server(SERVER_ADDR, test_params.clone()));
 
         // Wait for the server to start
         tokio::time::sleep(Duration::from_millis(200)).await;
 
-        let res =
-            run_client_auth_check(SERVER_ADDR, TEST_USER, test_password, test_params.clone()).await;
+        let res = run_client_auth_check(
+            SERVER_ADDR,
+            TEST_USER,
+            test_password,
+            test_params.clone(),
+            ProofMode::Interactive,
+        )
+        .await;
 
         assert!(res.is_ok());
         assert!(!res.unwrap().is_empty());
@@ -38,4 +48,1694 @@ mod integration_tests {
         // Stop the server
         server_thread.abort();
     }
+
+    async fn end_to_end_with_backend(addr: &'static str, backend: Backend, mode: ProofMode) {
+        let test_password = TEST_PASSWORD;
+        let server_thread = tokio::spawn(run_server(addr, backend.clone()));
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let res = run_client_auth_check(addr, TEST_USER, test_password, backend, mode).await;
+
+        assert!(res.is_ok());
+        assert!(!res.unwrap().is_empty());
+
+        server_thread.abort();
+    }
+
+    #[tokio::test]
+    async fn test_end_to_end_ristretto255() {
+        end_to_end_with_backend(
+            "127.0.0.1:8182",
+            Backend::Ristretto255,
+            ProofMode::Interactive,
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_end_to_end_p256() {
+        end_to_end_with_backend("127.0.0.1:8183", Backend::P256, ProofMode::Interactive).await;
+    }
+
+    #[tokio::test]
+    async fn test_end_to_end_fiat_shamir() {
+        end_to_end_with_backend(
+            "127.0.0.1:8184",
+            create_test_params().into(),
+            ProofMode::FiatShamir,
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_end_to_end_fiat_shamir_compact() {
+        end_to_end_with_backend(
+            "127.0.0.1:8185",
+            Backend::Ristretto255,
+            ProofMode::FiatShamirCompact,
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_proof_nonce_is_single_use() {
+        use zkp_auth::client::zkp_auth::ProveRequest;
+        use zkp_auth::client::Client;
+        use zkp_auth::error::ZkpAuthError;
+        use zkp_auth::group::{ChaumPedersen, Group, ProofContext, Ristretto255};
+        use zkp_auth::kdf::KdfParams;
+        use zkp_auth::server::LOGIN_PURPOSE;
+
+        let addr = "127.0.0.1:8186";
+        let server_thread = tokio::spawn(run_server(addr, Backend::Ristretto255));
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let cp = ChaumPedersen::new(Ristretto255);
+        let kdf = KdfParams::generate();
+        let x = kdf.derive_secret(TEST_PASSWORD, &Ristretto255).unwrap();
+
+        let mut client = Client::new(addr, TEST_USER.to_string()).await;
+        client.register_secret(&cp, &x, &kdf).await.unwrap();
+
+        let nonce = client.get_proof_nonce().await.unwrap().nonce;
+        let ctx = ProofContext {
+            purpose: LOGIN_PURPOSE,
+            user: TEST_USER,
+            nonce: &nonce,
+            context: b"",
+        };
+        let proof = cp.prove_non_interactive(&x, &ctx);
+        let request = ProveRequest {
+            user: TEST_USER.to_string(),
+            nonce: nonce.clone(),
+            context: vec![],
+            r1: Ristretto255.encode_element(&proof.r1),
+            r2: Ristretto255.encode_element(&proof.r2),
+            c: vec![],
+            s: Ristretto255.encode_scalar(&proof.s),
+        };
+
+        assert!(client.prove(request.clone()).await.is_ok());
+        // Replaying the same proof must fail, as the nonce has been consumed
+        let e = client.prove(request).await.unwrap_err();
+        assert_eq!(
+            e.downcast_ref::<ZkpAuthError>(),
+            Some(&ZkpAuthError::NonceNotFound)
+        );
+
+        server_thread.abort();
+    }
+
+    #[tokio::test]
+    async fn test_end_to_end_short_challenges() {
+        let addr = "127.0.0.1:8187";
+        let options = ServerOptions {
+            challenge_bits: Some(128),
+            ..Default::default()
+        };
+        let server_thread = tokio::spawn(run_server_with_options(addr, Backend::P256, options));
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let test_password = TEST_PASSWORD;
+        let res = run_client_auth_check(
+            addr,
+            TEST_USER,
+            test_password,
+            Backend::P256,
+            ProofMode::Interactive,
+        )
+        .await;
+        assert!(res.is_ok());
+
+        server_thread.abort();
+    }
+
+    #[tokio::test]
+    async fn test_server_rejects_oversized_challenges() {
+        // q = 5003 is only 13 bits
+        let options = ServerOptions {
+            challenge_bits: Some(14),
+            ..Default::default()
+        };
+        let res = run_server_with_options("127.0.0.1:8188", create_test_params(), options).await;
+        assert!(res.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_password_login() {
+        use zkp_auth::client::Client;
+        use zkp_auth::group::{ChaumPedersen, Group, P256};
+        use zkp_auth::kdf::KdfParams;
+
+        let addr = "127.0.0.1:8189";
+        let server_thread = tokio::spawn(run_server(addr, Backend::P256));
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let mut client = Client::new(addr, TEST_USER.to_string()).await;
+        client
+            .register_with_password(&P256, TEST_PASSWORD)
+            .await
+            .unwrap();
+
+        assert!(client
+            .login(P256, "Tr0ub4dor&3", ProofMode::Interactive)
+            .await
+            .is_err());
+        assert!(client
+            .login(P256, TEST_PASSWORD, ProofMode::Interactive)
+            .await
+            .is_ok());
+
+        // The server refuses KDF parameters weaker than the minimum
+        let weak = KdfParams {
+            memory_kib: 1024,
+            ..KdfParams::generate()
+        };
+        let mut weak_client = Client::new(addr, "weak_user".to_string()).await;
+        assert!(weak_client
+            .register_secret(&ChaumPedersen::new(P256), &P256.random_scalar(), &weak)
+            .await
+            .is_err());
+
+        server_thread.abort();
+    }
+
+    #[tokio::test]
+    async fn test_custom_store() {
+        use std::sync::Arc;
+        use zkp_auth::db::{AuthStore, InMemoryDB};
+
+        let addr = "127.0.0.1:8190";
+        let store = Arc::new(InMemoryDB::new());
+        let options = ServerOptions {
+            store: Some(store.clone()),
+            ..Default::default()
+        };
+        let server_thread = tokio::spawn(run_server_with_options(addr, Backend::P256, options));
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let session_id = run_client_auth_check(
+            addr,
+            TEST_USER,
+            TEST_PASSWORD,
+            Backend::P256,
+            ProofMode::FiatShamir,
+        )
+        .await
+        .unwrap();
+
+        // The server kept its state in the store it was given
+        assert!(store.get_user(TEST_USER).await.unwrap().is_some());
+        let sessions = store.list_sessions(TEST_USER).await.unwrap();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].session_id, session_id);
+
+        server_thread.abort();
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_survives_restart() {
+        use zkp_auth::client::Client;
+        use zkp_auth::db::open_store;
+        use zkp_auth::group::P256;
+
+        let dir = tempfile::tempdir().unwrap();
+        let url = format!("sqlite://{}", dir.path().join("zkp-auth.db").display());
+
+        let options = ServerOptions {
+            store: Some(open_store(&url).unwrap()),
+            ..Default::default()
+        };
+        let server_thread = tokio::spawn(run_server_with_options(
+            "127.0.0.1:8191",
+            Backend::P256,
+            options,
+        ));
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let mut client = Client::new("127.0.0.1:8191", TEST_USER.to_string()).await;
+        client
+            .register_with_password(&P256, TEST_PASSWORD)
+            .await
+            .unwrap();
+        server_thread.abort();
+
+        // A new server on the same database still knows the user
+        let options = ServerOptions {
+            store: Some(open_store(&url).unwrap()),
+            ..Default::default()
+        };
+        let server_thread = tokio::spawn(run_server_with_options(
+            "127.0.0.1:8192",
+            Backend::P256,
+            options,
+        ));
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let mut client = Client::new("127.0.0.1:8192", TEST_USER.to_string()).await;
+        assert!(client
+            .login(P256, TEST_PASSWORD, ProofMode::Interactive)
+            .await
+            .is_ok());
+
+        server_thread.abort();
+    }
+
+    /// Registers TEST_USER on P256 and returns their secret
+    async fn register_p256(
+        client: &mut zkp_auth::client::Client,
+    ) -> <zkp_auth::group::P256 as zkp_auth::group::Group>::Scalar {
+        use zkp_auth::group::{ChaumPedersen, P256};
+        use zkp_auth::kdf::KdfParams;
+
+        let kdf = KdfParams::generate();
+        let x = kdf.derive_secret(TEST_PASSWORD, &P256).unwrap();
+        client
+            .register_secret(&ChaumPedersen::new(P256), &x, &kdf)
+            .await
+            .unwrap();
+        x
+    }
+
+    #[tokio::test]
+    async fn test_challenge_is_single_use() {
+        use zkp_auth::client::Client;
+        use zkp_auth::error::ZkpAuthError;
+        use zkp_auth::group::{ChaumPedersen, Group, P256};
+
+        let addr = "127.0.0.1:8193";
+        let server_thread = tokio::spawn(run_server(addr, Backend::P256));
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let mut client = Client::new(addr, TEST_USER.to_string()).await;
+        let x = register_p256(&mut client).await;
+        let cp = ChaumPedersen::new(P256);
+
+        let k = cp.random_nonce();
+        let (r1, r2) = cp.r1_r2(&k);
+        let challenge = client
+            .create_authentication_challenge(P256.encode_element(&r1), P256.encode_element(&r2))
+            .await
+            .unwrap();
+        let c = P256.decode_scalar(&challenge.c).unwrap();
+        let s = P256.encode_scalar(&cp.s(&k, &c, &x));
+
+        let answer = client
+            .verify_authentication(s.clone(), challenge.auth_id.clone())
+            .await
+            .unwrap();
+        // Tokens are only issued when the server has a signing key
+        assert!(answer.token.is_empty());
+        assert!(client.get_public_keys().await.unwrap().is_empty());
+        // The admin service is only served with an admin token
+        let mut admin = zkp_auth::client::AdminClient::new(addr, "correct horse battery staple")
+            .await
+            .unwrap();
+        assert!(admin.list_users().await.is_err());
+        // Replaying a valid answer must fail, as the challenge has been consumed
+        let e = client
+            .verify_authentication(s, challenge.auth_id.clone())
+            .await
+            .unwrap_err();
+        assert_eq!(
+            e.downcast_ref::<ZkpAuthError>(),
+            Some(&ZkpAuthError::ChallengeNotFound {
+                auth_id: challenge.auth_id
+            })
+        );
+
+        // A wrong answer consumes the challenge too, so it can't be retried
+        let k = cp.random_nonce();
+        let (r1, r2) = cp.r1_r2(&k);
+        let challenge = client
+            .create_authentication_challenge(P256.encode_element(&r1), P256.encode_element(&r2))
+            .await
+            .unwrap();
+        let c = P256.decode_scalar(&challenge.c).unwrap();
+        let wrong = P256.encode_scalar(&cp.s(&k, &c, &P256.random_scalar()));
+        let right = P256.encode_scalar(&cp.s(&k, &c, &x));
+        assert!(client
+            .verify_authentication(wrong, challenge.auth_id.clone())
+            .await
+            .is_err());
+        assert!(client
+            .verify_authentication(right, challenge.auth_id)
+            .await
+            .is_err());
+
+        server_thread.abort();
+    }
+
+    #[tokio::test]
+    async fn test_expired_challenge_is_rejected() {
+        use std::sync::Arc;
+        use zkp_auth::client::Client;
+        use zkp_auth::db::{AuthStore, InMemoryDB};
+        use zkp_auth::error::ZkpAuthError;
+        use zkp_auth::group::{ChaumPedersen, Group, P256};
+        use zkp_auth::server::ZkpAuthService;
+
+        let addr = "127.0.0.1:8194";
+        let store = Arc::new(InMemoryDB::new());
+        let options = ServerOptions {
+            store: Some(store.clone()),
+            challenge_ttl: Some(Duration::from_secs(1)),
+            ..Default::default()
+        };
+        let server_thread = tokio::spawn(run_server_with_options(
+            addr,
+            Backend::P256,
+            options.clone(),
+        ));
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let mut client = Client::new(addr, TEST_USER.to_string()).await;
+        let x = register_p256(&mut client).await;
+        let cp = ChaumPedersen::new(P256);
+
+        let k = cp.random_nonce();
+        let (r1, r2) = cp.r1_r2(&k);
+        let challenge = client
+            .create_authentication_challenge(P256.encode_element(&r1), P256.encode_element(&r2))
+            .await
+            .unwrap();
+        let c = P256.decode_scalar(&challenge.c).unwrap();
+        let s = P256.encode_scalar(&cp.s(&k, &c, &x));
+
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        let e = client
+            .verify_authentication(s.clone(), challenge.auth_id.clone())
+            .await
+            .unwrap_err();
+        assert_eq!(
+            e.downcast_ref::<ZkpAuthError>(),
+            Some(&ZkpAuthError::ChallengeExpired {
+                auth_id: challenge.auth_id
+            })
+        );
+
+        let challenge = client
+            .create_authentication_challenge(P256.encode_element(&r1), P256.encode_element(&r2))
+            .await
+            .unwrap();
+        let auth_id = challenge.auth_id;
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+
+        // Garbage collection removes the expired challenge from the store
+        assert!(store.get_challenge(&auth_id).await.unwrap().is_some());
+        ZkpAuthService::with_options(P256, options)
+            .unwrap()
+            .collect_garbage()
+            .await
+            .unwrap();
+        assert!(store.get_challenge(&auth_id).await.unwrap().is_none());
+
+        // Once it's gone, answering it reports a missing challenge rather than a bare status
+        let e = client
+            .verify_authentication(s, auth_id.clone())
+            .await
+            .unwrap_err();
+        assert_eq!(
+            e.downcast_ref::<ZkpAuthError>(),
+            Some(&ZkpAuthError::ChallengeNotFound { auth_id })
+        );
+
+        server_thread.abort();
+    }
+
+    #[tokio::test]
+    async fn test_session_lifecycle() {
+        use zkp_auth::client::Client;
+
+        let addr = "127.0.0.1:8195";
+        let server_thread = tokio::spawn(run_server(addr, Backend::P256));
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let first = run_client_auth_check(
+            addr,
+            TEST_USER,
+            TEST_PASSWORD,
+            Backend::P256,
+            ProofMode::Interactive,
+        )
+        .await
+        .unwrap();
+        let second = run_client_auth_check(
+            addr,
+            TEST_USER,
+            TEST_PASSWORD,
+            Backend::P256,
+            ProofMode::FiatShamir,
+        )
+        .await
+        .unwrap();
+
+        let mut client = Client::new(addr, TEST_USER.to_string()).await;
+        let validated = client.validate_session(&first).await.unwrap();
+        assert_eq!(validated.user, TEST_USER);
+        assert!(validated.expires_in_secs > 0);
+        assert!(client.validate_session("not-a-session").await.is_err());
+
+        let sessions = client.list_sessions(&first).await.unwrap();
+        assert_eq!(sessions.len(), 2);
+        assert!(sessions.iter().any(|s| s.session_id == first && s.current));
+        assert!(sessions
+            .iter()
+            .any(|s| s.session_id == second && !s.current));
+
+        // Refreshing replaces the session
+        let refreshed = client.refresh_session(&first).await.unwrap().session_id;
+        assert_ne!(refreshed, first);
+        assert!(client.validate_session(&first).await.is_err());
+        assert!(client.validate_session(&refreshed).await.is_ok());
+
+        assert_eq!(client.logout(&refreshed, false).await.unwrap(), 1);
+        assert!(client.validate_session(&refreshed).await.is_err());
+        assert!(client.validate_session(&second).await.is_ok());
+
+        let third = run_client_auth_check(
+            addr,
+            TEST_USER,
+            TEST_PASSWORD,
+            Backend::P256,
+            ProofMode::FiatShamirCompact,
+        )
+        .await
+        .unwrap();
+        assert_eq!(client.logout(&second, true).await.unwrap(), 2);
+        assert!(client.validate_session(&third).await.is_err());
+
+        server_thread.abort();
+    }
+
+    #[tokio::test]
+    async fn test_session_expiry() {
+        use zkp_auth::client::Client;
+
+        let addr = "127.0.0.1:8196";
+        let options = ServerOptions {
+            session_lifetime: Some(Duration::from_secs(2)),
+            session_idle_timeout: Some(Duration::from_secs(1)),
+            ..Default::default()
+        };
+        let server_thread = tokio::spawn(run_server_with_options(addr, Backend::P256, options));
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let mut client = Client::new(addr, TEST_USER.to_string()).await;
+        let login = || {
+            run_client_auth_check(
+                addr,
+                TEST_USER,
+                TEST_PASSWORD,
+                Backend::P256,
+                ProofMode::FiatShamir,
+            )
+        };
+
+        // A session left idle ends
+        let idle = login().await.unwrap();
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        // And can't be used to log the user's other sessions out
+        let active = login().await.unwrap();
+        assert!(client.logout(&idle, true).await.is_err());
+        assert!(client.validate_session(&active).await.is_ok());
+        assert!(client.validate_session(&idle).await.is_err());
+
+        // A session in use still ends at its expiry
+        let busy = login().await.unwrap();
+        for _ in 0..4 {
+            assert!(client.validate_session(&busy).await.is_ok());
+            tokio::time::sleep(Duration::from_millis(500)).await;
+        }
+        tokio::time::sleep(Duration::from_millis(600)).await;
+        assert!(client.validate_session(&busy).await.is_err());
+
+        server_thread.abort();
+    }
+
+    #[tokio::test]
+    async fn test_signed_session_tokens() {
+        use std::sync::Arc;
+        use zkp_auth::client::Client;
+        use zkp_auth::group::{ChaumPedersen, Group, P256};
+        use zkp_auth::token::{verify_token, TokenSigner};
+
+        let addr = "127.0.0.1:8197";
+        let options = ServerOptions {
+            token_signer: Some(Arc::new(TokenSigner::generate())),
+            ..Default::default()
+        };
+        let server_thread = tokio::spawn(run_server_with_options(addr, Backend::P256, options));
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let mut client = Client::new(addr, TEST_USER.to_string()).await;
+        let x = register_p256(&mut client).await;
+        let cp = ChaumPedersen::new(P256);
+
+        let k = cp.random_nonce();
+        let (r1, r2) = cp.r1_r2(&k);
+        let challenge = client
+            .create_authentication_challenge(P256.encode_element(&r1), P256.encode_element(&r2))
+            .await
+            .unwrap();
+        let c = P256.decode_scalar(&challenge.c).unwrap();
+        let answer = client
+            .verify_authentication(
+                P256.encode_scalar(&cp.s(&k, &c, &x)),
+                challenge.auth_id.clone(),
+            )
+            .await
+            .unwrap();
+
+        // The token can be checked without calling back to the server
+        let keys = client.get_public_keys().await.unwrap();
+        assert_eq!(keys.len(), 1);
+        let claims = verify_token(&answer.token, &keys).unwrap();
+        assert_eq!(claims.sub, TEST_USER);
+        assert_eq!(claims.sid, answer.session_id);
+        assert_eq!(claims.auth_id, Some(challenge.auth_id));
+        assert_eq!(claims.fpr, hex::encode(P256.fingerprint()));
+        assert!(claims.exp > claims.iat);
+
+        let refreshed = client.refresh_session(&answer.session_id).await.unwrap();
+        let claims = verify_token(&refreshed.token, &keys).unwrap();
+        assert_eq!(claims.sid, refreshed.session_id);
+        assert_eq!(claims.auth_id, None);
+
+        // A token from another server's key is rejected
+        let other = TokenSigner::generate();
+        assert!(verify_token(&answer.token, &[other.public_key().clone()]).is_err());
+
+        server_thread.abort();
+    }
+
+    #[tokio::test]
+    async fn test_rotate_credentials() {
+        use std::sync::Arc;
+        use zkp_auth::client::run_client_rotate;
+        use zkp_auth::client::zkp_auth::RotateCredentialsRequest;
+        use zkp_auth::client::Client;
+        use zkp_auth::db::{AuthStore, InMemoryDB};
+        use zkp_auth::group::{ChaumPedersen, Group, ProofContext, P256};
+        use zkp_auth::server::{credentials_context, ROTATE_PURPOSE};
+
+        let addr = "127.0.0.1:8198";
+        let store = Arc::new(InMemoryDB::new());
+        let options = ServerOptions {
+            store: Some(store.clone()),
+            ..Default::default()
+        };
+        let server_thread = tokio::spawn(run_server_with_options(addr, Backend::P256, options));
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let mut client = Client::new(addr, TEST_USER.to_string()).await;
+        let x = register_p256(&mut client).await;
+        let session_id = client
+            .login(P256, TEST_PASSWORD, ProofMode::FiatShamir)
+            .await
+            .unwrap();
+
+        // A proof is bound to the new credentials it was made for
+        let cp = ChaumPedersen::new(P256);
+        let new_y = P256.encode_element(&P256.g());
+        let new_kdf = zkp_auth::kdf::KdfParams::generate();
+        let nonce = client.get_proof_nonce().await.unwrap().nonce;
+        let context = credentials_context(&new_y, &new_y, &new_kdf);
+        let proof = cp.prove_non_interactive(
+            &x,
+            &ProofContext {
+                purpose: ROTATE_PURPOSE,
+                user: TEST_USER,
+                nonce: &nonce,
+                context: &context,
+            },
+        );
+        let other_y = P256.encode_element(&P256.h());
+        assert!(client
+            .rotate_credentials(RotateCredentialsRequest {
+                user: TEST_USER.to_string(),
+                nonce,
+                c: P256.encode_scalar(&proof.c),
+                s: P256.encode_scalar(&proof.s),
+                new_y1: other_y.clone(),
+                new_y2: other_y,
+                new_kdf: Some(zkp_auth::client::zkp_auth::KdfParameters {
+                    algorithm: zkp_auth::kdf::ARGON2ID.to_string(),
+                    salt: new_kdf.salt.clone(),
+                    memory_kib: new_kdf.memory_kib,
+                    iterations: new_kdf.iterations,
+                    parallelism: new_kdf.parallelism,
+                }),
+                ..Default::default()
+            })
+            .await
+            .is_err());
+
+        assert!(
+            run_client_rotate(addr, TEST_USER, "wrong", "new password", Backend::P256)
+                .await
+                .is_err()
+        );
+        assert!(store
+            .list_credential_rotations(TEST_USER)
+            .await
+            .unwrap()
+            .is_empty());
+
+        let revoked = run_client_rotate(
+            addr,
+            TEST_USER,
+            TEST_PASSWORD,
+            "new password",
+            Backend::P256,
+        )
+        .await
+        .unwrap();
+        assert_eq!(revoked, 1);
+        assert!(client.validate_session(&session_id).await.is_err());
+        assert_eq!(
+            store
+                .list_credential_rotations(TEST_USER)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+
+        assert!(client
+            .login(P256, TEST_PASSWORD, ProofMode::Interactive)
+            .await
+            .is_err());
+        assert!(client
+            .login(P256, "new password", ProofMode::Interactive)
+            .await
+            .is_ok());
+
+        server_thread.abort();
+    }
+
+    #[tokio::test]
+    async fn test_admin_service() {
+        use zkp_auth::client::{AdminClient, Client};
+        use zkp_auth::error::ZkpAuthError;
+        use zkp_auth::group::P256;
+
+        let addr = "127.0.0.1:8199";
+        let admin_token = "an admin token that is long enough";
+        let options = ServerOptions {
+            admin_token: Some(admin_token.to_string()),
+            ..Default::default()
+        };
+        let server_thread = tokio::spawn(run_server_with_options(addr, Backend::P256, options));
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let login = |user: &'static str| {
+            run_client_auth_check(
+                addr,
+                user,
+                TEST_PASSWORD,
+                Backend::P256,
+                ProofMode::FiatShamir,
+            )
+        };
+        let alice_session = login("alice").await.unwrap();
+        login("bob").await.unwrap();
+
+        let mut wrong = AdminClient::new(addr, "not the admin token").await.unwrap();
+        assert!(wrong.list_users().await.is_err());
+        assert!(wrong.lock_user("alice").await.is_err());
+
+        let mut admin = AdminClient::new(addr, admin_token).await.unwrap();
+        let users = admin.list_users().await.unwrap();
+        assert_eq!(
+            users.iter().map(|u| u.user.as_str()).collect::<Vec<_>>(),
+            ["alice", "bob"]
+        );
+        assert!(users.iter().all(|u| !u.locked && u.active_sessions == 1));
+
+        // A locked user loses their sessions and can't log in until unlocked
+        assert_eq!(admin.lock_user("alice").await.unwrap(), 1);
+        assert!(admin.get_user("alice").await.unwrap().locked);
+        let mut alice = Client::new(addr, "alice".to_string()).await;
+        assert!(alice.validate_session(&alice_session).await.is_err());
+        assert!(alice
+            .login(P256, TEST_PASSWORD, ProofMode::Interactive)
+            .await
+            .is_err());
+        admin.unlock_user("alice").await.unwrap();
+        assert!(alice
+            .login(P256, TEST_PASSWORD, ProofMode::Interactive)
+            .await
+            .is_ok());
+
+        assert_eq!(admin.revoke_all_sessions("alice").await.unwrap(), 1);
+        assert_eq!(admin.get_user("alice").await.unwrap().active_sessions, 0);
+
+        // A deleted user is gone, and the name can be registered again
+        assert_eq!(admin.delete_user("bob").await.unwrap(), 1);
+        let not_found = |user: &str| {
+            Some(ZkpAuthError::UserNotFound {
+                user: user.to_string(),
+            })
+        };
+        let e = admin.get_user("bob").await.unwrap_err();
+        assert_eq!(e.downcast_ref::<ZkpAuthError>().cloned(), not_found("bob"));
+        let e = admin.delete_user("bob").await.unwrap_err();
+        assert_eq!(e.downcast_ref::<ZkpAuthError>().cloned(), not_found("bob"));
+        let e = admin.lock_user("nobody").await.unwrap_err();
+        assert_eq!(
+            e.downcast_ref::<ZkpAuthError>().cloned(),
+            not_found("nobody")
+        );
+        let e = admin.unlock_user("nobody").await.unwrap_err();
+        assert_eq!(
+            e.downcast_ref::<ZkpAuthError>().cloned(),
+            not_found("nobody")
+        );
+        login("bob").await.unwrap();
+
+        server_thread.abort();
+    }
+
+    /// The gRPC status code a client call failed with
+    fn status_code(e: &anyhow::Error) -> Option<tonic::Code> {
+        use zkp_auth::error::ZkpAuthError;
+
+        e.downcast_ref::<ZkpAuthError>()
+            .map(ZkpAuthError::code)
+            .or_else(|| e.downcast_ref::<tonic::Status>().map(|s| s.code()))
+    }
+
+    #[tokio::test]
+    async fn test_brute_force_lockout() {
+        use zkp_auth::client::{AdminClient, Client};
+        use zkp_auth::error::ZkpAuthError;
+        use zkp_auth::group::P256;
+        use zkp_auth::server::lockout::LockoutPolicy;
+
+        let addr = "127.0.0.1:8200";
+        let admin_token = "an admin token that is long enough";
+        let options = ServerOptions {
+            admin_token: Some(admin_token.to_string()),
+            lockout_policy: Some(LockoutPolicy {
+                max_failures: 3,
+                base_delay: Duration::from_secs(1),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let server_thread = tokio::spawn(run_server_with_options(addr, Backend::P256, options));
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        run_client_auth_check(
+            addr,
+            "alice",
+            TEST_PASSWORD,
+            Backend::P256,
+            ProofMode::FiatShamir,
+        )
+        .await
+        .unwrap();
+        let mut alice = Client::new(addr, "alice".to_string()).await;
+        let mut admin = AdminClient::new(addr, admin_token).await.unwrap();
+
+        // Three wrong passwords lock alice out, even with the right one
+        for mode in [
+            ProofMode::FiatShamir,
+            ProofMode::Interactive,
+            ProofMode::FiatShamir,
+        ] {
+            let e = alice.login(P256, "wrong password", mode).await.unwrap_err();
+            assert_eq!(
+                e.downcast_ref::<ZkpAuthError>(),
+                Some(&ZkpAuthError::ProofInvalid {
+                    user: "alice".to_string()
+                })
+            );
+        }
+        let e = alice
+            .login(P256, TEST_PASSWORD, ProofMode::FiatShamir)
+            .await
+            .unwrap_err();
+        assert_eq!(status_code(&e), Some(tonic::Code::ResourceExhausted));
+        assert!(matches!(
+            e.downcast_ref::<ZkpAuthError>(),
+            Some(ZkpAuthError::Locked {
+                retry_after: Some(_),
+                ..
+            })
+        ));
+        assert!(admin.get_user("alice").await.unwrap().locked_out_until > 0);
+
+        // The lockout ends by itself
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+        assert!(alice
+            .login(P256, TEST_PASSWORD, ProofMode::FiatShamir)
+            .await
+            .is_ok());
+        assert_eq!(admin.get_user("alice").await.unwrap().locked_out_until, 0);
+
+        // Or when an admin unlocks the user
+        for _ in 0..3 {
+            assert!(alice
+                .login(P256, "wrong password", ProofMode::FiatShamir)
+                .await
+                .is_err());
+        }
+        assert!(alice
+            .login(P256, TEST_PASSWORD, ProofMode::FiatShamir)
+            .await
+            .is_err());
+        admin.unlock_user("alice").await.unwrap();
+        assert!(alice
+            .login(P256, TEST_PASSWORD, ProofMode::FiatShamir)
+            .await
+            .is_ok());
+
+        let mut nobody = Client::new(addr, "nobody".to_string()).await;
+        let e = nobody
+            .login(P256, TEST_PASSWORD, ProofMode::FiatShamir)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            e.downcast_ref::<ZkpAuthError>(),
+            Some(&ZkpAuthError::UserNotFound {
+                user: "nobody".to_string()
+            })
+        );
+
+        server_thread.abort();
+    }
+
+    #[tokio::test]
+    async fn test_rate_limits() {
+        use zkp_auth::client::Client;
+        use zkp_auth::group::P256;
+        use zkp_auth::server::rate_limit::{Quota, RateLimits};
+
+        let addr = "127.0.0.1:8201";
+        let options = ServerOptions {
+            rate_limits: Some(RateLimits {
+                per_user: Some(Quota::per_minute(3)),
+                ..RateLimits::unlimited()
+            }),
+            ..Default::default()
+        };
+        let server_thread = tokio::spawn(run_server_with_options(addr, Backend::P256, options));
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let register = |user: &'static str| {
+            run_client_auth_check(
+                addr,
+                user,
+                TEST_PASSWORD,
+                Backend::P256,
+                ProofMode::FiatShamir,
+            )
+        };
+        register("alice").await.unwrap();
+
+        // Each proof is an attempt, whether or not it succeeds
+        let mut alice = Client::new(addr, "alice".to_string()).await;
+        assert!(alice
+            .login(P256, TEST_PASSWORD, ProofMode::FiatShamir)
+            .await
+            .is_ok());
+        assert!(alice
+            .login(P256, "wrong password", ProofMode::FiatShamir)
+            .await
+            .is_err());
+        let e = alice
+            .login(P256, TEST_PASSWORD, ProofMode::FiatShamir)
+            .await
+            .unwrap_err();
+        assert_eq!(status_code(&e), Some(tonic::Code::ResourceExhausted));
+
+        // Other users have their own limit
+        register("bob").await.unwrap();
+
+        server_thread.abort();
+    }
+
+    /// Writes a fresh CA, a server certificate for 127.0.0.1 and a client certificate, all PEM encoded, to dir
+    fn generate_certificates(dir: &std::path::Path) {
+        use rcgen::{BasicConstraints, Certificate, CertificateParams, IsCa};
+
+        let mut ca_params = CertificateParams::new(vec![]);
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let ca = Certificate::from_params(ca_params).unwrap();
+        std::fs::write(dir.join("ca.pem"), ca.serialize_pem().unwrap()).unwrap();
+
+        for (name, san) in [("server", "127.0.0.1"), ("client", "client")] {
+            let cert =
+                Certificate::from_params(CertificateParams::new(vec![san.to_string()])).unwrap();
+            std::fs::write(
+                dir.join(format!("{}.pem", name)),
+                cert.serialize_pem_with_signer(&ca).unwrap(),
+            )
+            .unwrap();
+            std::fs::write(
+                dir.join(format!("{}.key", name)),
+                cert.serialize_private_key_pem(),
+            )
+            .unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mutual_tls() {
+        use zkp_auth::client::{run_client_auth_check_with_options, Client, ClientOptions};
+        use zkp_auth::group::P256;
+        use zkp_auth::tls::{client_tls_config, server_tls_config};
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        generate_certificates(dir.path());
+
+        let addr = "127.0.0.1:8202";
+        let options = ServerOptions {
+            tls: Some(
+                server_tls_config(path("server.pem"), path("server.key"), Some(path("ca.pem")))
+                    .unwrap(),
+            ),
+            ..Default::default()
+        };
+        let server_thread = tokio::spawn(run_server_with_options(addr, Backend::P256, options));
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let with_client_cert = client_tls_config(
+            path("ca.pem"),
+            Some((path("client.pem"), path("client.key"))),
+        )
+        .unwrap();
+        let session_id = run_client_auth_check_with_options(
+            addr,
+            TEST_USER,
+            TEST_PASSWORD,
+            Backend::P256,
+            ProofMode::Interactive,
+            ClientOptions {
+                tls: Some(with_client_cert.clone()),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        let mut client = Client::connect(addr, TEST_USER.to_string(), Some(with_client_cert))
+            .await
+            .unwrap();
+        assert_eq!(
+            client.validate_session(&session_id).await.unwrap().user,
+            TEST_USER
+        );
+
+        // Clients without a certificate signed by the CA are refused
+        let without_client_cert = client_tls_config(path("ca.pem"), None::<(&str, &str)>).unwrap();
+        assert!(run_client_auth_check_with_options(
+            addr,
+            TEST_USER,
+            TEST_PASSWORD,
+            Backend::P256,
+            ProofMode::Interactive,
+            ClientOptions {
+                tls: Some(without_client_cert),
+                ..Default::default()
+            },
+        )
+        .await
+        .is_err());
+
+        // So are plaintext clients
+        assert!(Client::connect(addr, TEST_USER.to_string(), None)
+            .await
+            .unwrap()
+            .login(P256, TEST_PASSWORD, ProofMode::Interactive)
+            .await
+            .is_err());
+
+        // And the server's certificate must be signed by the CA the client trusts
+        let other_dir = tempfile::tempdir().unwrap();
+        generate_certificates(other_dir.path());
+        let other_ca = client_tls_config(
+            other_dir.path().join("ca.pem"),
+            Some((path("client.pem"), path("client.key"))),
+        )
+        .unwrap();
+        assert!(run_client_auth_check_with_options(
+            addr,
+            TEST_USER,
+            TEST_PASSWORD,
+            Backend::P256,
+            ProofMode::Interactive,
+            ClientOptions {
+                tls: Some(other_ca),
+                ..Default::default()
+            },
+        )
+        .await
+        .is_err());
+
+        server_thread.abort();
+    }
+
+    #[tokio::test]
+    async fn test_restricted_registration() {
+        use zkp_auth::client::zkp_auth::RegisterRequest;
+        use zkp_auth::client::{
+            run_client_auth_check_with_options, AdminClient, Client, ClientOptions,
+        };
+        use zkp_auth::error::ZkpAuthError;
+        use zkp_auth::group::{ChaumPedersen, Group, ProofContext, P256};
+        use zkp_auth::kdf::KdfParams;
+        use zkp_auth::server::enrollment::RegistrationPolicy;
+        use zkp_auth::server::{credentials_context, REGISTER_PURPOSE};
+
+        let addr = "127.0.0.1:8203";
+        let admin_token = "an admin token that is long enough";
+        let enrollment_token = "an enrollment token that is long enough";
+        let options = ServerOptions {
+            admin_token: Some(admin_token.to_string()),
+            registration: Some(RegistrationPolicy {
+                enrollment_token: Some(enrollment_token.to_string()),
+                invites: true,
+            }),
+            ..Default::default()
+        };
+        let server_thread = tokio::spawn(run_server_with_options(addr, Backend::P256, options));
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let register = |user: &'static str, enrollment_code: Option<String>| {
+            run_client_auth_check_with_options(
+                addr,
+                user,
+                TEST_PASSWORD,
+                Backend::P256,
+                ProofMode::FiatShamir,
+                ClientOptions {
+                    enrollment_code,
+                    ..Default::default()
+                },
+            )
+        };
+
+        // Without the token or an invite, nobody can register, so the login that follows finds no user
+        let e = register("alice", None).await.unwrap_err();
+        assert!(format!("{:#}", e).contains("does not exist"));
+        assert!(register("alice", Some("not the token".to_string()))
+            .await
+            .is_err());
+        register("alice", Some(enrollment_token.to_string()))
+            .await
+            .unwrap();
+
+        // Invites are single-use
+        let mut admin = AdminClient::new(addr, admin_token).await.unwrap();
+        let invite = admin.create_invite(0).await.unwrap();
+        assert!(invite.expires_in_secs > 0);
+        register("bob", Some(invite.code.clone())).await.unwrap();
+        assert!(register("carol", Some(invite.code)).await.is_err());
+
+        // A proof made for other credentials or another user doesn't register anyone
+        let cp = ChaumPedersen::new(P256);
+        let kdf = KdfParams::generate();
+        let x = P256.random_scalar();
+        let (y1, y2) = cp.y1_y2(&x);
+        let (y1, y2) = (P256.encode_element(&y1), P256.encode_element(&y2));
+        let mut mallory = Client::new(addr, "mallory".to_string()).await;
+        for (user, y2_proven) in [("mallory", y1.clone()), ("dave", y2.clone())] {
+            let nonce = mallory.get_proof_nonce().await.unwrap().nonce;
+            let context = credentials_context(&y1, &y2_proven, &kdf);
+            let ctx = ProofContext {
+                purpose: REGISTER_PURPOSE,
+                user,
+                nonce: &nonce,
+                context: &context,
+            };
+            let proof = cp.prove_non_interactive(&x, &ctx);
+            let e = mallory
+                .register(RegisterRequest {
+                    user: "mallory".to_string(),
+                    y1: y1.clone(),
+                    y2: y2.clone(),
+                    kdf: Some(zkp_auth::client::zkp_auth::KdfParameters {
+                        algorithm: zkp_auth::kdf::ARGON2ID.to_string(),
+                        salt: kdf.salt.clone(),
+                        memory_kib: kdf.memory_kib,
+                        iterations: kdf.iterations,
+                        parallelism: kdf.parallelism,
+                    }),
+                    nonce,
+                    c: P256.encode_scalar(&proof.c),
+                    s: P256.encode_scalar(&proof.s),
+                    enrollment_code: enrollment_token.to_string(),
+                    ..Default::default()
+                })
+                .await
+                .unwrap_err();
+            assert_eq!(status_code(&e), Some(tonic::Code::InvalidArgument));
+            assert_eq!(
+                e.downcast_ref::<ZkpAuthError>(),
+                Some(&ZkpAuthError::RegistrationProofInvalid {
+                    user: "mallory".to_string()
+                })
+            );
+        }
+        mallory.set_enrollment_code(enrollment_token);
+        mallory.register_secret(&cp, &x, &kdf).await.unwrap();
+
+        server_thread.abort();
+    }
+
+    #[tokio::test]
+    async fn test_rejects_invalid_elements() {
+        use num::{BigInt, One};
+        use zkp_auth::chaum_pedersen::{encode_fixed, NamedGroup};
+        use zkp_auth::client::zkp_auth::RegisterRequest;
+        use zkp_auth::client::Client;
+        use zkp_auth::group::{ChaumPedersen, Group};
+        use zkp_auth::kdf::KdfParams;
+
+        let addr = "127.0.0.1:8204";
+        let params = NamedGroup::Rfc5114_2048_256.params();
+        let server_thread = tokio::spawn(run_server(addr, params.clone()));
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let cp = ChaumPedersen::new(params.clone());
+        let kdf = KdfParams::generate();
+        let encode = |v: &BigInt| encode_fixed(v, params.element_len());
+        let p_minus_one = &params.p - BigInt::one();
+        // Has order dividing (p-1)/q, so it lies in a small subgroup
+        let small_order = BigInt::from(2).modpow(&params.q, &params.p);
+        let invalid = [
+            BigInt::from(0),
+            BigInt::one(),
+            params.p.clone(),
+            p_minus_one,
+            small_order,
+        ];
+
+        // x = 0 gives y1 = y2 = 1, which any secret would prove knowledge of
+        let mut client = Client::new(addr, "identity".to_string()).await;
+        let res = client
+            .register_secret(&cp, &params.scalar_from_bigint(&BigInt::from(0)), &kdf)
+            .await
+            .unwrap_err();
+        assert_eq!(status_code(&res), Some(tonic::Code::InvalidArgument));
+
+        let mut client = Client::new(addr, TEST_USER.to_string()).await;
+        let valid = encode(&cp.y1_y2(&params.scalar_from_bigint(&BigInt::from(64))).0);
+        for v in &invalid {
+            for (y1, y2) in [(encode(v), valid.clone()), (valid.clone(), encode(v))] {
+                let nonce = client.get_proof_nonce().await.unwrap().nonce;
+                let res = client
+                    .register(RegisterRequest {
+                        user: TEST_USER.to_string(),
+                        y1,
+                        y2,
+                        kdf: Some(zkp_auth::client::zkp_auth::KdfParameters {
+                            algorithm: zkp_auth::kdf::ARGON2ID.to_string(),
+                            salt: kdf.salt.clone(),
+                            memory_kib: kdf.memory_kib,
+                            iterations: kdf.iterations,
+                            parallelism: kdf.parallelism,
+                        }),
+                        nonce,
+                        ..Default::default()
+                    })
+                    .await
+                    .unwrap_err();
+                assert_eq!(
+                    status_code(&res),
+                    Some(tonic::Code::InvalidArgument),
+                    "{}",
+                    v
+                );
+                assert!(format!("{:#}", res).contains("malformed credentials"));
+            }
+        }
+
+        // Commitments and answers are checked too
+        let x = kdf.derive_secret(TEST_PASSWORD, &params).unwrap();
+        client.register_secret(&cp, &x, &kdf).await.unwrap();
+        let (r1, r2) = cp.r1_r2(&cp.random_nonce());
+        for v in &invalid {
+            for (r1, r2) in [(encode(v), encode(&r2)), (encode(&r1), encode(v))] {
+                let res = client
+                    .create_authentication_challenge(r1, r2)
+                    .await
+                    .unwrap_err();
+                assert_eq!(
+                    status_code(&res),
+                    Some(tonic::Code::InvalidArgument),
+                    "{}",
+                    v
+                );
+                assert!(format!("{:#}", res).contains("malformed commitment"));
+            }
+        }
+
+        // Answers must be canonical scalars : below q, and padded to exactly |q| bytes
+        let scalar_len = params.scalar_len();
+        for padded in [false, true] {
+            let k = cp.random_nonce();
+            let (r1, r2) = cp.r1_r2(&k);
+            let challenge = client
+                .create_authentication_challenge(encode(&r1), encode(&r2))
+                .await
+                .unwrap();
+            let c = params.decode_scalar(&challenge.c).unwrap();
+            let s = if padded {
+                encode_fixed(&cp.s(&k, &c, &x).to_bigint(), scalar_len + 1)
+            } else {
+                encode_fixed(&params.q, scalar_len)
+            };
+            let res = client
+                .verify_authentication(s, challenge.auth_id)
+                .await
+                .unwrap_err();
+            assert_eq!(status_code(&res), Some(tonic::Code::InvalidArgument));
+        }
+
+        server_thread.abort();
+    }
+
+    #[tokio::test]
+    async fn test_decoys_hide_unknown_users() {
+        use std::sync::Arc;
+        use zkp_auth::client::{run_client_auth_check_with_options, Client, ClientOptions};
+        use zkp_auth::error::ZkpAuthError;
+        use zkp_auth::group::P256;
+        use zkp_auth::server::decoy::DecoyKey;
+        use zkp_auth::server::enrollment::RegistrationPolicy;
+
+        let addr = "127.0.0.1:8205";
+        let enrollment_token = "an enrollment token that is long enough";
+        let options = ServerOptions {
+            decoy_key: Some(Arc::new(DecoyKey::generate())),
+            registration: Some(RegistrationPolicy {
+                enrollment_token: Some(enrollment_token.to_string()),
+                invites: false,
+            }),
+            ..Default::default()
+        };
+        let server_thread = tokio::spawn(run_server_with_options(addr, Backend::P256, options));
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        run_client_auth_check_with_options(
+            addr,
+            "alice",
+            TEST_PASSWORD,
+            Backend::P256,
+            ProofMode::FiatShamir,
+            ClientOptions {
+                enrollment_code: Some(enrollment_token.to_string()),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        let mut alice = Client::new(addr, "alice".to_string()).await;
+        let mut nobody = Client::new(addr, "nobody".to_string()).await;
+
+        // Unknown users have KDF parameters too, and the same ones every time
+        let kdf = nobody.get_kdf_parameters().await.unwrap();
+        assert!(kdf.validate().is_ok());
+        assert_eq!(nobody.get_kdf_parameters().await.unwrap(), kdf);
+
+        // And authenticating as them fails just as a wrong password does
+        for mode in [ProofMode::Interactive, ProofMode::FiatShamir] {
+            let e = alice.login(P256, "wrong password", mode).await.unwrap_err();
+            assert_eq!(
+                e.downcast_ref::<ZkpAuthError>(),
+                Some(&ZkpAuthError::ProofInvalid {
+                    user: "alice".to_string()
+                })
+            );
+
+            let e = nobody.login(P256, TEST_PASSWORD, mode).await.unwrap_err();
+            assert_eq!(
+                e.downcast_ref::<ZkpAuthError>(),
+                Some(&ZkpAuthError::ProofInvalid {
+                    user: "nobody".to_string()
+                })
+            );
+        }
+        assert!(alice
+            .login(P256, TEST_PASSWORD, ProofMode::Interactive)
+            .await
+            .is_ok());
+
+        server_thread.abort();
+    }
+
+    #[tokio::test]
+    async fn test_decoys_hide_locked_users() {
+        use std::sync::Arc;
+        use zkp_auth::client::{AdminClient, Client};
+        use zkp_auth::error::ZkpAuthError;
+        use zkp_auth::group::{ChaumPedersen, P256};
+        use zkp_auth::server::decoy::DecoyKey;
+        use zkp_auth::server::enrollment::RegistrationPolicy;
+
+        let addr = "127.0.0.1:8213";
+        let admin_token = "an admin token that is long enough";
+        let enrollment_token = "an enrollment token that is long enough";
+        let options = ServerOptions {
+            admin_token: Some(admin_token.to_string()),
+            decoy_key: Some(Arc::new(DecoyKey::generate())),
+            registration: Some(RegistrationPolicy {
+                enrollment_token: Some(enrollment_token.to_string()),
+                invites: false,
+            }),
+            ..Default::default()
+        };
+        let server_thread = tokio::spawn(run_server_with_options(addr, Backend::P256, options));
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let cp = ChaumPedersen::new(P256);
+        let mut alice = Client::new(addr, "alice".to_string()).await;
+        alice.set_enrollment_code(enrollment_token);
+        let x = register_p256(&mut alice).await;
+        let mut admin = AdminClient::new(addr, admin_token).await.unwrap();
+        admin.lock_user("alice").await.unwrap();
+
+        // A locked user's right secret fails as an unknown user's would, rather than being refused up front
+        let proof_invalid = |e: anyhow::Error, user: &str| {
+            assert_eq!(
+                e.downcast_ref::<ZkpAuthError>(),
+                Some(&ZkpAuthError::ProofInvalid {
+                    user: user.to_string()
+                })
+            );
+        };
+        proof_invalid(alice.authenticate(&cp, &x).await.unwrap_err(), "alice");
+        proof_invalid(
+            alice
+                .authenticate_non_interactive(&cp, &x, false)
+                .await
+                .unwrap_err(),
+            "alice",
+        );
+        let mut nobody = Client::new(addr, "nobody".to_string()).await;
+        proof_invalid(nobody.authenticate(&cp, &x).await.unwrap_err(), "nobody");
+
+        admin.unlock_user("alice").await.unwrap();
+        assert!(alice.authenticate(&cp, &x).await.is_ok());
+
+        server_thread.abort();
+    }
+
+    #[tokio::test]
+    async fn test_server_info_and_pinning() {
+        use zkp_auth::client::zkp_auth::{auth_client::AuthClient, ServerInfoRequest};
+        use zkp_auth::client::{run_client_auth_check_with_options, Client, ClientOptions};
+        use zkp_auth::group::{Group, P256};
+        use zkp_auth::server::PROTOCOL_VERSION;
+
+        let addr = "127.0.0.1:8206";
+        let server_thread = tokio::spawn(run_server(addr, Backend::P256));
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let mut client = Client::new(addr, TEST_USER.to_string()).await;
+        let info = client.get_server_info().await.unwrap();
+        assert_eq!(info.protocol_version, PROTOCOL_VERSION);
+        assert_eq!(info.group, P256.name());
+        assert_eq!(info.fingerprint, Backend::P256.fingerprint());
+        assert_eq!(info.proof_modes.len(), ProofMode::ALL.len());
+
+        // A client configured for another group fails before proving anything
+        let e = run_client_auth_check(
+            addr,
+            TEST_USER,
+            TEST_PASSWORD,
+            Backend::Ristretto255,
+            ProofMode::FiatShamir,
+        )
+        .await
+        .unwrap_err();
+        assert!(e.to_string().contains("fingerprint"), "{:#}", e);
+
+        let pinned = |fingerprint: [u8; 32]| ClientOptions {
+            pinned_fingerprint: Some(fingerprint),
+            ..Default::default()
+        };
+        assert!(run_client_auth_check_with_options(
+            addr,
+            TEST_USER,
+            TEST_PASSWORD,
+            Backend::P256,
+            ProofMode::FiatShamir,
+            pinned(Backend::Ristretto255.fingerprint()),
+        )
+        .await
+        .is_err());
+        assert!(run_client_auth_check_with_options(
+            addr,
+            TEST_USER,
+            TEST_PASSWORD,
+            Backend::P256,
+            ProofMode::FiatShamir,
+            pinned(Backend::P256.fingerprint()),
+        )
+        .await
+        .is_ok());
+
+        // Clients speaking another protocol version are turned away
+        let mut raw = AuthClient::connect(format!("http://{}", addr))
+            .await
+            .unwrap();
+        let status = raw
+            .get_server_info(ServerInfoRequest {
+                protocol_version: PROTOCOL_VERSION + 1,
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::FailedPrecondition);
+
+        server_thread.abort();
+    }
+
+    #[tokio::test]
+    async fn test_client_rejects_oversized_kdf_parameters() {
+        use std::sync::Arc;
+        use zkp_auth::client::Client;
+        use zkp_auth::db::{AuthStore, InMemoryDB, User};
+        use zkp_auth::group::{ChaumPedersen, Group, P256};
+        use zkp_auth::kdf::KdfParams;
+
+        let addr = "127.0.0.1:8207";
+        let store = Arc::new(InMemoryDB::new());
+        let options = ServerOptions {
+            store: Some(store.clone()),
+            ..Default::default()
+        };
+        let server_thread = tokio::spawn(run_server_with_options(addr, Backend::P256, options));
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        // Written behind the server's back, as a hostile or misconfigured server could serve it
+        let cp = ChaumPedersen::new(P256);
+        let (y1, y2) = cp.y1_y2(&P256.random_scalar());
+        let kdf = KdfParams {
+            memory_kib: u32::MAX,
+            ..KdfParams::generate()
+        };
+        store
+            .create_user(User::new(
+                TEST_USER.to_string(),
+                P256.encode_element(&y1),
+                P256.encode_element(&y2),
+                kdf,
+            ))
+            .await
+            .unwrap();
+
+        let mut client = Client::new(addr, TEST_USER.to_string()).await;
+        let e = client.get_kdf_parameters().await.unwrap_err();
+        assert!(format!("{:#}", e).contains("unacceptable KDF parameters"));
+        assert!(client
+            .login(P256, TEST_PASSWORD, ProofMode::Interactive)
+            .await
+            .is_err());
+
+        server_thread.abort();
+    }
+
+    #[tokio::test]
+    async fn test_decoys_require_restricted_registration() {
+        use std::sync::Arc;
+        use zkp_auth::server::decoy::DecoyKey;
+
+        // Open registration would tell anyone who tries to register a name whether it is taken
+        let options = ServerOptions {
+            decoy_key: Some(Arc::new(DecoyKey::generate())),
+            ..Default::default()
+        };
+        let e = run_server_with_options("127.0.0.1:8208", Backend::P256, options)
+            .await
+            .unwrap_err();
+        assert!(format!("{:#}", e).contains("requires restricted registration"));
+    }
+
+    #[tokio::test]
+    async fn test_decoys_keep_invites_for_taken_names() {
+        use std::sync::Arc;
+        use zkp_auth::client::{AdminClient, Client};
+        use zkp_auth::group::P256;
+        use zkp_auth::server::decoy::DecoyKey;
+        use zkp_auth::server::enrollment::RegistrationPolicy;
+
+        let addr = "127.0.0.1:8209";
+        let admin_token = "an admin token that is long enough";
+        let options = ServerOptions {
+            admin_token: Some(admin_token.to_string()),
+            decoy_key: Some(Arc::new(DecoyKey::generate())),
+            registration: Some(RegistrationPolicy {
+                enrollment_token: None,
+                invites: true,
+            }),
+            ..Default::default()
+        };
+        let server_thread = tokio::spawn(run_server_with_options(addr, Backend::P256, options));
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let register = |user: &'static str, code: String| async move {
+            let mut client = Client::new(addr, user.to_string()).await;
+            client.set_enrollment_code(code);
+            client.register_with_password(&P256, TEST_PASSWORD).await
+        };
+
+        let mut admin = AdminClient::new(addr, admin_token).await.unwrap();
+        register("alice", admin.create_invite(0).await.unwrap().code)
+            .await
+            .unwrap();
+
+        // Without a valid code, a taken name can't be told from a free one
+        let e = register("alice", "not an invite".to_string())
+            .await
+            .unwrap_err();
+        assert_eq!(status_code(&e), Some(tonic::Code::PermissionDenied));
+        let e = register("bob", "not an invite".to_string())
+            .await
+            .unwrap_err();
+        assert_eq!(status_code(&e), Some(tonic::Code::PermissionDenied));
+
+        // A valid invite learns the name is taken, but isn't used up
+        let invite = admin.create_invite(0).await.unwrap().code;
+        let e = register("alice", invite.clone()).await.unwrap_err();
+        assert_eq!(status_code(&e), Some(tonic::Code::AlreadyExists));
+        register("bob", invite.clone()).await.unwrap();
+        let e = register("carol", invite).await.unwrap_err();
+        assert_eq!(status_code(&e), Some(tonic::Code::PermissionDenied));
+
+        server_thread.abort();
+    }
+
+    #[tokio::test]
+    async fn test_decoy_lockouts_match_registered_users() {
+        use std::sync::Arc;
+        use zkp_auth::client::Client;
+        use zkp_auth::db::{AuthStore, InMemoryDB};
+        use zkp_auth::error::ZkpAuthError;
+        use zkp_auth::group::{ChaumPedersen, Group, P256};
+        use zkp_auth::server::decoy::DecoyKey;
+        use zkp_auth::server::enrollment::RegistrationPolicy;
+        use zkp_auth::server::lockout::LockoutPolicy;
+        use zkp_auth::server::rate_limit::RateLimits;
+
+        let addr = "127.0.0.1:8210";
+        let enrollment_token = "an enrollment token that is long enough";
+        let store = Arc::new(InMemoryDB::new());
+        let options = ServerOptions {
+            store: Some(store.clone()),
+            decoy_key: Some(Arc::new(DecoyKey::generate())),
+            registration: Some(RegistrationPolicy {
+                enrollment_token: Some(enrollment_token.to_string()),
+                invites: false,
+            }),
+            rate_limits: Some(RateLimits::unlimited()),
+            lockout_policy: Some(LockoutPolicy {
+                max_failures: 2,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let server_thread = tokio::spawn(run_server_with_options(addr, Backend::P256, options));
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let mut alice = Client::new(addr, "alice".to_string()).await;
+        alice.set_enrollment_code(enrollment_token);
+        register_p256(&mut alice).await;
+        let mut nobody = Client::new(addr, "nobody".to_string()).await;
+
+        // Proves with a random secret, which never matches
+        async fn fail(client: &mut Client) -> anyhow::Result<String> {
+            let x = P256.random_scalar();
+            client
+                .authenticate_non_interactive(&ChaumPedersen::new(P256), &x, false)
+                .await
+        }
+        let is_locked = |e: &anyhow::Error| {
+            matches!(
+                e.downcast_ref::<ZkpAuthError>(),
+                Some(ZkpAuthError::Locked {
+                    retry_after: Some(_),
+                    ..
+                })
+            )
+        };
+
+        // A registered and an unknown user are locked out alike, and their lockouts are stored alike
+        for client in [&mut alice, &mut nobody] {
+            for _ in 0..2 {
+                let e = fail(client).await.unwrap_err();
+                assert!(matches!(
+                    e.downcast_ref::<ZkpAuthError>(),
+                    Some(ZkpAuthError::ProofInvalid { .. })
+                ));
+            }
+            assert!(is_locked(&fail(client).await.unwrap_err()));
+        }
+        assert!(store.get_lockout("alice").await.unwrap().is_some());
+        assert!(store.get_lockout("nobody").await.unwrap().is_some());
+
+        // Failing as many made-up names as an attacker likes doesn't lift either lockout early
+        for i in 0..200 {
+            let mut client = Client::new(addr, format!("made-up-{}", i)).await;
+            assert!(fail(&mut client).await.is_err());
+        }
+        assert!(is_locked(&fail(&mut alice).await.unwrap_err()));
+        assert!(is_locked(&fail(&mut nobody).await.unwrap_err()));
+
+        server_thread.abort();
+    }
+
+    #[tokio::test]
+    async fn test_server_info_reports_configured_group() {
+        use zkp_auth::chaum_pedersen::NamedGroup;
+        use zkp_auth::client::Client;
+
+        for (addr, backend, name) in [
+            (
+                "127.0.0.1:8211",
+                Backend::from(NamedGroup::Ffdhe2048.params()),
+                "ffdhe2048",
+            ),
+            (
+                "127.0.0.1:8212",
+                Backend::from(create_test_params()),
+                "custom",
+            ),
+        ] {
+            let fingerprint = backend.fingerprint();
+            let server_thread = tokio::spawn(run_server(addr, backend));
+            tokio::time::sleep(Duration::from_millis(200)).await;
+
+            let mut client = Client::new(addr, TEST_USER.to_string()).await;
+            let info = client.get_server_info().await.unwrap();
+            assert_eq!(info.group, name);
+            assert_eq!(info.fingerprint, fingerprint);
+
+            server_thread.abort();
+        }
+    }
 }